@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Ready-made ffmpeg settings for encoding rendered frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum EncoderPreset {
+    /// H.265 10-bit MOV with sRGB tags, for archiving
+    #[default]
+    Hevc,
+
+    /// H.264 MP4, plays everywhere on the web
+    H264,
+
+    /// ProRes 422 HQ MOV, for video editors
+    Prores,
+
+    /// VP9 WebM
+    Vp9,
+
+    /// Animated GIF with generated palette
+    Gif,
+
+    /// Animated PNG
+    Apng,
+
+    /// Only PNG sequence, ffmpeg is not used
+    Png,
+}
+
+impl EncoderPreset {
+    /// `None` means that result is a directory with frames.
+    pub fn extension(self) -> Option<&'static str> {
+        use EncoderPreset::*;
+        match self {
+            Hevc | Prores => Some("mov"),
+            H264 => Some("mp4"),
+            Vp9 => Some("webm"),
+            Gif => Some("gif"),
+            Apng => Some("png"),
+            Png => None,
+        }
+    }
+
+    #[rustfmt::skip]
+    fn codec_args(self) -> &'static [&'static str] {
+        use EncoderPreset::*;
+        match self {
+            Hevc => &[
+                // Keep full-range; tag/convert explicitly to sRGB in YUV420 10-bit
+                "-vf", "zscale=primariesin=bt709:transferin=iec61966-2-1:matrixin=bt709:rangein=full:primaries=bt709:transfer=iec61966-2-1:matrix=bt709:range=full,format=yuv420p10le",
+                "-c:v", "libx265",
+                "-pix_fmt", "yuv420p10le",
+                "-crf", "15",
+                "-preset", "slow",
+                // Bitstream + container tags (sRGB transfer)
+                "-x265-params", "colorprim=bt709:transfer=iec61966-2-1:colormatrix=bt709:range=full",
+                "-colorspace", "bt709",
+                "-color_primaries", "bt709",
+                "-color_trc", "iec61966-2-1",
+                "-color_range", "pc",
+                // MOV tends to keep colr atom; hvc1 tag improves compatibility
+                "-movflags", "+write_colr+faststart",
+                "-tag:v", "hvc1",
+            ],
+            H264 => &[
+                "-c:v", "libx264",
+                "-pix_fmt", "yuv420p",
+                "-crf", "18",
+                "-preset", "slow",
+                "-movflags", "+faststart",
+            ],
+            Prores => &[
+                "-c:v", "prores_ks",
+                "-profile:v", "3",
+                "-pix_fmt", "yuv422p10le",
+            ],
+            Vp9 => &[
+                "-c:v", "libvpx-vp9",
+                "-pix_fmt", "yuv420p",
+                "-crf", "30",
+                "-b:v", "0",
+                "-row-mt", "1",
+            ],
+            Gif => &["-vf", "split[a][b];[a]palettegen[p];[b][p]paletteuse"],
+            Apng => &["-plays", "0", "-f", "apng"],
+            Png => &[],
+        }
+    }
+}
+
+/// Turns directory with `frame_{i}.png` files into the final video.
+#[derive(Debug, Clone, Default)]
+pub struct Encoder {
+    pub preset: EncoderPreset,
+
+    /// Replaces all ffmpeg arguments of preset, split by whitespace. `{fps}`, `{input}` and
+    /// `{output}` are substituted. Extension of output is still taken from preset. For EXR frames
+    /// `-apply_trc iec61966_2_1` is added before `-i {input}`, as in presets.
+    pub ffmpeg_template: Option<String>,
+}
+
+impl Encoder {
+    pub fn uses_ffmpeg(&self) -> bool {
+        self.preset != EncoderPreset::Png
+    }
+
+    /// `base` is output path without extension.
+    pub fn output_path(&self, base: &Path) -> PathBuf {
+        match self.preset.extension() {
+            Some(extension) => PathBuf::from(format!("{}.{extension}", base.display())),
+            None => base.to_owned(),
+        }
+    }
+
+    /// Should be called before rendering, so missing ffmpeg is found before hours of rendering.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(template) = &self.ffmpeg_template {
+            for placeholder in ["{input}", "{output}"] {
+                if !template.contains(placeholder) {
+                    return Err(format!(
+                        "ffmpeg template `{template}` should contain `{placeholder}`"
+                    ));
+                }
+            }
+        }
+
+        if !self.uses_ffmpeg() {
+            if self.ffmpeg_template.is_some() {
+                return Err(
+                    "`--ffmpeg-template` can't be used with `--encoder png`, because PNG frames are kept without ffmpeg. Choose another encoder for the extension of output."
+                        .to_owned(),
+                );
+            }
+            return Ok(());
+        }
+
+        match Command::new("ffmpeg").arg("-version").output() {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(format!("`ffmpeg -version` failed with {}", output.status)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(
+                "ffmpeg is not found in PATH. Install it, or use `--encoder png` to keep only PNG frames."
+                    .to_owned(),
+            ),
+            Err(err) => Err(format!("Failed to run ffmpeg: {err}")),
+        }
+    }
+
+    fn args(&self, fps: usize, input: &str, output: &str) -> Vec<String> {
+        let fps = fps.to_string();
+        // EXR frames are linear, but presets expect sRGB input like in PNG frames
+        let input_args: &[&str] = if input.ends_with(".exr") {
+            &["-apply_trc", "iec61966_2_1"]
+        } else {
+            &[]
+        };
+        match &self.ffmpeg_template {
+            Some(template) => {
+                let mut args = template
+                    .split_whitespace()
+                    .map(|arg| {
+                        arg.replace("{fps}", &fps)
+                            .replace("{input}", input)
+                            .replace("{output}", output)
+                    })
+                    .collect::<Vec<_>>();
+                // Input options should be right before `-i` of the frames, the template can have
+                // other inputs
+                let frames_input = template
+                    .split_whitespace()
+                    .position(|arg| arg.contains("{input}"))
+                    .filter(|&i| i > 0 && args[i - 1] == "-i");
+                if let Some(i) = frames_input {
+                    args.splice(i - 1..i - 1, input_args.iter().map(|arg| arg.to_string()));
+                }
+                args
+            }
+            None => input_args
+                .iter()
+                .copied()
+                .chain(["-framerate", fps.as_str(), "-i", input])
+                .chain(self.preset.codec_args().iter().copied())
+                .chain(["-y", output])
+                .map(str::to_owned)
+                .collect(),
+        }
+    }
+
+    /// Encodes `frames_dir/frame_%d.{frame_extension}` and removes the frames. They are kept if
+    /// encoding failed.
+    pub fn encode(
+        &self,
+        frames_dir: &Path,
+        frame_extension: &str,
+        fps: usize,
+        base: &Path,
+    ) -> Result<PathBuf, String> {
+        let output = self.output_path(base);
+
+        if !self.uses_ffmpeg() {
+            if output.exists() {
+                std::fs::remove_dir_all(&output)
+                    .map_err(|err| format!("Failed to remove `{}`: {err}", output.display()))?;
+            }
+            std::fs::rename(frames_dir, &output).map_err(|err| {
+                format!(
+                    "Failed to move `{}` to `{}`: {err}",
+                    frames_dir.display(),
+                    output.display()
+                )
+            })?;
+            return Ok(output);
+        }
+
+        let input = frames_dir.join(format!("frame_%d.{frame_extension}"));
+        let result = Command::new("ffmpeg")
+            .args(self.args(fps, &input.to_string_lossy(), &output.to_string_lossy()))
+            .output()
+            .map_err(|err| format!("Failed to run ffmpeg: {err}"))?;
+        if !result.status.success() {
+            return Err(format!(
+                "ffmpeg exited with {}:\n{}\n\n{}",
+                result.status,
+                String::from_utf8_lossy(&result.stdout),
+                String::from_utf8_lossy(&result.stderr)
+            ));
+        }
+
+        std::fs::remove_dir_all(frames_dir)
+            .map_err(|err| format!("Failed to remove `{}`: {err}", frames_dir.display()))?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_substitution() {
+        let encoder = Encoder {
+            preset: EncoderPreset::H264,
+            ffmpeg_template: Some("-r {fps} -i {input} -c:v libx264 {output}".to_owned()),
+        };
+        assert_eq!(
+            encoder.args(30, "anim/frame_%d.png", "video/a.mp4"),
+            [
+                "-r",
+                "30",
+                "-i",
+                "anim/frame_%d.png",
+                "-c:v",
+                "libx264",
+                "video/a.mp4"
+            ]
+        );
+
+        assert_eq!(
+            encoder.args(30, "anim/frame_%d.exr", "video/a.mp4")[..6],
+            [
+                "-r",
+                "30",
+                "-apply_trc",
+                "iec61966_2_1",
+                "-i",
+                "anim/frame_%d.exr"
+            ]
+        );
+
+        let encoder = Encoder {
+            preset: EncoderPreset::Png,
+            ffmpeg_template: Some("-i {input} out.mp4".to_owned()),
+        };
+        assert!(encoder.check().unwrap_err().contains("{output}"));
+
+        let encoder = Encoder {
+            preset: EncoderPreset::Png,
+            ffmpeg_template: Some("-i {input} {output}".to_owned()),
+        };
+        assert!(encoder.check().unwrap_err().contains("--encoder png"));
+    }
+
+    #[test]
+    fn preset_args() {
+        let encoder = Encoder {
+            preset: EncoderPreset::Vp9,
+            ffmpeg_template: None,
+        };
+        let args = encoder.args(60, "in", "out.webm");
+        assert_eq!(&args[..4], ["-framerate", "60", "-i", "in"]);
+        assert_eq!(&args[args.len() - 2..], ["-y", "out.webm"]);
+        assert_eq!(
+            encoder.output_path(Path::new("video/scene/anim")),
+            Path::new("video/scene/anim.webm")
+        );
+    }
+}
@@ -16,6 +16,10 @@
 
 //%intersection_material_functions//%
 
+// Which object is hit by the last call of `scene_intersect` and `scene_intersect_material_process`, for auxiliary passes. 0 - nothing.
+int scene_object_id = 0;
+int material_object_id = 0;
+
 SceneIntersection scene_intersect(Ray r) {
     SceneIntersection i = SceneIntersection(0, intersection_none, false);
     SceneIntersection ihit = SceneIntersection(0, intersection_none, false);
@@ -24,6 +28,8 @@ SceneIntersection scene_intersect(Ray r) {
     int inside = NOT_INSIDE;
     float len = 1.;
     Ray transformed_ray = ray_none;
+    float previous_t = i.hit.t;
+    scene_object_id = 0;
 
 //%intersections//%
 
@@ -52,6 +58,7 @@ MaterialProcessing material_process(Ray r, SceneIntersection i) {
 SceneIntersectionWithMaterial scene_intersect_material_process(Ray r) {
     SceneIntersectionWithMaterial result = SceneIntersectionWithMaterial(scene_intersection_none, material_empty());
     SceneIntersectionWithMaterial hit = SceneIntersectionWithMaterial(scene_intersection_none, material_empty());
+    material_object_id = 0;
 
 //%intersection_material_processing//%
     
@@ -103,10 +110,84 @@ vec3 sample_depth_gradient(float depth) {
     return depth_gradient_inferno(t);
 }
 
+uniform vec3 _fog_color;
+uniform float _fog_density;
+uniform float _fog_falloff;
+uniform vec3 _subspace_fog_color;
+uniform float _subspace_fog_density;
+uniform float _subspace_fog_falloff;
+
+// Fraction of light that passes through fog of current subspace on the ray segment `t`. Density is exponential by height, so it is integrated analytically.
+float fog_transmittance(Ray r, float t) {
+    float density = r.in_subspace ? _subspace_fog_density : _fog_density;
+    if (density <= 0.) return 1.;
+    float falloff = r.in_subspace ? _subspace_fog_falloff : _fog_falloff;
+
+    float len = length(r.d.xyz);
+    float segment_length = t * len;
+    float k = falloff * r.d.y / len;
+    float start_density = density * exp(clamp(-falloff * r.o.y, -80., 80.));
+    float optical_depth;
+    if (abs(k) < 1e-4) {
+        optical_depth = start_density * segment_length;
+    } else {
+        optical_depth = start_density * (1. - exp(clamp(-k * segment_length, -80., 80.))) / k;
+    }
+    return exp(-min(optical_depth, 100.));
+}
+
+void apply_fog(Ray r, float t, inout vec3 current_color, inout vec3 emitted_color) {
+    float transmittance = fog_transmittance(r, t);
+    vec3 fog_color = sqrvec(r.in_subspace ? _subspace_fog_color : _fog_color);
+    emitted_color += current_color * fog_color * (1. - transmittance);
+    current_color *= transmittance;
+}
+
+uniform int _aux_pass; // 0 - color, 1 - depth, 2 - normal, 3 - object id, 4 - material id, 5 - portal hops
+
+// Written by `ray_tracing` at the first hit that is not a portal
+bool aux_recorded;
+float aux_depth;
+vec3 aux_normal;
+int aux_object_id;
+int aux_material_id;
+int aux_portal_hops;
+
+void aux_record(SceneIntersection i, int object_id, float all_t, float camera_scale) {
+    if (aux_recorded) return;
+    if (i.material >= PORTAL_MATERIAL_START) {
+        aux_portal_hops++;
+        return;
+    }
+    aux_recorded = true;
+    aux_depth = all_t / max(camera_scale, 1e-6);
+    aux_normal = i.hit.n;
+    aux_object_id = object_id;
+    aux_material_id = i.material >= USER_MATERIAL_OFFSET ? i.material - USER_MATERIAL_OFFSET + 1 : 0;
+}
+
+// Raw values, they are not averaged by antialiasing, so ids stay integer
+vec3 aux_pass_value() {
+    if (_aux_pass == 1) return vec3(aux_depth);
+    if (_aux_pass == 2) return aux_recorded ? aux_normal * 0.5 + 0.5 : vec3(0.5);
+    if (_aux_pass == 3) return vec3(float(aux_object_id));
+    if (_aux_pass == 4) return vec3(float(aux_material_id));
+    return vec3(float(aux_portal_hops));
+}
+
 RayTraceResult ray_tracing(Ray r, float camera_scale) {
+    aux_recorded = false;
+    aux_depth = 0.;
+    aux_normal = vec3(0.);
+    aux_object_id = 0;
+    aux_material_id = 0;
+    aux_portal_hops = 0;
+
     //%skybox_processing//%
 
+    dispersion_masked = false;
     vec3 current_color = vec3(1.);
+    vec3 emitted_color = vec3(0.);
     float all_t = 0.;
     for (int j = 0; j < 10000; j++) { if (j >= _ray_tracing_depth) break; // !FOR_NUMBER!
     for (int j = 0; j < _ray_tracing_depth; j++) { // !FOR_VARIABLE!
@@ -115,23 +196,31 @@ RayTraceResult ray_tracing(Ray r, float camera_scale) {
         
         MaterialProcessing m;
         if (nearer(i.hit, i2.scene.hit)) {
+            apply_fog(r, i2.scene.hit.t, current_color, emitted_color);
             r.o += r.d * i2.scene.hit.t;
             all_t += i2.scene.hit.t * r.tmul;
+            aux_record(i2.scene, material_object_id, all_t, camera_scale);
             if (i2.scene.material == CUSTOM_MATERIAL) {
                 m = i2.material;
             } else {
                 m = material_process(r, i2.scene);
             }
         } else if (i.hit.hit) {
+            apply_fog(r, i.hit.t, current_color, emitted_color);
             r.o += r.d * i.hit.t;
             all_t += i.hit.t * r.tmul;
+            aux_record(i, scene_object_id, all_t, camera_scale);
             m = material_process(r, i);
+        } else {
+            apply_fog(r, 1e6, current_color, emitted_color);
         }
 
         // Offset ray
         if (i.hit.hit || i2.scene.hit.hit) {
+            emitted_color += current_color * m.emission;
             current_color *= m.mul_to_color;
             if (m.is_final) {
+                current_color += emitted_color;
                 float depth = all_t / max(camera_scale, 1e-6);
                 if (all_t > _t_start * camera_scale && _darken_by_distance == 1) {
                     if (all_t > _t_end * camera_scale) all_t = _t_end * camera_scale;
@@ -149,13 +238,13 @@ RayTraceResult ray_tracing(Ray r, float camera_scale) {
             }
         } else {
             if (r.in_subspace) {
-                return RayTraceResult(color(0., 0., 0.), 0.0, false);
+                return RayTraceResult(emitted_color, 0.0, false);
             } else {
-                return RayTraceResult(current_color * not_found_color, 0.0, false);
+                return RayTraceResult(emitted_color + current_color * not_found_color, 0.0, false);
             }
         }
     }
-    return RayTraceResult(color(0., 0., 0.), 0.0, false);
+    return RayTraceResult(emitted_color, 0.0, false);
 }
 
 // ---------------------------------------------------------------------------
@@ -200,6 +289,7 @@ struct ExternalRayTeleportation {
     vec3 pos;
     bool encounter_object;
     bool change_subspace;
+    float encounter_at; // part of the segment before the object
 };
 
 uniform int _camera_in_subspace;
@@ -248,11 +338,13 @@ ExternalRayTeleportation teleport_external_ray(Ray r) {
             break;
         }
     }
+    // Final material breaks the loop, so `all_t` is still at the object
+    float encounter_at = stop_at_object ? all_t : 1.;
     if (have_result) {
         r.o += r.d * (1.0 - all_t) / r.tmul;
-        return ExternalRayTeleportation(r.o.xyz, stop_at_object, int(r.in_subspace) != _camera_in_subspace);
+        return ExternalRayTeleportation(r.o.xyz, stop_at_object, int(r.in_subspace) != _camera_in_subspace, encounter_at);
     } else {
-        return ExternalRayTeleportation(vec3(0.), stop_at_object, int(r.in_subspace) != _camera_in_subspace);
+        return ExternalRayTeleportation(vec3(0.), stop_at_object, int(r.in_subspace) != _camera_in_subspace, encounter_at);
     }
 }
 
@@ -267,14 +359,21 @@ uniform float _camera_scale;
 uniform float _left_eye_scale;
 uniform float _right_eye_scale;
 uniform float _view_angle;
-uniform int _use_panini_projection;
-uniform int _use_360_camera;
-uniform int _use_180_camera;
+uniform float _aperture;
+uniform float _focus_distance;
+uniform int _projection; // 0 - perspective, 1 - panini, 2 - 360, 3 - 180, 4 - fisheye equidistant, 5 - fisheye equisolid, 6 - cubemap, 7 - orthographic
+uniform float _ortho_size;
 uniform float _panini_param;
 uniform int _aa_count;
 uniform int _aa_start;
+uniform int _output_mode; // 0 - display, 1 - high byte of 16-bit value, 2 - low byte, 3 - linear color for float render target
+uniform float _output_scale; // linear color that is encoded as 1 in high precision modes
 uniform int _draw_side_by_side;
+uniform int _stereo_layout; // 0 - side-by-side, 1 - top-bottom, 2 - row interlaced, 3 - column interlaced
+uniform int _vr_format; // 0 - none, 1 - ODS 360 top-bottom, 2 - VR180 side-by-side
+uniform float _eye_distance; // from center to each eye, negative if eyes are swapped
 uniform vec2 _resolution;
+uniform vec2 _tile_offset;
 uniform int _draw_anaglyph;
 uniform float _anaglyph_p;
 uniform float _anaglyph_q;
@@ -290,7 +389,6 @@ in vec2 uv_screen; // !GLSL300!
 
 layout(location=0) out vec4 FragColor; // !GLSL300!
 
-uniform int _teleport_external_ray;
 uniform vec3 _external_ray_a;
 uniform vec3 _external_ray_b;
 
@@ -341,16 +439,66 @@ vec3 PaniniProjection(vec2 tc, float fov, float d)
     return vec3(sinPhi, tanTheta, cosPhi) * s;
 }
 
+// Least squares matrices by Eric Dubois. Matrices are written by rows, so `v * m` is used.
+vec3 anaglyphDubois(vec3 leftLin, vec3 rightLin, int mode)
+{
+    mat3 left;
+    mat3 right;
+    if (mode == 2) {
+        // red/cyan
+        left = mat3(
+             0.437,  0.449,  0.164,
+            -0.062, -0.062, -0.024,
+            -0.048, -0.050, -0.017
+        );
+        right = mat3(
+            -0.011, -0.032, -0.007,
+             0.377,  0.761,  0.009,
+            -0.026, -0.093,  1.234
+        );
+    } else if (mode == 3) {
+        // green/magenta
+        left = mat3(
+            -0.062, -0.158, -0.039,
+             0.284,  0.668,  0.143,
+            -0.015, -0.027,  0.021
+        );
+        right = mat3(
+             0.529,  0.705,  0.024,
+            -0.016, -0.015, -0.065,
+             0.009,  0.075,  0.937
+        );
+    } else {
+        // amber/blue
+        left = mat3(
+             1.062, -0.205,  0.299,
+            -0.026,  0.908,  0.068,
+            -0.038, -0.173,  0.022
+        );
+        right = mat3(
+            -0.016, -0.123, -0.017,
+             0.006,  0.062, -0.017,
+             0.094,  0.185,  0.911
+        );
+    }
+    return clamp(leftLin * left + rightLin * right, 0.0, 1.0);
+}
+
 // Anaglyph modes:
 // 0 = grayscale luminance red/cyan
-// 1 = Dubois (optimized) red/cyan
+// 1 = half-color red/cyan
+// 2, 3, 4 = Dubois red/cyan, green/magenta, amber/blue
 vec3 anaglyphCombineLinear(vec3 leftLin, vec3 rightLin, int mode)
 {
     // Clamp inputs just in case
     leftLin  = clamp(leftLin,  0.0, 1.0);
     rightLin = clamp(rightLin, 0.0, 1.0);
 
-    if (mode == 0)
+    if (mode >= 2)
+    {
+        return anaglyphDubois(leftLin, rightLin, mode);
+    }
+    else if (mode == 0)
     {
         // Grayscale red/cyan with deghost compensation (linear space).
         // p = cyan -> red-eye leak
@@ -405,12 +553,52 @@ vec3 anaglyphCombineLinear(vec3 leftLin, vec3 rightLin, int mode)
     }
 }
 
+vec3 trace_camera_ray(Ray r, float camera_scale) {
+    RayTraceResult trace = ray_tracing(r, camera_scale);
+    if (_aux_pass != 0) {
+        return aux_pass_value();
+    }
+    if (_draw_depth_map == 1) {
+        if (trace.has_depth) {
+            return sample_depth_gradient(trace.depth);
+        } else {
+            return vec3(0.0);
+        }
+    }
+    return trace.color;
+}
+
+// Ray from `center + offset` with direction `d`. Start can be on the other side of portal, then
+// direction is moved as a nearby point, same as `teleport_matrix` does for the camera on CPU.
+Ray offset_camera_ray(vec4 center, vec4 offset, vec4 d, bool in_subspace, float camera_scale) {
+    ExternalRayTeleportation start = ExternalRayTeleportation(vec3(0.), false, false, 1.);
+    if (length(offset.xyz) > 0.) {
+        start = teleport_external_ray(Ray(center, offset, 1., in_subspace)); // !CAMERA_TELEPORTATION!
+    }
+    if (start.pos == vec3(0.)) {
+        return Ray(center + offset, d, 1.0, in_subspace);
+    }
+    vec4 o = vec4(start.pos, 1.);
+    vec3 ahead = vec3(0.);
+    ahead = teleport_external_ray(Ray(center, offset + d * 0.001 * camera_scale, 1., in_subspace)).pos; // !CAMERA_TELEPORTATION!
+    if (ahead != vec3(0.)) {
+        d = normalize(vec4(ahead - start.pos, 0.));
+    }
+    if (start.change_subspace) {
+        in_subspace = !in_subspace;
+    }
+    return Ray(o, d, 1.0, in_subspace);
+}
+
+// Point on lens disk for current sample, set in `main`.
+vec2 lens_sample = vec2(0.);
+
 vec3 get_color2(vec2 image_position, mat4 camera_matrix, bool in_subspace, float camera_scale, vec2 resolution) {
     vec4 o = camera_matrix * vec4(0., 0., 0., 1.);
     vec4 d;
-    if (_use_panini_projection == 1) {
+    if (_projection == 1) {
         d = normalize(camera_matrix * vec4(PaniniProjection(vec2(image_position.x, image_position.y), _view_angle, _panini_param), 0.));
-    } else if (_use_360_camera == 1) {
+    } else if (_projection == 2) {
         // Equirectangular mapping where the center of the image looks straight forward
         // in camera-local space (0, 0, 1). Then we rotate it by `camera_matrix` so the
         // 360 view follows the same orientation as the regular camera.
@@ -435,7 +623,7 @@ vec3 get_color2(vec2 image_position, mat4 camera_matrix, bool in_subspace, float
         float pitch = (image_position.y / ry) * Pi05;
         vec3 dir_local = vec3(sin(yaw) * cos(pitch), sin(pitch), cos(yaw) * cos(pitch));
         d = normalize(camera_matrix * vec4(dir_local, 0.));
-    } else if (_use_180_camera == 1) {
+    } else if (_projection == 3) {
         // VR180 (front hemisphere) equirectangular.
         // Use the full image width/height for [-90°, +90°] yaw/pitch, with black borders
         // outside the square view area (e.g. when the viewport is wider than tall).
@@ -446,24 +634,106 @@ vec3 get_color2(vec2 image_position, mat4 camera_matrix, bool in_subspace, float
         float pitch = image_position.y * Pi05;
         vec3 dir_local = vec3(sin(yaw) * cos(pitch), sin(pitch), cos(yaw) * cos(pitch));
         d = normalize(camera_matrix * vec4(dir_local, 0.));
+    } else if (_projection == 4 || _projection == 5) {
+        // Image circle touches the shorter side of the frame
+        float radius = length(image_position);
+        if (radius > 1.0) {
+            return vec3(0.0);
+        }
+        float theta;
+        if (_projection == 4) {
+            theta = radius * _view_angle / 2.;
+        } else {
+            theta = 2. * asin(clamp(radius * sin(_view_angle / 4.), -1., 1.));
+        }
+        vec2 side = radius > 0. ? image_position / radius : vec2(0.);
+        vec3 dir_local = vec3(side * sin(theta), cos(theta));
+        d = normalize(camera_matrix * vec4(dir_local, 0.));
+    } else if (_projection == 6) {
+        // Strip of faces +X, -X, +Y, -Y, +Z, -Z. Every face is 90° and should be square.
+        float coef = min(resolution.x, resolution.y);
+        vec2 position = (image_position / 2. * coef + resolution / 2.) / resolution;
+        float face = floor(clamp(position.x, 0., 0.999999) * 6.);
+        vec2 st = vec2(fract(position.x * 6.), position.y) * 2. - 1.;
+        vec3 right;
+        vec3 up = vec3(0., 1., 0.);
+        vec3 forward;
+        if (face == 0.) {
+            right = vec3(0., 0., -1.);
+            forward = vec3(1., 0., 0.);
+        } else if (face == 1.) {
+            right = vec3(0., 0., 1.);
+            forward = vec3(-1., 0., 0.);
+        } else if (face == 2.) {
+            right = vec3(1., 0., 0.);
+            up = vec3(0., 0., -1.);
+            forward = vec3(0., 1., 0.);
+        } else if (face == 3.) {
+            right = vec3(1., 0., 0.);
+            up = vec3(0., 0., 1.);
+            forward = vec3(0., -1., 0.);
+        } else if (face == 4.) {
+            right = vec3(1., 0., 0.);
+            forward = vec3(0., 0., 1.);
+        } else {
+            right = vec3(-1., 0., 0.);
+            forward = vec3(0., 0., -1.);
+        }
+        d = normalize(camera_matrix * vec4(right * st.x + up * st.y + forward, 0.));
+    } else if (_projection == 7) {
+        vec4 offset = camera_matrix * vec4(image_position * _ortho_size, 0., 0.);
+        d = normalize(camera_matrix * vec4(0., 0., 1., 0.));
+        Ray r = offset_camera_ray(o, offset, d, in_subspace, camera_scale);
+        o = r.o;
+        d = r.d;
+        in_subspace = r.in_subspace;
     } else {
         float h = tan(_view_angle / 2.);
         d = normalize(camera_matrix * vec4(image_position.x * h, image_position.y * h, 1.0, 0.));
     }
-     
-    Ray r = Ray(o, d, 1.0, in_subspace);
-    RayTraceResult trace = ray_tracing(r, camera_scale);
-    if (_draw_depth_map == 1) {
-        if (trace.has_depth) {
-            return sample_depth_gradient(trace.depth);
-        } else {
-            return vec3(0.0);
-        }
+
+    if (_aperture > 0.) {
+        // Thin lens: rays from all points of lens converge at the focus distance along pinhole ray
+        vec4 focus = o + d * _focus_distance * camera_scale;
+        o += camera_matrix * vec4(lens_sample * _aperture, 0., 0.);
+        d = normalize(focus - o);
     }
-    return trace.color;
+
+    return trace_camera_ray(Ray(o, d, 1.0, in_subspace), camera_scale);
+}
+
+// Omnidirectional stereo. Eye is on the circle around the camera, perpendicular to the horizontal
+// direction of the column. Circle shrinks to the center at the poles, so there is no stereo there
+// instead of broken image.
+vec3 get_color_vr(vec2 image_position) {
+    float coef = min(_resolution.x, _resolution.y);
+    vec2 position = (image_position / 2. * coef + _resolution / 2.) / _resolution;
+    float eye;
+    float yaw;
+    float pitch;
+    if (_vr_format == 1) {
+        eye = position.y >= 0.5 ? -1. : 1.;
+        yaw = (position.x * 2. - 1.) * Pi;
+        pitch = (fract(position.y * 2.) * 2. - 1.) * Pi05;
+    } else {
+        eye = position.x < 0.5 ? -1. : 1.;
+        yaw = (fract(position.x * 2.) * 2. - 1.) * Pi05;
+        pitch = (position.y * 2. - 1.) * Pi05;
+    }
+    vec3 dir_local = vec3(sin(yaw) * cos(pitch), sin(pitch), cos(yaw) * cos(pitch));
+    vec3 eye_local = vec3(cos(yaw), 0., -sin(yaw)) * eye * _eye_distance * cos(pitch);
+
+    bool in_subspace = _camera_in_subspace == 1;
+    vec4 center = _camera * vec4(0., 0., 0., 1.);
+    vec4 offset = _camera * vec4(eye_local, 0.);
+    vec4 d = normalize(_camera * vec4(dir_local, 0.));
+    return trace_camera_ray(offset_camera_ray(center, offset, d, in_subspace, _camera_scale), _camera_scale);
 }
 
 vec3 get_color(vec2 image_position) {
+    if (_vr_format != 0) {
+        return get_color_vr(image_position);
+    }
     if (_draw_anaglyph == 1) { // !ANAGLYPH!
         return anaglyphCombineLinear( // !ANAGLYPH!
             get_color2(image_position, _camera_left_eye, _left_eye_in_subspace == 1, _left_eye_scale, _resolution), // !ANAGLYPH!
@@ -480,21 +750,34 @@ vec3 get_color(vec2 image_position) {
             float coef = min(_resolution.x, _resolution.y);
             vec2 position = image_position / 2. * coef + _resolution/2.;
 
-            vec2 resolution = vec2(_resolution.x / 2., _resolution.y);
+            vec2 resolution;
+            bool left_eye;
+            if (_stereo_layout == 0) {
+                resolution = vec2(_resolution.x / 2., _resolution.y);
+                left_eye = position.x < resolution.x;
+                if (!left_eye) position.x -= resolution.x;
+            } else if (_stereo_layout == 1) {
+                resolution = vec2(_resolution.x, _resolution.y / 2.);
+                left_eye = position.y >= resolution.y;
+                if (left_eye) position.y -= resolution.y;
+            } else {
+                // Every eye sees the whole frame, parity is taken from pixel without antialiasing offset
+                resolution = _resolution;
+                float line = _stereo_layout == 2 ? uv.y + _tile_offset.y : uv.x + _tile_offset.x;
+                left_eye = mod(floor(line), 2.) == 0.;
+            }
             float coef2 = min(resolution.x, resolution.y);
-            
-            if (position.x < resolution.x) {
-                image_position = (position.xy - resolution/2.) / coef2 * 2.;
+            image_position = (position.xy - resolution/2.) / coef2 * 2.;
+            final_resolution = resolution;
+
+            if (left_eye) {
                 final_matrix = _camera_left_eye;
                 final_in_subspace = _left_eye_in_subspace == 1;
                 final_scale = _left_eye_scale;
-                final_resolution = resolution;
             } else {
-                image_position = (position.xy - vec2(resolution.x, 0.) - resolution/2.) / coef2 * 2.;
                 final_matrix = _camera_right_eye;
                 final_in_subspace = _right_eye_in_subspace == 1;
                 final_scale = _right_eye_scale;
-                final_resolution = resolution;
             }
         }
 
@@ -512,18 +795,41 @@ vec2 quasi_random(int i) {
     );
 }
 
+// Uniform point on unit disk. Lens sequence is shifted for every pixel, so few samples give noise
+// instead of several sharp copies of image.
+vec2 lens_disk(vec2 u) {
+    float angle = u.x * Pi2;
+    return vec2(cos(angle), sin(angle)) * sqrt(u.y);
+}
+
 void main() {
     vec3 result = vec3(0.);
 
     if (_teleport_external_ray == 0) {
         float pixel_size = 1. / min(_resolution.x, _resolution.y);
         int a = 0;
-        for (int a = 0; a < 16; a++) { if (a >= _aa_count) break; // !FOR_NUMBER! !ANTIALIASING!
-        for (int a = _aa_start; a < _aa_count + _aa_start; a++) { // !FOR_VARIABLE! !ANTIALIASING!
-            vec2 offset = quasi_random(a);
+        // Up to 16 samples, rounded up to 18 for dispersion, see `effective_aa_count`
+        for (int a = 0; a < 18; a++) { if (a >= _aa_count) break; // !FOR_NUMBER! !ANTIALIASING!
+        for (int a = 0; a < _aa_count; a++) { // !FOR_VARIABLE! !ANTIALIASING!
+            // Progressive frames continue the sequence from `_aa_start` in both loop variants
+            int aa_index = _aa_start + a;
+            vec2 offset = quasi_random(aa_index);
+            random_init(uv + _tile_offset, aa_index);
+            lens_sample = lens_disk(fract(quasi_random(aa_index * 3 + 1) + vec2(random_next(), random_next())));
             result += get_color(uv_screen + offset * pixel_size * 2.);
         } // !ANTIALIASING!
         result = sqrt(result/float(_aa_count));
+
+        if (_output_mode == 3) {
+            result = result * result;
+        } else if (_output_mode != 0) {
+            vec3 value = clamp(result / sqrt(_output_scale), 0., 1.) * 255.;
+            if (_output_mode == 1) {
+                result = floor(value) / 255.;
+            } else {
+                result = fract(value);
+            }
+        }
     } else {
         ExternalRayTeleportation teleported;
         teleported = teleport_external_ray(Ray(vec4(_external_ray_a, 1.), vec4(_external_ray_b - _external_ray_a, 0.), 1., _camera_in_subspace == 1)); // !CAMERA_TELEPORTATION!
@@ -531,7 +837,8 @@ void main() {
         float val = 0.;
         if (int(uv.y) == 0) { val = teleported.pos.x; } else
         if (int(uv.y) == 1) { val = teleported.pos.y; } else
-        if (int(uv.y) == 2) { val = teleported.pos.z; }
+        if (int(uv.y) == 2) { val = teleported.pos.z; } else
+        if (int(uv.y) == 3) { val = teleported.encounter_at; }
 
         if (int(uv.x) == 0) {
             result = encode_float(val).xyz;
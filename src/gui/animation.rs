@@ -1,6 +1,7 @@
 use crate::gui::camera::Cam;
 use crate::gui::camera::CameraId;
 use crate::gui::camera::CurrentCam;
+use crate::gui::camera_path::{CameraPath, CameraPathId};
 use crate::gui::combo_box::*;
 use crate::gui::common::*;
 use crate::gui::easing::Easing;
@@ -1041,6 +1042,12 @@ pub struct RealAnimation {
     pub cam_easing: Easing,
     #[serde(default)]
     pub cam_easing_uniform: Option<Option<UniformId>>,
+
+    /// Camera moves along the path instead of interpolation between start and end cams
+    #[serde(default)]
+    pub use_cam_path: bool,
+    #[serde(default)]
+    pub cam_path: Option<CameraPathId>,
 }
 
 impl Default for RealAnimation {
@@ -1060,6 +1067,8 @@ impl Default for RealAnimation {
             cam_any_start: None,
             cam_any_end: None,
             cam_easing_uniform: None,
+            use_cam_path: false,
+            cam_path: None,
         }
     }
 }
@@ -1116,6 +1125,7 @@ impl StorageElem2 for RealAnimation {
         Vec<(AnimationId, String)>,
         Vec<(RealAnimationId, String)>,
         Storage2<Cam>,
+        Storage2<CameraPath>,
         AnimationFilters,
         GlobalUserUniforms,
         Storage2<Matrix>,
@@ -1127,7 +1137,10 @@ impl StorageElem2 for RealAnimation {
     fn egui(
         &mut self,
         ui: &mut Ui,
-        (animation_stages, (real_animations, (cams, (filters, (global, (matrices, input)))))): &mut Self::Input,
+        (
+            animation_stages,
+            (real_animations, (cams, (paths, (filters, (global, (matrices, input)))))),
+        ): &mut Self::Input,
         _: &mut InlineHelper<Self>,
         mut data_id: egui::Id,
         _: Self::IdWrapper,
@@ -1205,120 +1218,135 @@ impl StorageElem2 for RealAnimation {
 
         ui.separator();
 
-        if self.use_any_cam_as_start.is_some() {
-            self.use_prev_cam = false;
-        }
-        ui.add_enabled_ui(self.use_any_cam_as_start.is_none(), |ui| {
-            changed.uniform |= egui_bool_named(ui, &mut self.use_prev_cam, "Use prev end cam");
-        });
-
-        if self.use_any_cam_as_end.is_some() {
-            self.use_start_cam_as_end = false;
-        }
-        ui.add_enabled_ui(self.use_any_cam_as_end.is_none(), |ui| {
-            changed.uniform |= egui_bool_named(
+        changed.uniform |= egui_bool_named(ui, &mut self.use_cam_path, "Use camera path");
+        if self.use_cam_path {
+            changed |= paths.inline_only_name(
+                "Camera path:",
+                65.0,
+                &mut self.cam_path,
                 ui,
-                &mut self.use_start_cam_as_end,
-                "Use start cam as end cam",
+                data_id.with("cam_path"),
             );
-        });
+        } else {
+            if self.use_any_cam_as_start.is_some() {
+                self.use_prev_cam = false;
+            }
+            ui.add_enabled_ui(self.use_any_cam_as_start.is_none(), |ui| {
+                changed.uniform |= egui_bool_named(ui, &mut self.use_prev_cam, "Use prev end cam");
+            });
 
-        if self.use_prev_cam {
-            self.use_any_cam_as_start = None;
-        }
-        ui.add_enabled_ui(!self.use_prev_cam, |ui| {
-            ui.horizontal(|ui| {
-                changed.uniform |= egui_option(
+            if self.use_any_cam_as_end.is_some() {
+                self.use_start_cam_as_end = false;
+            }
+            ui.add_enabled_ui(self.use_any_cam_as_end.is_none(), |ui| {
+                changed.uniform |= egui_bool_named(
                     ui,
-                    &mut self.use_any_cam_as_start,
-                    "Use any cam as start",
-                    || false,
-                    |ui, t| {
-                        ui.separator();
-                        let mut changed = false;
-                        changed |= ui.selectable_value(t, false, "Start").changed();
-                        changed |= ui.selectable_value(t, true, "End").changed();
-                        changed
-                    },
+                    &mut self.use_start_cam_as_end,
+                    "Use start cam as end cam",
                 );
-                if self.use_any_cam_as_start.is_some() {
-                    ui.separator();
-                    egui::ComboBox::new(data_id.with("combo1"), "")
-                        .selected_text(get_real_animation_name(
-                            self.cam_any_start,
-                            &*real_animations,
-                        ))
-                        .show_ui(ui, |ui| {
-                            for (id, name) in &*real_animations {
-                                changed.uniform |=
-                                    check_changed(&mut self.cam_any_start, |value| {
-                                        ui.selectable_value(value, Some(*id), name.clone());
-                                    });
-                            }
-                        });
-                } else {
-                    self.cam_any_start = None;
-                }
             });
-        });
 
-        if self.use_start_cam_as_end {
-            self.use_any_cam_as_end = None;
-        }
-        ui.add_enabled_ui(!self.use_start_cam_as_end, |ui| {
-            ui.horizontal(|ui| {
-                changed.uniform |= egui_option(
-                    ui,
-                    &mut self.use_any_cam_as_end,
-                    "Use any cam as end",
-                    || false,
-                    |ui, t| {
+            if self.use_prev_cam {
+                self.use_any_cam_as_start = None;
+            }
+            ui.add_enabled_ui(!self.use_prev_cam, |ui| {
+                ui.horizontal(|ui| {
+                    changed.uniform |= egui_option(
+                        ui,
+                        &mut self.use_any_cam_as_start,
+                        "Use any cam as start",
+                        || false,
+                        |ui, t| {
+                            ui.separator();
+                            let mut changed = false;
+                            changed |= ui.selectable_value(t, false, "Start").changed();
+                            changed |= ui.selectable_value(t, true, "End").changed();
+                            changed
+                        },
+                    );
+                    if self.use_any_cam_as_start.is_some() {
                         ui.separator();
-                        let mut changed = false;
-                        changed |= ui.selectable_value(t, false, "Start").changed();
-                        changed |= ui.selectable_value(t, true, "End").changed();
-                        changed
-                    },
-                );
-                if self.use_any_cam_as_end.is_some() {
-                    ui.separator();
-                    egui::ComboBox::new(data_id.with("combo2"), "")
-                        .selected_text(get_real_animation_name(self.cam_any_end, &*real_animations))
-                        .show_ui(ui, |ui| {
-                            for (id, name) in &*real_animations {
-                                changed.uniform |= check_changed(&mut self.cam_any_end, |value| {
-                                    ui.selectable_value(value, Some(*id), name.clone());
-                                });
-                            }
-                        });
-                } else {
-                    self.cam_any_end = None;
-                }
+                        egui::ComboBox::new(data_id.with("combo1"), "")
+                            .selected_text(get_real_animation_name(
+                                self.cam_any_start,
+                                &*real_animations,
+                            ))
+                            .show_ui(ui, |ui| {
+                                for (id, name) in &*real_animations {
+                                    changed.uniform |=
+                                        check_changed(&mut self.cam_any_start, |value| {
+                                            ui.selectable_value(value, Some(*id), name.clone());
+                                        });
+                                }
+                            });
+                    } else {
+                        self.cam_any_start = None;
+                    }
+                });
             });
-        });
-        if !self.use_prev_cam && self.use_any_cam_as_start.is_none() {
-            changed |= cams.inline(
-                "Start cam:",
-                65.0,
-                &mut self.cam_start,
-                ui,
-                matrices,
-                data_id.with("cam_start"),
-            );
-        } else {
-            self.cam_start = None;
-        }
-        if !self.use_start_cam_as_end && self.use_any_cam_as_end.is_none() {
-            changed |= cams.inline(
-                "End cam:",
-                65.0,
-                &mut self.cam_end,
-                ui,
-                matrices,
-                data_id.with("cam_end"),
-            );
-        } else {
-            self.cam_end = None;
+
+            if self.use_start_cam_as_end {
+                self.use_any_cam_as_end = None;
+            }
+            ui.add_enabled_ui(!self.use_start_cam_as_end, |ui| {
+                ui.horizontal(|ui| {
+                    changed.uniform |= egui_option(
+                        ui,
+                        &mut self.use_any_cam_as_end,
+                        "Use any cam as end",
+                        || false,
+                        |ui, t| {
+                            ui.separator();
+                            let mut changed = false;
+                            changed |= ui.selectable_value(t, false, "Start").changed();
+                            changed |= ui.selectable_value(t, true, "End").changed();
+                            changed
+                        },
+                    );
+                    if self.use_any_cam_as_end.is_some() {
+                        ui.separator();
+                        egui::ComboBox::new(data_id.with("combo2"), "")
+                            .selected_text(get_real_animation_name(
+                                self.cam_any_end,
+                                &*real_animations,
+                            ))
+                            .show_ui(ui, |ui| {
+                                for (id, name) in &*real_animations {
+                                    changed.uniform |=
+                                        check_changed(&mut self.cam_any_end, |value| {
+                                            ui.selectable_value(value, Some(*id), name.clone());
+                                        });
+                                }
+                            });
+                    } else {
+                        self.cam_any_end = None;
+                    }
+                });
+            });
+            if !self.use_prev_cam && self.use_any_cam_as_start.is_none() {
+                changed |= cams.inline(
+                    "Start cam:",
+                    65.0,
+                    &mut self.cam_start,
+                    ui,
+                    matrices,
+                    data_id.with("cam_start"),
+                );
+            } else {
+                self.cam_start = None;
+            }
+            if !self.use_start_cam_as_end && self.use_any_cam_as_end.is_none() {
+                changed |= cams.inline(
+                    "End cam:",
+                    65.0,
+                    &mut self.cam_end,
+                    ui,
+                    matrices,
+                    data_id.with("cam_end"),
+                );
+            } else {
+                self.cam_end = None;
+            }
         }
 
         ui.separator();
@@ -1354,7 +1382,7 @@ impl StorageElem2 for RealAnimation {
     fn remove<F: FnMut(Self::IdWrapper, &mut Self::Input)>(
         &self,
         _: F,
-        (_, (_, (_, (_, (_, (matrices, input)))))): &mut Self::Input,
+        (_, (_, (_, (_, (_, (_, (matrices, input))))))): &mut Self::Input,
     ) {
         self.matrices.remove(matrices, input);
         let hpat![uniforms, formulas_cache] = input;
@@ -1367,7 +1395,7 @@ impl StorageElem2 for RealAnimation {
     fn errors_count<F: FnMut(Self::IdWrapper) -> usize>(
         &self,
         _: F,
-        (_, (_, (_, (_, (_, (matrices, input)))))): &Self::Input,
+        (_, (_, (_, (_, (_, (_, (matrices, input))))))): &Self::Input,
         _: Self::IdWrapper,
     ) -> usize {
         self.matrices.errors_count(matrices, input) + {
@@ -1383,7 +1411,10 @@ impl StorageElem2 for RealAnimation {
     fn duplicate_inline<F>(
         &self,
         _map_self: &mut F,
-        (_animation_stages, (_real_animations, (_cams, (_filters, (_global, (matrices, input)))))): &mut Self::Input,
+        (
+            _animation_stages,
+            (_real_animations, (_cams, (_paths, (_filters, (_global, (matrices, input)))))),
+        ): &mut Self::Input,
     ) -> Self
     where
         F: FnMut(Self::IdWrapper, &mut Self::Input) -> Self::IdWrapper,
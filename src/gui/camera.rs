@@ -1,4 +1,5 @@
 use crate::gui::animation::ElementsDescription;
+use crate::gui::combo_box::egui_combo_box;
 use crate::gui::common::almost_identity;
 use crate::gui::common::check_changed;
 use crate::gui::common::deg2rad;
@@ -12,6 +13,7 @@ use crate::gui::storage2::*;
 use crate::gui::uniform::AnyUniform;
 use crate::gui::uniform::FormulasCache;
 use crate::gui::unique_id::UniqueId;
+use crate::projection::Projection;
 use egui::Button;
 use egui::DragValue;
 use egui::Ui;
@@ -29,6 +31,19 @@ pub struct CalculatedCam {
     pub in_subspace: bool,
     pub matrix: DMat4,
     pub override_matrix: bool,
+
+    /// Radius of thin lens, 0 is pinhole camera without depth of field
+    pub aperture: f64,
+
+    /// Distance from camera to sharp surface, in camera units, same as depth pass
+    pub focus_distance: f64,
+
+    pub projection: Projection,
+    pub view_angle: f64, // radians
+    pub panini_param: f64,
+
+    /// `matrix` is a frame of animated matrix, so it should be updated every frame
+    pub follows_matrix: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
@@ -48,6 +63,12 @@ impl Default for CalculatedCam {
             free_movement: false,
             matrix: DMat4::IDENTITY,
             override_matrix: false,
+            aperture: 0.,
+            focus_distance: default_focus_distance(),
+            projection: Projection::Perspective,
+            view_angle: default_view_angle(),
+            panini_param: default_panini_param(),
+            follows_matrix: false,
         }
     }
 }
@@ -56,6 +77,10 @@ impl Default for CalculatedCam {
 pub enum CamLookAt {
     MatrixCenter(Option<MatrixId>), // uses inline_only_name
     Coordinate(DVec3),
+
+    /// Camera moves and rotates together with the matrix, coordinate is look_at in coordinates of
+    /// the matrix
+    MatrixFrame(Option<MatrixId>, DVec3), // uses inline_only_name
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,6 +98,33 @@ pub struct Cam {
 
     #[serde(default)]
     pub matrix: DMat4,
+
+    #[serde(default)]
+    pub aperture: f64,
+
+    #[serde(default = "default_focus_distance")]
+    pub focus_distance: f64,
+
+    #[serde(default)]
+    pub projection: Projection,
+
+    #[serde(default = "default_view_angle")]
+    pub view_angle: f64,
+
+    #[serde(default = "default_panini_param")]
+    pub panini_param: f64,
+}
+
+pub fn default_focus_distance() -> f64 {
+    3.5
+}
+
+pub fn default_view_angle() -> f64 {
+    deg2rad(90.)
+}
+
+pub fn default_panini_param() -> f64 {
+    1.0
 }
 
 impl Default for CamLookAt {
@@ -91,6 +143,11 @@ impl Default for Cam {
             in_subspace: false,
             free_movement: false,
             matrix: DMat4::IDENTITY,
+            aperture: 0.,
+            focus_distance: default_focus_distance(),
+            projection: Projection::Perspective,
+            view_angle: default_view_angle(),
+            panini_param: default_panini_param(),
         }
     }
 }
@@ -108,6 +165,23 @@ impl Wrapper for CameraId {
 }
 
 impl Cam {
+    pub fn from_calculated(cam: &CalculatedCam) -> Self {
+        Self {
+            look_at: CamLookAt::Coordinate(cam.look_at),
+            alpha: cam.alpha,
+            beta: cam.beta,
+            r: cam.r,
+            in_subspace: cam.in_subspace,
+            free_movement: cam.free_movement,
+            matrix: cam.matrix,
+            aperture: cam.aperture,
+            focus_distance: cam.focus_distance,
+            projection: cam.projection,
+            view_angle: cam.view_angle,
+            panini_param: cam.panini_param,
+        }
+    }
+
     pub fn get_pos(
         &self,
         matrices: &Storage2<Matrix>,
@@ -119,6 +193,7 @@ impl Cam {
                     + DVec3::new(0.001, 0.001, 0.001)
             }
             CamLookAt::Coordinate(pos) => pos,
+            CamLookAt::MatrixFrame(id, offset) => matrices.get(id?, input)?.project_point3(offset),
         })
     }
 
@@ -127,15 +202,27 @@ impl Cam {
         matrices: &Storage2<Matrix>,
         input: &hlist![Storage2<AnyUniform>, FormulasCache],
     ) -> Option<CalculatedCam> {
+        let (look_at, matrix, follows_matrix) = match self.look_at {
+            CamLookAt::MatrixFrame(id, offset) => {
+                (offset, matrices.get(id?, input)? * self.matrix, true)
+            }
+            _ => (self.get_pos(matrices, input)?, self.matrix, false),
+        };
         Some(CalculatedCam {
-            look_at: self.get_pos(matrices, input)?,
+            look_at,
             alpha: self.alpha,
             beta: self.beta,
             r: self.r,
             in_subspace: self.in_subspace,
             free_movement: self.free_movement,
-            matrix: self.matrix,
+            matrix,
             override_matrix: true,
+            aperture: self.aperture,
+            focus_distance: self.focus_distance,
+            projection: self.projection,
+            view_angle: self.view_angle,
+            panini_param: self.panini_param,
+            follows_matrix,
         })
     }
 
@@ -150,6 +237,12 @@ impl Cam {
                 free_movement: self.free_movement,
                 matrix: self.matrix,
                 override_matrix: true,
+                aperture: self.aperture,
+                focus_distance: self.focus_distance,
+                projection: self.projection,
+                view_angle: self.view_angle,
+                panini_param: self.panini_param,
+                follows_matrix: false,
             })
         } else {
             None
@@ -216,19 +309,48 @@ impl StorageElem2 for Cam {
         self_id: Self::IdWrapper,
     ) -> WhatChanged {
         let mut changed = WhatChanged::default();
-        let mut from_matrix = matches!(self.look_at, CamLookAt::MatrixCenter(_));
-        if ui.checkbox(&mut from_matrix, "From matrix").clicked() {
-            if from_matrix {
-                self.look_at = CamLookAt::MatrixCenter(None);
-            } else {
-                self.look_at = CamLookAt::Coordinate(Default::default());
+        let mut from_matrix = !matches!(self.look_at, CamLookAt::Coordinate(_));
+        let mut follow_frame = matches!(self.look_at, CamLookAt::MatrixFrame(_, _));
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut from_matrix, "From matrix").clicked() {
+                if from_matrix {
+                    self.look_at = CamLookAt::MatrixCenter(None);
+                } else {
+                    self.look_at = CamLookAt::Coordinate(Default::default());
+                }
+                changed.uniform = true;
             }
-            changed.uniform = true;
-        }
+            if from_matrix
+                && ui
+                    .checkbox(&mut follow_frame, "Follow matrix frame")
+                    .on_hover_text("Camera also rotates with the matrix, look at is an offset in coordinates of the matrix")
+                    .clicked()
+            {
+                self.look_at = match self.look_at {
+                    CamLookAt::MatrixCenter(id) => CamLookAt::MatrixFrame(id, DVec3::ZERO),
+                    CamLookAt::MatrixFrame(id, _) => CamLookAt::MatrixCenter(id),
+                    CamLookAt::Coordinate(coord) => CamLookAt::Coordinate(coord),
+                };
+                changed.uniform = true;
+            }
+        });
         match &mut self.look_at {
             CamLookAt::MatrixCenter(id) => {
                 changed |= matrices.inline_only_name("Name:", 45., id, ui, data_id)
             }
+            CamLookAt::MatrixFrame(id, offset) => {
+                changed |= matrices.inline_only_name("Name:", 45., id, ui, data_id);
+                ui.horizontal(|ui| {
+                    ui.label("Offset X");
+                    changed.uniform |= egui_f64(ui, &mut offset.x);
+                    ui.separator();
+                    ui.label("Y");
+                    changed.uniform |= egui_f64(ui, &mut offset.y);
+                    ui.separator();
+                    ui.label("Z");
+                    changed.uniform |= egui_f64(ui, &mut offset.z);
+                });
+            }
             CamLookAt::Coordinate(coord) => {
                 ui.horizontal(|ui| {
                     ui.monospace("X");
@@ -283,6 +405,66 @@ impl StorageElem2 for Cam {
                 );
             });
         });
+        ui.horizontal(|ui| {
+            ui.label("Aperture");
+            changed.uniform |= check_changed(&mut self.aperture, |aperture| {
+                ui.add(
+                    DragValue::new(aperture)
+                        .speed(0.001)
+                        .range(0.0..=10.0)
+                        .min_decimals(0)
+                        .max_decimals(3),
+                );
+            });
+            ui.separator();
+            ui.label("Focus");
+            changed.uniform |= check_changed(&mut self.focus_distance, |focus| {
+                ui.add(
+                    DragValue::new(focus)
+                        .speed(0.01)
+                        .range(0.01..=1000.0)
+                        .min_decimals(0)
+                        .max_decimals(2),
+                );
+            });
+        });
+        changed.uniform |= egui_combo_box(
+            ui,
+            "Projection",
+            70.,
+            &mut self.projection,
+            data_id.with("projection"),
+        );
+        ui.horizontal(|ui| {
+            if let Some((min, max)) = self.projection.view_angle_range() {
+                ui.label("View angle");
+                changed.uniform |= check_changed(&mut self.view_angle, |angle| {
+                    let mut current = rad2deg(*angle);
+                    ui.add(
+                        DragValue::new(&mut current)
+                            .speed(1.0)
+                            .range(min..=max)
+                            .suffix("°")
+                            .min_decimals(0)
+                            .max_decimals(1),
+                    );
+                    *angle = deg2rad(current);
+                });
+            }
+            if self.projection == Projection::Panini {
+                ui.separator();
+                ui.label("Panini");
+                changed.uniform |= check_changed(&mut self.panini_param, |param| {
+                    ui.add(
+                        DragValue::new(param)
+                            .speed(0.01)
+                            .range(0.0..=1.0)
+                            .min_decimals(0)
+                            .max_decimals(2),
+                    );
+                });
+            }
+        });
         if almost_identity(&self.matrix) {
             ui.monospace("Matrix: IDENTITY");
         } else {
@@ -319,6 +501,18 @@ impl StorageElem2 for Cam {
             rad2deg(current_cam.beta),
             current_cam.r
         ));
+        if current_cam.aperture > 0. {
+            ui.monospace(format!(
+                "Aperture: {:.3}, focus: {:.2}",
+                current_cam.aperture, current_cam.focus_distance
+            ));
+        }
+        ui.monospace(format!(
+            "{}, view angle: {:.1}°, panini: {:.2}",
+            current_cam.projection.name(),
+            rad2deg(current_cam.view_angle),
+            current_cam.panini_param
+        ));
         if almost_identity(&current_cam.matrix) {
             ui.monospace("Matrix: IDENTITY");
         } else {
@@ -367,19 +561,35 @@ impl StorageElem2 for Cam {
                 self.alpha = current_cam.alpha;
                 self.beta = current_cam.beta;
                 self.r = current_cam.r;
-                self.matrix = current_cam.matrix;
+                if !current_cam.follows_matrix {
+                    self.matrix = current_cam.matrix;
+                }
                 self.free_movement = current_cam.free_movement;
                 self.in_subspace = current_cam.in_subspace;
-                if matches!(self.look_at, CamLookAt::Coordinate(_)) {
-                    self.look_at = CamLookAt::Coordinate(current_cam.look_at);
+                self.aperture = current_cam.aperture;
+                self.focus_distance = current_cam.focus_distance;
+                self.projection = current_cam.projection;
+                self.view_angle = current_cam.view_angle;
+                self.panini_param = current_cam.panini_param;
+                match &mut self.look_at {
+                    CamLookAt::Coordinate(coord) => *coord = current_cam.look_at,
+                    // Current camera follows matrix, so look at is already in its coordinates
+                    CamLookAt::MatrixFrame(_, offset) if current_cam.follows_matrix => {
+                        *offset = current_cam.look_at
+                    }
+                    _ => {}
                 }
                 changed.uniform = true;
             }
-            if ui.button("Copy to current cam").clicked() {
+            let easy = self.get_easy();
+            if ui
+                .add_enabled(easy.is_some(), Button::new("Copy to current cam"))
+                .clicked()
+            {
                 ui.memory_mut(|memory| {
                     memory
                         .data
-                        .insert_persisted(egui::Id::new("OverrideCam"), self.get_easy().unwrap());
+                        .insert_persisted(egui::Id::new("OverrideCam"), easy.unwrap());
                 });
             }
         });
@@ -401,7 +611,10 @@ impl StorageElem2 for Cam {
         _: &Self::Input,
         _: Self::IdWrapper,
     ) -> usize {
-        matches!(self.look_at, CamLookAt::MatrixCenter(None)) as usize
+        matches!(
+            self.look_at,
+            CamLookAt::MatrixCenter(None) | CamLookAt::MatrixFrame(None, _)
+        ) as usize
     }
 
     fn duplicate_inline<F>(&self, _map_self: &mut F, _input: &mut Self::Input) -> Self
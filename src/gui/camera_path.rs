@@ -0,0 +1,562 @@
+use crate::gui::camera::*;
+use crate::gui::combo_box::*;
+use crate::gui::common::*;
+use crate::gui::matrix::*;
+use crate::gui::storage2::*;
+use crate::gui::uniform::*;
+use crate::gui::unique_id::UniqueId;
+
+use egui::*;
+use glam::DVec3;
+use serde::{Deserialize, Serialize};
+
+/// Samples per segment for arc length of constant speed paths.
+const ARC_LENGTH_SAMPLES: usize = 64;
+
+/// Maximal difference of interpolated parameters from dropped frames of camera recording.
+const RECORDING_TOLERANCE: f64 = 1e-3;
+
+/// Waypoints are shown under collapsed header when there are more of them.
+const COLLAPSED_WAYPOINTS: usize = 10;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PathInterpolation {
+    /// Passes through every waypoint
+    #[default]
+    CatmullRom,
+
+    /// Passes only through the first and the last waypoints, others are control points
+    Bezier,
+
+    /// Straight segments, for recorded cameras
+    Linear,
+}
+
+impl ComboBoxChoosable for PathInterpolation {
+    fn variants() -> &'static [&'static str] {
+        &["Catmull-Rom", "Bézier", "Linear"]
+    }
+
+    fn get_number(&self) -> usize {
+        use PathInterpolation::*;
+        match self {
+            CatmullRom => 0,
+            Bezier => 1,
+            Linear => 2,
+        }
+    }
+
+    fn set_number(&mut self, number: usize) {
+        use PathInterpolation::*;
+        *self = match number {
+            0 => CatmullRom,
+            1 => Bezier,
+            2 => Linear,
+            _ => unreachable!(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraWaypoint {
+    pub cam: Option<CameraId>,
+
+    /// Relative duration of the segment to the next waypoint, ignored for the last waypoint
+    pub duration: f64,
+}
+
+impl Default for CameraWaypoint {
+    fn default() -> Self {
+        Self {
+            cam: None,
+            duration: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CameraPath {
+    pub waypoints: Vec<CameraWaypoint>,
+
+    #[serde(default)]
+    pub interpolation: PathInterpolation,
+
+    /// Camera moves with constant speed along the whole path, durations of segments are ignored
+    #[serde(default)]
+    pub constant_speed: bool,
+}
+
+#[derive(Clone, Debug, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct CameraPathId(UniqueId);
+
+impl Wrapper for CameraPathId {
+    fn wrap(id: UniqueId) -> Self {
+        Self(id)
+    }
+    fn un_wrap(self) -> UniqueId {
+        self.0
+    }
+}
+
+/// Interpolated parameters of camera: look_at, alpha, beta, r, aperture, focus distance, view
+/// angle, panini parameter.
+type PathPoint = [f64; 10];
+
+fn to_point(cam: &CalculatedCam) -> PathPoint {
+    [
+        cam.look_at.x,
+        cam.look_at.y,
+        cam.look_at.z,
+        cam.alpha,
+        cam.beta,
+        cam.r,
+        cam.aperture,
+        cam.focus_distance,
+        cam.view_angle,
+        cam.panini_param,
+    ]
+}
+
+/// Position of camera itself, same as in `RotateAroundCam`.
+fn eye(p: &PathPoint, free_movement: bool) -> DVec3 {
+    let look_at = DVec3::new(p[0], p[1], p[2]);
+    if free_movement {
+        look_at
+    } else {
+        let (alpha, beta, r) = (p[3], p[4], p[5]);
+        look_at
+            + DVec3::new(
+                beta.sin() * alpha.cos(),
+                beta.cos(),
+                beta.sin() * alpha.sin(),
+            ) * r
+    }
+}
+
+fn combine(points: [(f64, &PathPoint); 4]) -> PathPoint {
+    let mut result = [0.; 10];
+    for (k, p) in points {
+        for (r, x) in result.iter_mut().zip(p) {
+            *r += k * x;
+        }
+    }
+    result
+}
+
+/// Index of segment and position inside it.
+fn segment(segments: usize, u: f64) -> (usize, f64) {
+    let pos = u.clamp(0., 1.) * segments as f64;
+    let i = (pos.floor() as usize).min(segments - 1);
+    (i, pos - i as f64)
+}
+
+fn catmull_rom(points: &[PathPoint], u: f64) -> PathPoint {
+    let segments = points.len() - 1;
+    let (i, t) = segment(segments, u);
+    let p0 = &points[i.saturating_sub(1)];
+    let p1 = &points[i];
+    let p2 = &points[i + 1];
+    let p3 = &points[(i + 2).min(segments)];
+    let (t2, t3) = (t * t, t * t * t);
+    combine([
+        (0.5 * (-t + 2. * t2 - t3), p0),
+        (0.5 * (2. - 5. * t2 + 3. * t3), p1),
+        (0.5 * (t + 4. * t2 - 3. * t3), p2),
+        (0.5 * (-t2 + t3), p3),
+    ])
+}
+
+fn linear(points: &[PathPoint], u: f64) -> PathPoint {
+    let (i, t) = segment(points.len() - 1, u);
+    let zero = [0.; 10];
+    combine([
+        (1. - t, &points[i]),
+        (t, &points[i + 1]),
+        (0., &zero),
+        (0., &zero),
+    ])
+}
+
+fn bezier(points: &[PathPoint], u: f64) -> PathPoint {
+    let mut points = points.to_vec();
+    for len in (1..points.len()).rev() {
+        for i in 0..len {
+            let next = points[i + 1];
+            for (a, b) in points[i].iter_mut().zip(next) {
+                *a += (b - *a) * u;
+            }
+        }
+    }
+    points[0]
+}
+
+/// Keyframes of camera recording that are enough for linear interpolation by time: dropped frames
+/// differ from it by less than `RECORDING_TOLERANCE`. Segment takes matrix, subspace and other not
+/// interpolated parameters from its start, so frames where they change are kept, and teleports
+/// happen at the same frames.
+pub fn decimate_recording(keyframes: &[(f64, CalculatedCam)]) -> Vec<(f64, CalculatedCam)> {
+    let same_except_point = |a: &CalculatedCam, b: &CalculatedCam| {
+        let p = to_point(a);
+        CalculatedCam {
+            look_at: DVec3::new(p[0], p[1], p[2]),
+            alpha: p[3],
+            beta: p[4],
+            r: p[5],
+            aperture: p[6],
+            focus_distance: p[7],
+            view_angle: p[8],
+            panini_param: p[9],
+            ..*b
+        } == *a
+    };
+    let fits = |from: usize, to: usize| {
+        let ((start, a), (end, b)) = (&keyframes[from], &keyframes[to]);
+        let (a_point, b_point) = (to_point(a), to_point(b));
+        keyframes[from + 1..to].iter().all(|(time, cam)| {
+            let t = (time - start) / (end - start);
+            same_except_point(cam, a)
+                && to_point(cam)
+                    .iter()
+                    .zip(a_point.iter().zip(&b_point))
+                    .all(|(x, (a, b))| (a + (b - a) * t - x).abs() < RECORDING_TOLERANCE)
+        })
+    };
+
+    let Some(first) = keyframes.first() else {
+        return Vec::new();
+    };
+    let mut result = vec![*first];
+    let mut from = 0;
+    while from + 1 < keyframes.len() {
+        let mut to = from + 1;
+        while to + 1 < keyframes.len() && fits(from, to + 1) {
+            to += 1;
+        }
+        result.push(keyframes[to]);
+        from = to;
+    }
+    result
+}
+
+impl CameraPath {
+    fn interpolate(&self, points: &[PathPoint], u: f64) -> PathPoint {
+        match self.interpolation {
+            PathInterpolation::CatmullRom => catmull_rom(points, u),
+            PathInterpolation::Bezier => bezier(points, u),
+            PathInterpolation::Linear => linear(points, u),
+        }
+    }
+
+    /// Curve parameter from time by durations of segments, waypoint `i` is at `i/(n-1)`.
+    fn time_to_parameter(&self, segments: usize, t: f64) -> f64 {
+        let durations = (0..segments)
+            .map(|i| {
+                self.waypoints
+                    .get(i)
+                    .map(|w| w.duration.max(0.))
+                    .unwrap_or(1.)
+            })
+            .collect::<Vec<_>>();
+        let total = durations.iter().sum::<f64>();
+        if total <= 0. {
+            return t;
+        }
+        let mut time = t * total;
+        for (i, duration) in durations.iter().enumerate() {
+            if time < *duration || i + 1 == segments {
+                let local = if *duration > 0. { time / duration } else { 0. };
+                return (i as f64 + local.clamp(0., 1.)) / segments as f64;
+            }
+            time -= duration;
+        }
+        1.
+    }
+
+    /// Curve parameter such that camera position moves with constant speed.
+    fn arc_length_parameter(&self, points: &[PathPoint], free_movement: bool, t: f64) -> f64 {
+        let count = ARC_LENGTH_SAMPLES * (points.len() - 1);
+        let mut lengths = vec![0.];
+        let mut prev = eye(&self.interpolate(points, 0.), free_movement);
+        for i in 1..=count {
+            let current = eye(
+                &self.interpolate(points, i as f64 / count as f64),
+                free_movement,
+            );
+            lengths.push(lengths[i - 1] + current.distance(prev));
+            prev = current;
+        }
+        let total = lengths[count];
+        if total <= 0. {
+            return t;
+        }
+        let target = t * total;
+        let i = lengths.partition_point(|x| *x < target).clamp(1, count);
+        let segment = lengths[i] - lengths[i - 1];
+        let local = if segment > 0. {
+            (target - lengths[i - 1]) / segment
+        } else {
+            0.
+        };
+        (i as f64 - 1. + local) / count as f64
+    }
+
+    /// Camera at time `t` from 0 to 1, only look_at, angles and lens are interpolated, matrix and
+    /// subspace are taken from the start of current segment, so teleports of recorded cameras
+    /// happen at the same frames.
+    pub fn sample(&self, cams: &[CalculatedCam], t: f64) -> Option<CalculatedCam> {
+        let first = *cams.first()?;
+        if cams.len() == 1 {
+            return Some(first);
+        }
+        let points = cams.iter().map(to_point).collect::<Vec<_>>();
+        let t = t.clamp(0., 1.);
+        let u = if self.constant_speed {
+            self.arc_length_parameter(&points, first.free_movement, t)
+        } else {
+            self.time_to_parameter(points.len() - 1, t)
+        };
+        let p = self.interpolate(&points, u);
+        let (i, t) = segment(points.len() - 1, u);
+        let current = if t >= 1. { cams[i + 1] } else { cams[i] };
+        Some(CalculatedCam {
+            look_at: DVec3::new(p[0], p[1], p[2]),
+            alpha: p[3],
+            beta: p[4],
+            r: p[5],
+            aperture: p[6].max(0.),
+            focus_distance: p[7].max(0.01),
+            view_angle: p[8],
+            panini_param: p[9],
+            free_movement: first.free_movement,
+            ..current
+        })
+    }
+
+    pub fn first_cam(&self) -> Option<CameraId> {
+        self.waypoints.first()?.cam
+    }
+
+    pub fn last_cam(&self) -> Option<CameraId> {
+        self.waypoints.last()?.cam
+    }
+
+    /// `None` if some waypoint has no camera.
+    pub fn get_cams(
+        &self,
+        cams: &Storage2<Cam>,
+        matrices: &Storage2<Matrix>,
+        input: &hlist![Storage2<AnyUniform>, FormulasCache],
+    ) -> Option<Vec<CalculatedCam>> {
+        self.waypoints
+            .iter()
+            .map(|w| cams.get_original(w.cam?)?.get(matrices, input))
+            .collect()
+    }
+}
+
+impl StorageElem2 for CameraPath {
+    type IdWrapper = CameraPathId;
+    type GetType = ();
+
+    const SAFE_TO_RENAME: bool = true;
+
+    type Input = hlist![Storage2<Cam>, Storage2<Matrix>];
+    type GetInput = ();
+
+    fn egui(
+        &mut self,
+        ui: &mut Ui,
+        hpat![cams, matrices]: &mut Self::Input,
+        _: &mut InlineHelper<Self>,
+        data_id: egui::Id,
+        _: Self::IdWrapper,
+    ) -> WhatChanged {
+        let mut changed = WhatChanged::default();
+
+        changed.uniform |= egui_combo_box(
+            ui,
+            "Interpolation:",
+            90.,
+            &mut self.interpolation,
+            data_id.with("interpolation"),
+        );
+        changed.uniform |= egui_bool_named(ui, &mut self.constant_speed, "Constant speed");
+        ui.separator();
+
+        let len = self.waypoints.len();
+        let mut to_delete = None;
+        let mut to_move_up = None;
+        let constant_speed = self.constant_speed;
+        let mut show_waypoints = |ui: &mut Ui| {
+            for (pos, waypoint) in self.waypoints.iter_mut().enumerate() {
+                changed |= cams.inline(
+                    "Cam:",
+                    30.,
+                    &mut waypoint.cam,
+                    ui,
+                    matrices,
+                    data_id.with(pos),
+                );
+                ui.horizontal(|ui| {
+                    if pos + 1 != len {
+                        ui.separator();
+                        ui.label("Duration:");
+                        ui.add_enabled_ui(!constant_speed, |ui| {
+                            changed.uniform |= egui_f64_positive(ui, &mut waypoint.duration);
+                        });
+                    }
+                    ui.separator();
+                    if ui.add_enabled(pos != 0, Button::new("⏶")).clicked() {
+                        to_move_up = Some(pos);
+                    }
+                    if ui.add_enabled(pos + 1 != len, Button::new("⏷")).clicked() {
+                        to_move_up = Some(pos + 1);
+                    }
+                    if ui.button("Delete").clicked() {
+                        to_delete = Some(pos);
+                    }
+                });
+                ui.separator();
+            }
+        };
+        if len > COLLAPSED_WAYPOINTS {
+            CollapsingHeader::new(format!("Waypoints ({})", len))
+                .id_salt(data_id.with("waypoints"))
+                .default_open(false)
+                .show(ui, |ui| show_waypoints(ui));
+        } else {
+            show_waypoints(ui);
+        }
+        if let Some(pos) = to_move_up {
+            self.waypoints.swap(pos - 1, pos);
+            changed.uniform = true;
+        }
+        if let Some(pos) = to_delete {
+            if let Some(id) = self.waypoints.remove(pos).cam {
+                cams.remove_as_field(id, matrices);
+            }
+            changed.uniform = true;
+        }
+        if ui.button("Add waypoint").clicked() {
+            self.waypoints.push(Default::default());
+            changed.uniform = true;
+        }
+
+        changed
+    }
+
+    fn get(&self, _: &GetHelper<Self>, _: &Self::GetInput) -> Option<Self::GetType> {
+        Some(())
+    }
+
+    fn remove<F: FnMut(Self::IdWrapper, &mut Self::Input)>(
+        &self,
+        _: F,
+        hpat![cams, matrices]: &mut Self::Input,
+    ) {
+        for id in self.waypoints.iter().filter_map(|w| w.cam) {
+            cams.remove_as_field(id, matrices);
+        }
+    }
+
+    fn errors_count<F: FnMut(Self::IdWrapper) -> usize>(
+        &self,
+        _: F,
+        _: &Self::Input,
+        _: Self::IdWrapper,
+    ) -> usize {
+        self.waypoints.iter().filter(|w| w.cam.is_none()).count()
+    }
+
+    fn duplicate_inline<F>(
+        &self,
+        _map_self: &mut F,
+        hpat![cams, matrices]: &mut Self::Input,
+    ) -> Self
+    where
+        F: FnMut(Self::IdWrapper, &mut Self::Input) -> Self::IdWrapper,
+    {
+        let mut new = self.clone();
+        for w in &mut new.waypoints {
+            w.cam = w.cam.map(|id| cams.duplicate_as_field(id, matrices));
+        }
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cam(x: f64, alpha: f64) -> CalculatedCam {
+        CalculatedCam {
+            look_at: DVec3::new(x, 0., 0.),
+            alpha,
+            free_movement: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn passes_through_waypoints() {
+        let cams = [cam(0., 0.), cam(1., 1.), cam(5., 2.)];
+        let path = CameraPath {
+            waypoints: vec![
+                CameraWaypoint {
+                    cam: None,
+                    duration: 1.,
+                },
+                CameraWaypoint {
+                    cam: None,
+                    duration: 3.,
+                },
+                Default::default(),
+            ],
+            interpolation: PathInterpolation::CatmullRom,
+            constant_speed: false,
+        };
+        assert_eq!(path.sample(&cams, 0.).unwrap(), cams[0]);
+        assert_eq!(path.sample(&cams, 0.25).unwrap(), cams[1]);
+        assert_eq!(path.sample(&cams, 1.).unwrap(), cams[2]);
+
+        let path = CameraPath {
+            interpolation: PathInterpolation::Bezier,
+            ..path
+        };
+        assert_eq!(path.sample(&cams, 1.).unwrap(), cams[2]);
+        assert!(path.sample(&[], 0.5).is_none());
+    }
+
+    #[test]
+    fn constant_speed() {
+        let cams = [cam(0., 0.), cam(1., 0.), cam(10., 0.)];
+        let path = CameraPath {
+            waypoints: vec![Default::default(); 3],
+            interpolation: PathInterpolation::Bezier,
+            constant_speed: true,
+        };
+        for (t, x) in [(0.25, 2.5), (0.5, 5.), (0.75, 7.5)] {
+            let sampled = path.sample(&cams, t).unwrap().look_at.x;
+            assert!((sampled - x).abs() < 0.01, "{} != {}", sampled, x);
+        }
+    }
+
+    #[test]
+    fn recording_decimation() {
+        let keyframes = (0..=10)
+            .map(|i| (i as f64 * 0.1, cam(i as f64, 0.)))
+            .chain((11..=20).map(|i| {
+                let mut cam = cam(i as f64, 0.);
+                cam.in_subspace = true;
+                (i as f64 * 0.1, cam)
+            }))
+            .chain([(2.5, cam(30., 1.)), (3., cam(30., 2.)), (3.5, cam(30., 2.))])
+            .collect::<Vec<_>>();
+        let times = decimate_recording(&keyframes)
+            .iter()
+            .map(|(t, _)| (t * 10.).round() as usize)
+            .collect::<Vec<_>>();
+        // Subspace is changed at frame 11, the rest are corners of motion
+        assert_eq!(times, [0, 11, 20, 25, 30, 35]);
+    }
+}
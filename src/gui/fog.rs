@@ -0,0 +1,88 @@
+use crate::gui::common::*;
+
+use egui::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Participating media that fills whole subspace, it is applied to every ray segment.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Fog {
+    pub color: [f64; 3],
+    pub density: f64,        // 0 means no fog
+    pub height_falloff: f64, // density is multiplied by exp(-height_falloff * y)
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            color: [0.5, 0.5, 0.5],
+            density: 0.0,
+            height_falloff: 0.0,
+        }
+    }
+}
+
+/// Separate fog for ordinary space and for subspace, so pocket dimensions can look different.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct SubspacesFog {
+    pub normal: Fog,
+    pub subspace: Fog,
+}
+
+impl Fog {
+    pub fn egui(&mut self, ui: &mut Ui) -> WhatChanged {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            changed |= egui_color_f64(ui, &mut self.color);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Density");
+            changed |= egui_f64_positive(ui, &mut self.density);
+            ui.separator();
+            ui.label("Height falloff");
+            changed |= egui_f64(ui, &mut self.height_falloff);
+        });
+        WhatChanged::from_uniform(changed)
+    }
+
+    fn set_uniforms(&self, material: &mut macroquad::material::Material, prefix: &str) {
+        let color = glam::DVec3::from(self.color).as_f32();
+        material.set_uniform(&format!("{prefix}_color"), color);
+        material.set_uniform(&format!("{prefix}_density"), self.density as f32);
+        material.set_uniform(&format!("{prefix}_falloff"), self.height_falloff as f32);
+    }
+}
+
+impl SubspacesFog {
+    pub fn egui(&mut self, ui: &mut Ui) -> WhatChanged {
+        let mut changed = WhatChanged::default();
+        ui.label("Ordinary space:");
+        changed |= self.normal.egui(ui);
+        ui.separator();
+        ui.label("Subspace:");
+        changed |= self.subspace.egui(ui);
+        changed
+    }
+
+    pub fn uniforms() -> Vec<(String, macroquad::prelude::UniformType)> {
+        use macroquad::prelude::UniformType;
+        ["_fog", "_subspace_fog"]
+            .into_iter()
+            .flat_map(|prefix| {
+                [
+                    (format!("{prefix}_color"), UniformType::Float3),
+                    (format!("{prefix}_density"), UniformType::Float1),
+                    (format!("{prefix}_falloff"), UniformType::Float1),
+                ]
+            })
+            .collect()
+    }
+
+    pub fn set_uniforms(&self, material: &mut macroquad::material::Material) {
+        self.normal.set_uniforms(material, "_fog");
+        self.subspace.set_uniforms(material, "_subspace_fog");
+    }
+}
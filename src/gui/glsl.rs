@@ -120,6 +120,87 @@ impl Default for IsInsideCode {
     }
 }
 
+/// `is_inside` code that is understood on CPU, so camera crosses the object without shader.
+/// Arguments are GLSL numbers or uniforms `name_u`, material can be `TELEPORT`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IsInsidePreset {
+    /// Default code with `x*x + y*y < radius_sq`
+    Disk { radius_sq: String, material: String },
+
+    /// `return is_inside_square(x, y, size_x, size_y, material);`, where the function is
+    /// `IS_INSIDE_SQUARE` from user library
+    Square {
+        size_x: String,
+        size_y: String,
+        material: String,
+    },
+}
+
+/// Function from user library of bundled scenes.
+pub const IS_INSIDE_SQUARE: &str =
+    "int is_inside_square(float x, float y, float sizex, float sizey, int material) {
+  if (abs(x) < sizex && abs(y) < sizey) {
+    return material;
+  } else {
+    return NOT_INSIDE;
+  }
+}";
+
+fn without_spaces(code: &str) -> String {
+    code.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+impl IsInsideCode {
+    pub fn preset(&self) -> Option<IsInsidePreset> {
+        let code = without_spaces(&self.0 .0);
+        let preset = if let Some(rest) = code.strip_prefix("if(x*x+y*y<") {
+            let (radius_sq, rest) = rest.split_once("){return")?;
+            IsInsidePreset::Disk {
+                radius_sq: radius_sq.to_owned(),
+                material: rest.strip_suffix(";}else{returnNOT_INSIDE;}")?.to_owned(),
+            }
+        } else {
+            let arguments = code
+                .strip_prefix("returnis_inside_square(x,y,")?
+                .strip_suffix(");")?
+                .split(',')
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            let [size_x, size_y, material] = <[String; 3]>::try_from(arguments).ok()?;
+            IsInsidePreset::Square {
+                size_x,
+                size_y,
+                material,
+            }
+        };
+        let arguments = match &preset {
+            IsInsidePreset::Disk {
+                radius_sq,
+                material,
+            } => vec![radius_sq, material],
+            IsInsidePreset::Square {
+                size_x,
+                size_y,
+                material,
+            } => vec![size_x, size_y, material],
+        };
+        arguments
+            .iter()
+            .all(|x| {
+                !x.is_empty()
+                    && x.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            })
+            .then_some(preset)
+    }
+}
+
+impl LibraryCode {
+    pub fn defines_is_inside_square(&self) -> bool {
+        without_spaces(&self.0 .0).contains(&without_spaces(IS_INSIDE_SQUARE))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Hash)]
 pub struct IntersectCode(pub GlslCode);
 
@@ -187,3 +268,51 @@ if (u > 0.) {
         ))
     }
 }
+
+// Code must return signed distance to surface and write material to `material`. Material can be NOT_INSIDE to make hole in surface.
+#[derive(Debug, Clone, Serialize, Deserialize, Hash)]
+pub struct SdfCode(pub GlslCode);
+
+impl Default for SdfCode {
+    fn default() -> Self {
+        Self(GlslCode(
+            "material = grid_gray_M;\nreturn sd_torus(p, vec2(1., 0.3));".to_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_inside_presets() {
+        let preset = |code: &str| IsInsideCode(GlslCode(code.to_owned())).preset();
+        assert_eq!(
+            IsInsideCode::default().preset(),
+            Some(IsInsidePreset::Disk {
+                radius_sq: "1.".to_owned(),
+                material: "grid_gray_M".to_owned(),
+            })
+        );
+        assert_eq!(
+            preset("return is_inside_square(x, y, room_size_x_u, 2.5, TELEPORT);"),
+            Some(IsInsidePreset::Square {
+                size_x: "room_size_x_u".to_owned(),
+                size_y: "2.5".to_owned(),
+                material: "TELEPORT".to_owned(),
+            })
+        );
+        assert_eq!(
+            preset("return is_inside_square(x, y, a_u * 2., 1., red_M);"),
+            None
+        );
+        assert_eq!(
+            preset("if (back) return NOT_INSIDE;\nreturn is_inside_square(x, y, 1., 1., red_M);"),
+            None
+        );
+        assert!(
+            LibraryCode(GlslCode(IS_INSIDE_SQUARE.replace("  ", "\t"))).defines_is_inside_square()
+        );
+    }
+}
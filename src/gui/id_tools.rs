@@ -1,5 +1,6 @@
 use super::animation::*;
 use super::camera::*;
+use super::camera_path::*;
 use super::glsl::*;
 use super::intersection_material::IntersectionMaterialId;
 use super::material::*;
@@ -20,6 +21,7 @@ pub struct IdMaps {
     pub matrices: BTreeMap<UniqueId, UniqueId>,
     pub objects: BTreeMap<UniqueId, UniqueId>,
     pub cameras: BTreeMap<UniqueId, UniqueId>,
+    pub camera_paths: BTreeMap<UniqueId, UniqueId>,
     pub textures: BTreeMap<UniqueId, UniqueId>,
     pub videos: BTreeMap<UniqueId, UniqueId>,
     pub materials: BTreeMap<UniqueId, UniqueId>,
@@ -52,12 +54,18 @@ impl IdMaps {
     pub fn map_object(&self, id: ObjectId) -> ObjectId {
         Self::map(&self.objects, id)
     }
+    pub fn map_opt_object(&self, id: Option<ObjectId>) -> Option<ObjectId> {
+        Self::map_opt(&self.objects, id)
+    }
     pub fn map_camera(&self, id: CameraId) -> CameraId {
         Self::map(&self.cameras, id)
     }
     pub fn map_opt_camera(&self, id: Option<CameraId>) -> Option<CameraId> {
         Self::map_opt(&self.cameras, id)
     }
+    pub fn map_camera_path(&self, id: CameraPathId) -> CameraPathId {
+        Self::map(&self.camera_paths, id)
+    }
     pub fn map_texture(&self, id: TextureId) -> TextureId {
         Self::map(&self.textures, id)
     }
@@ -179,6 +187,9 @@ fn remap_cam_value(c: &Cam, maps: &IdMaps) -> Cam {
         look_at: match c.look_at {
             CamLookAt::Coordinate(v) => CamLookAt::Coordinate(v),
             CamLookAt::MatrixCenter(id) => CamLookAt::MatrixCenter(maps.map_opt_matrix(id)),
+            CamLookAt::MatrixFrame(id, offset) => {
+                CamLookAt::MatrixFrame(maps.map_opt_matrix(id), offset)
+            }
         },
         alpha: c.alpha,
         beta: c.beta,
@@ -186,7 +197,20 @@ fn remap_cam_value(c: &Cam, maps: &IdMaps) -> Cam {
         in_subspace: c.in_subspace,
         free_movement: c.free_movement,
         matrix: c.matrix,
+        aperture: c.aperture,
+        focus_distance: c.focus_distance,
+        projection: c.projection,
+        view_angle: c.view_angle,
+        panini_param: c.panini_param,
+    }
+}
+
+fn remap_camera_path_value(p: &CameraPath, maps: &IdMaps) -> CameraPath {
+    let mut p2 = p.clone();
+    for w in &mut p2.waypoints {
+        w.cam = maps.map_opt_camera(w.cam);
     }
+    p2
 }
 
 fn remap_video_value(v: &Video, maps: &IdMaps) -> Video {
@@ -195,6 +219,14 @@ fn remap_video_value(v: &Video, maps: &IdMaps) -> Video {
     v2
 }
 
+fn remap_material_value(m: &Material, maps: &IdMaps) -> Material {
+    let mut m2 = m.clone();
+    if let Material::Textured { texture, .. } = &mut m2 {
+        *texture = texture.map(|id| maps.map_texture(id));
+    }
+    m2
+}
+
 fn remap_object_value(o: &Object, maps: &IdMaps) -> Object {
     use Object::*;
     match o {
@@ -227,6 +259,45 @@ fn remap_object_value(o: &Object, maps: &IdMaps) -> Object {
             intersect: intersect.clone(),
             in_subspace: in_subspace.clone(),
         },
+        Mesh {
+            path,
+            kind,
+            material,
+            in_subspace,
+        } => Mesh {
+            path: path.clone(),
+            kind: match kind {
+                ObjectType::Simple(a) => ObjectType::Simple(maps.map_opt_matrix(*a)),
+                ObjectType::Portal(a, b) => {
+                    ObjectType::Portal(maps.map_opt_matrix(*a), maps.map_opt_matrix(*b))
+                }
+            },
+            material: material.clone(),
+            in_subspace: in_subspace.clone(),
+        },
+        Sdf {
+            kind,
+            sdf_code,
+            max_steps,
+            epsilon,
+            in_subspace,
+        } => Sdf {
+            kind: match kind {
+                ObjectType::Simple(a) => ObjectType::Simple(maps.map_opt_matrix(*a)),
+                ObjectType::Portal(a, b) => {
+                    ObjectType::Portal(maps.map_opt_matrix(*a), maps.map_opt_matrix(*b))
+                }
+            },
+            sdf_code: sdf_code.clone(),
+            max_steps: *max_steps,
+            epsilon: *epsilon,
+            in_subspace: in_subspace.clone(),
+        },
+        Csg { op, a, b } => Csg {
+            op: *op,
+            a: maps.map_opt_object(*a),
+            b: maps.map_opt_object(*b),
+        },
     }
 }
 
@@ -333,6 +404,7 @@ fn remap_real_animation_value(ra: &RealAnimation, maps: &IdMaps) -> RealAnimatio
     r.cam_any_start = ra.cam_any_start.map(|id| maps.map_real_anim(id));
     r.cam_any_end = ra.cam_any_end.map(|id| maps.map_real_anim(id));
     r.cam_easing_uniform = ra.cam_easing_uniform.map(|opt| maps.map_opt_uniform(opt));
+    r.cam_path = ra.cam_path.map(|id| maps.map_camera_path(id));
     r
 }
 
@@ -344,6 +416,7 @@ pub fn compute_maps(scene: &Scene) -> IdMaps {
     maps.matrices = scene.matrices.hash_id_map();
     maps.objects = scene.objects.hash_id_map();
     maps.cameras = scene.cameras.hash_id_map();
+    maps.camera_paths = scene.camera_paths.hash_id_map();
     maps.textures = scene.textures.hash_id_map();
     maps.videos = scene.videos.hash_id_map();
     maps.materials = scene.materials.hash_id_map();
@@ -379,6 +452,11 @@ pub fn remap_scene(scene: &Scene, maps: &IdMaps) -> Scene {
         .remap_ids_and_values(&|id| *maps.cameras.get(&id).unwrap_or(&id), &|v| {
             remap_cam_value(v, maps)
         });
+    s.camera_paths = s
+        .camera_paths
+        .remap_ids_and_values(&|id| *maps.camera_paths.get(&id).unwrap_or(&id), &|v| {
+            remap_camera_path_value(v, maps)
+        });
 
     s.textures = s
         .textures
@@ -391,7 +469,7 @@ pub fn remap_scene(scene: &Scene, maps: &IdMaps) -> Scene {
     s.materials = s
         .materials
         .remap_ids_and_values(&|id| *maps.materials.get(&id).unwrap_or(&id), &|v| {
-            v.clone()
+            remap_material_value(v, maps)
         });
     s.intersection_materials = s
         .intersection_materials
@@ -526,6 +604,7 @@ pub fn stabilize_ids(mut scene: Scene, max_iters: usize) -> Scene {
             && is_identity(&maps.matrices)
             && is_identity(&maps.objects)
             && is_identity(&maps.cameras)
+            && is_identity(&maps.camera_paths)
             && is_identity(&maps.textures)
             && is_identity(&maps.videos)
             && is_identity(&maps.materials)
@@ -537,6 +616,7 @@ pub fn stabilize_ids(mut scene: Scene, max_iters: usize) -> Scene {
             && maps.matrices == prev_maps.matrices
             && maps.objects == prev_maps.objects
             && maps.cameras == prev_maps.cameras
+            && maps.camera_paths == prev_maps.camera_paths
             && maps.textures == prev_maps.textures
             && maps.videos == prev_maps.videos
             && maps.materials == prev_maps.materials
@@ -2,6 +2,7 @@ use crate::gui::combo_box::*;
 use crate::gui::common::*;
 use crate::gui::glsl::*;
 use crate::gui::storage2::*;
+use crate::gui::texture::*;
 use crate::gui::unique_id::UniqueId;
 
 use crate::gui::common::ShaderErrors;
@@ -31,10 +32,48 @@ pub enum Material {
     Refract {
         refractive_index: f64,
         add_to_color: [f64; 3],
+
+        #[serde(default)]
+        abbe_number: Option<f64>, // None means no dispersion
+
+        #[serde(default)]
+        fresnel: f64, // 0..1, weight of fresnel reflection
     },
     Complex {
         code: MaterialCode, // gets (SphereIntersection hit, Ray r) -> MaterialProcessing, must use material_next or material_final
     },
+    Textured {
+        texture: Option<TextureId>,
+        uv_scale: [f64; 2],
+        uv_offset: [f64; 2],
+        normal_coef: f64, // 0..1
+    },
+    Checker {
+        color_a: [f64; 3],
+        color_b: [f64; 3],
+        scale: f64,
+        normal_coef: f64, // 0..1
+    },
+    Stripes {
+        color_a: [f64; 3],
+        color_b: [f64; 3],
+        scale: f64,
+        width: f64,       // 0..1, part of period that has `color_a`
+        normal_coef: f64, // 0..1
+    },
+    Noise {
+        color_a: [f64; 3],
+        color_b: [f64; 3],
+        scale: f64,
+        octaves: usize,
+        normal_coef: f64, // 0..1
+    },
+    Pbr {
+        albedo: [f64; 3],
+        roughness: f64,     // 0..1
+        metallic: f64,      // 0..1
+        emission: [f64; 3], // can be bigger than 1
+    },
 }
 
 impl Default for Material {
@@ -69,17 +108,18 @@ impl StorageElem2 for Material {
 
     const SAFE_TO_RENAME: bool = false;
 
-    type Input = ShaderErrors;
+    type Input = hlist![ShaderErrors, Storage2<TextureName>];
     type GetInput = ();
 
     fn egui(
         &mut self,
         ui: &mut Ui,
-        errors: &mut Self::Input,
+        input: &mut Self::Input,
         _: &mut InlineHelper<Self>,
         data_id: egui::Id,
         self_id: Self::IdWrapper,
     ) -> WhatChanged {
+        let hpat!(errors, textures) = input;
         let mut changed = egui_combo_box(ui, "Type:", 45., self, data_id);
         ui.separator();
 
@@ -160,6 +200,8 @@ impl StorageElem2 for Material {
             Refract {
                 refractive_index,
                 add_to_color,
+                abbe_number,
+                fresnel,
             } => {
                 ui.horizontal(|ui| {
                     ui.label("Add to color");
@@ -179,6 +221,35 @@ impl StorageElem2 for Material {
                         )
                     });
                 });
+                ui.horizontal(|ui| {
+                    let mut dispersion = abbe_number.is_some();
+                    if ui.checkbox(&mut dispersion, "Dispersion").changed() {
+                        *abbe_number = dispersion.then_some(40.0);
+                        changed = true;
+                    }
+                    if let Some(abbe_number) = abbe_number {
+                        ui.separator();
+                        ui.label("Abbe number");
+                        changed |= check_changed(abbe_number, |x| {
+                            drop(
+                                ui.add(
+                                    DragValue::new(x)
+                                        .speed(0.1)
+                                        .range(1.0..=100.0)
+                                        .min_decimals(0)
+                                        .max_decimals(1),
+                                ),
+                            )
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Fresnel reflection");
+                    changed |= egui_0_1(ui, fresnel);
+                });
+                if abbe_number.is_some() || *fresnel > 0.0 {
+                    ui.label("Enable antialiasing or progressive rendering to reduce noise.");
+                }
             }
             Complex { code } => {
                 ui.horizontal_wrapped(|ui| {
@@ -215,6 +286,91 @@ impl StorageElem2 for Material {
                     egui_errors(ui, local_errors);
                 }
             }
+            Textured {
+                texture,
+                uv_scale,
+                uv_offset,
+                normal_coef,
+            } => {
+                changed |= textures
+                    .inline_only_name("Texture:", 45., texture, ui, data_id.with("texture"))
+                    .uniform;
+                ui.horizontal(|ui| {
+                    egui_label(ui, "Scale:", 45.);
+                    changed |= egui_f64(ui, &mut uv_scale[0]);
+                    changed |= egui_f64(ui, &mut uv_scale[1]);
+                });
+                ui.horizontal(|ui| {
+                    egui_label(ui, "Offset:", 45.);
+                    changed |= egui_f64(ui, &mut uv_offset[0]);
+                    changed |= egui_f64(ui, &mut uv_offset[1]);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Normal coef");
+                    changed |= egui_0_1(ui, normal_coef);
+                });
+            }
+            Checker {
+                color_a,
+                color_b,
+                scale,
+                normal_coef,
+            } => {
+                changed |= egui_procedural(ui, color_a, color_b, scale, normal_coef);
+            }
+            Stripes {
+                color_a,
+                color_b,
+                scale,
+                width,
+                normal_coef,
+            } => {
+                changed |= egui_procedural(ui, color_a, color_b, scale, normal_coef);
+                ui.horizontal(|ui| {
+                    ui.label("Width");
+                    changed |= egui_0_1(ui, width);
+                });
+            }
+            Noise {
+                color_a,
+                color_b,
+                scale,
+                octaves,
+                normal_coef,
+            } => {
+                changed |= egui_procedural(ui, color_a, color_b, scale, normal_coef);
+                ui.horizontal(|ui| {
+                    ui.label("Octaves");
+                    changed |= check_changed(octaves, |x| {
+                        drop(ui.add(DragValue::new(x).range(1..=MAX_NOISE_OCTAVES)))
+                    });
+                });
+            }
+            Pbr {
+                albedo,
+                roughness,
+                metallic,
+                emission,
+            } => {
+                ui.horizontal(|ui| {
+                    ui.label("Albedo");
+                    changed |= egui_color_f64(ui, albedo);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Roughness");
+                    changed |= egui_0_1(ui, roughness);
+                    ui.separator();
+                    ui.label("Metallic");
+                    changed |= egui_0_1(ui, metallic);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Emission");
+                    for x in emission {
+                        changed |= egui_f64_positive(ui, x);
+                    }
+                });
+                ui.label("Enable antialiasing or progressive rendering to reduce noise.");
+            }
         }
 
         WhatChanged::from_shader(changed)
@@ -229,10 +385,16 @@ impl StorageElem2 for Material {
     fn errors_count<F: FnMut(Self::IdWrapper) -> usize>(
         &self,
         _: F,
-        errors: &Self::Input,
+        hpat!(errors, textures): &Self::Input,
         self_id: Self::IdWrapper,
     ) -> usize {
-        errors.get(self_id).map(|x| x.len()).unwrap_or(0)
+        let texture_missing = match self {
+            Material::Textured { texture, .. } => texture
+                .map(|id| textures.get_name(id).is_none())
+                .unwrap_or(true) as usize,
+            _ => 0,
+        };
+        errors.get(self_id).map(|x| x.len()).unwrap_or(0) + texture_missing
     }
 
     fn duplicate_inline<F>(&self, _map_self: &mut F, _input: &mut Self::Input) -> Self
@@ -245,7 +407,10 @@ impl StorageElem2 for Material {
 
 impl ComboBoxChoosable for Material {
     fn variants() -> &'static [&'static str] {
-        &["Simple", "Reflect", "Refract", "Complex"]
+        &[
+            "Simple", "Reflect", "Refract", "Complex", "Textured", "Checker", "Stripes", "Noise",
+            "PBR",
+        ]
     }
     fn get_number(&self) -> usize {
         use Material::*;
@@ -254,6 +419,11 @@ impl ComboBoxChoosable for Material {
             Reflect { .. } => 1,
             Refract { .. } => 2,
             Complex { .. } => 3,
+            Textured { .. } => 4,
+            Checker { .. } => 5,
+            Stripes { .. } => 6,
+            Noise { .. } => 7,
+            Pbr { .. } => 8,
         }
     }
     fn set_number(&mut self, number: usize) {
@@ -266,11 +436,71 @@ impl ComboBoxChoosable for Material {
             2 => Refract {
                 add_to_color: [1.0, 1.0, 1.0],
                 refractive_index: 1.5,
+                abbe_number: None,
+                fresnel: 0.0,
             },
             3 => Complex {
                 code: Default::default(),
             },
+            4 => Textured {
+                texture: None,
+                uv_scale: [1.0, 1.0],
+                uv_offset: [0.0, 0.0],
+                normal_coef: 0.5,
+            },
+            5 => Checker {
+                color_a: [0.9, 0.9, 0.9],
+                color_b: [0.2, 0.2, 0.2],
+                scale: 4.0,
+                normal_coef: 0.5,
+            },
+            6 => Stripes {
+                color_a: [0.9, 0.9, 0.9],
+                color_b: [0.2, 0.2, 0.2],
+                scale: 4.0,
+                width: 0.5,
+                normal_coef: 0.5,
+            },
+            7 => Noise {
+                color_a: [0.9, 0.9, 0.9],
+                color_b: [0.2, 0.2, 0.2],
+                scale: 4.0,
+                octaves: 4,
+                normal_coef: 0.5,
+            },
+            8 => Pbr {
+                albedo: [0.8, 0.8, 0.8],
+                roughness: 0.5,
+                metallic: 0.0,
+                emission: [0.0, 0.0, 0.0],
+            },
             _ => unreachable!(),
         };
     }
 }
+
+/// Must be the same as `NOISE_MAX_OCTAVES` in `library.glsl`.
+pub const MAX_NOISE_OCTAVES: usize = 8;
+
+fn egui_procedural(
+    ui: &mut Ui,
+    color_a: &mut [f64; 3],
+    color_b: &mut [f64; 3],
+    scale: &mut f64,
+    normal_coef: &mut f64,
+) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Colors");
+        changed |= egui_color_f64(ui, color_a);
+        changed |= egui_color_f64(ui, color_b);
+        ui.separator();
+        ui.label("Scale");
+        changed |= egui_f64_positive(ui, scale);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Normal coef");
+        changed |= egui_0_1(ui, normal_coef);
+    });
+    changed
+}
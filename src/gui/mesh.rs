@@ -0,0 +1,383 @@
+use glam::Vec3;
+
+/// Width of the texture that holds packed mesh data, must be the same as `MESH_TEXTURE_WIDTH` in `library.glsl`.
+pub const MESH_TEXTURE_WIDTH: usize = 1024;
+
+/// Maximum triangles in one BVH leaf, must be the same as `MESH_LEAF_SIZE` in `library.glsl`.
+pub const MESH_LEAF_SIZE: usize = 4;
+
+/// Floats per one node in packed texture: min (3), max (3), miss index, triangles start, triangles count.
+const NODE_FLOATS: usize = 9;
+
+/// Floats per one triangle in packed texture: three vertices.
+const TRIANGLE_FLOATS: usize = 9;
+
+/// Floats before first node: nodes count, offset of triangles.
+const HEADER_FLOATS: usize = 2;
+
+pub fn mesh_texture_name(tag: &str) -> String {
+    crate::gui::texture::TextureName::name(&format!("mesh_{}", tag))
+}
+
+pub fn mesh_height_name(tag: &str) -> String {
+    format!("mesh_{}_height", tag)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Triangle(pub [Vec3; 3]);
+
+impl Triangle {
+    fn min(&self) -> Vec3 {
+        self.0[0].min(self.0[1]).min(self.0[2])
+    }
+
+    fn max(&self) -> Vec3 {
+        self.0[0].max(self.0[1]).max(self.0[2])
+    }
+
+    fn center(&self) -> Vec3 {
+        (self.0[0] + self.0[1] + self.0[2]) / 3.
+    }
+
+    /// Same as `triangle` in `library.glsl`, `t` can be negative or NaN.
+    fn intersect(&self, o: Vec3, d: Vec3) -> Option<f32> {
+        let [v0, v1, v2] = self.0;
+        let v1v0 = v1 - v0;
+        let v2v0 = v2 - v0;
+        let rov0 = o - v0;
+        let n = v1v0.cross(v2v0);
+        let q = rov0.cross(d);
+        let k = 1.0 / d.dot(n);
+        let u = k * (-q).dot(v2v0);
+        let v = k * q.dot(v1v0);
+        if u < 0. || v < 0. || u + v > 1. {
+            return None;
+        }
+        Some(k * (-n).dot(rov0))
+    }
+}
+
+/// Same as `box_hit` in `library.glsl`.
+fn box_hit(o: Vec3, d: Vec3, min: Vec3, max: Vec3, max_t: f32) -> bool {
+    let inv = Vec3::splat(1.) / d;
+    let t1 = (min - o) * inv;
+    let t2 = (max - o) * inv;
+    let t_near = t1.min(t2).max_element();
+    let t_far = t1.max(t2).min_element();
+    t_near <= t_far && t_far > 0. && t_near < max_t
+}
+
+/// Node of BVH, stored in depth-first order. Traversal is stackless: if ray hits the box, go to the next node, otherwise go to `miss`, which is the first node after this subtree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhNode {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub miss: u32,
+    pub triangles_start: u32,
+    pub triangles_count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub nodes: Vec<BvhNode>,
+    pub triangles: Vec<Triangle>,
+}
+
+/// Mesh packed into RGBA8 texture, each texel is one little-endian `f32`. Decoded by `decode_float` in shader.
+#[derive(Debug, Clone)]
+pub struct PackedMesh {
+    pub width: u16,
+    pub height: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Mesh {
+    pub fn load(path: &str, bytes: &[u8]) -> Result<Mesh, String> {
+        let extension = std::path::Path::new(path)
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_lowercase());
+        let triangles = match extension.as_deref() {
+            Some("obj") => parse_obj(&String::from_utf8_lossy(bytes))?,
+            Some("stl") => parse_stl(bytes)?,
+            _ => {
+                return Err(format!(
+                    "unknown mesh format of `{}`, expected .obj or .stl",
+                    path
+                ))
+            }
+        };
+        Mesh::from_triangles(triangles)
+    }
+
+    pub fn from_triangles(mut triangles: Vec<Triangle>) -> Result<Mesh, String> {
+        if triangles.is_empty() {
+            return Err("mesh has no triangles".to_owned());
+        }
+        let mut nodes = Vec::new();
+        let len = triangles.len();
+        build_node(&mut triangles, 0, len, &mut nodes);
+        Ok(Mesh { nodes, triangles })
+    }
+
+    /// Nearest positive hit of the ray, traversal is the same as in `mesh_intersect` in shader.
+    pub fn intersect(&self, o: Vec3, d: Vec3) -> Option<f32> {
+        let mut nearest = None;
+        let mut node = 0;
+        while let Some(current) = self.nodes.get(node) {
+            if !box_hit(
+                o,
+                d,
+                current.min,
+                current.max,
+                nearest.unwrap_or(f32::INFINITY),
+            ) {
+                node = current.miss as usize;
+                continue;
+            }
+            let start = current.triangles_start as usize;
+            let end = start + current.triangles_count as usize;
+            for triangle in &self.triangles[start..end] {
+                if let Some(t) = triangle.intersect(o, d) {
+                    if t > 0. && nearest.map(|nearest| t < nearest).unwrap_or(true) {
+                        nearest = Some(t);
+                    }
+                }
+            }
+            node += 1;
+        }
+        nearest
+    }
+
+    pub fn pack(&self) -> PackedMesh {
+        let triangles_offset = HEADER_FLOATS + self.nodes.len() * NODE_FLOATS;
+        let mut floats =
+            Vec::with_capacity(triangles_offset + self.triangles.len() * TRIANGLE_FLOATS);
+
+        floats.push(self.nodes.len() as f32);
+        floats.push(triangles_offset as f32);
+        for node in &self.nodes {
+            floats.extend_from_slice(&[node.min.x, node.min.y, node.min.z]);
+            floats.extend_from_slice(&[node.max.x, node.max.y, node.max.z]);
+            floats.push(node.miss as f32);
+            floats.push(node.triangles_start as f32);
+            floats.push(node.triangles_count as f32);
+        }
+        for triangle in &self.triangles {
+            for v in &triangle.0 {
+                floats.extend_from_slice(&[v.x, v.y, v.z]);
+            }
+        }
+
+        let height = floats.len().div_ceil(MESH_TEXTURE_WIDTH).max(1);
+        floats.resize(height * MESH_TEXTURE_WIDTH, 0.);
+
+        PackedMesh {
+            width: MESH_TEXTURE_WIDTH as u16,
+            height: height as u16,
+            bytes: floats.iter().flat_map(|f| f.to_le_bytes()).collect(),
+        }
+    }
+}
+
+fn build_node(triangles: &mut [Triangle], start: usize, end: usize, nodes: &mut Vec<BvhNode>) {
+    let slice = &mut triangles[start..end];
+    let min = slice
+        .iter()
+        .fold(Vec3::splat(f32::INFINITY), |acc, t| acc.min(t.min()));
+    let max = slice
+        .iter()
+        .fold(Vec3::splat(f32::NEG_INFINITY), |acc, t| acc.max(t.max()));
+
+    let index = nodes.len();
+    nodes.push(BvhNode {
+        min,
+        max,
+        miss: 0,
+        triangles_start: 0,
+        triangles_count: 0,
+    });
+
+    if slice.len() <= MESH_LEAF_SIZE {
+        nodes[index].triangles_start = start as u32;
+        nodes[index].triangles_count = slice.len() as u32;
+    } else {
+        // Median split by the longest axis of centers
+        let centers_min = slice
+            .iter()
+            .fold(Vec3::splat(f32::INFINITY), |acc, t| acc.min(t.center()));
+        let centers_max = slice
+            .iter()
+            .fold(Vec3::splat(f32::NEG_INFINITY), |acc, t| acc.max(t.center()));
+        let size = centers_max - centers_min;
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+        slice.sort_by(|a, b| {
+            a.center()[axis]
+                .partial_cmp(&b.center()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let middle = start + slice.len() / 2;
+        build_node(triangles, start, middle, nodes);
+        build_node(triangles, middle, end, nodes);
+    }
+
+    nodes[index].miss = nodes.len() as u32;
+}
+
+fn parse_f32(s: Option<&str>, line_no: usize) -> Result<f32, String> {
+    s.ok_or_else(|| format!("line {}: not enough numbers", line_no))?
+        .parse::<f32>()
+        .map_err(|err| format!("line {}: {}", line_no, err))
+}
+
+pub fn parse_obj(text: &str) -> Result<Vec<Triangle>, String> {
+    let mut vertices: Vec<Vec3> = Vec::new();
+    let mut triangles = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line_no = line_no + 1;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let x = parse_f32(words.next(), line_no)?;
+                let y = parse_f32(words.next(), line_no)?;
+                let z = parse_f32(words.next(), line_no)?;
+                vertices.push(Vec3::new(x, y, z));
+            }
+            Some("f") => {
+                let face = words
+                    .map(|word| {
+                        // Face element is `v`, `v/vt`, `v//vn` or `v/vt/vn`, negative index is relative to the end
+                        let index = word
+                            .split('/')
+                            .next()
+                            .unwrap_or_default()
+                            .parse::<i64>()
+                            .map_err(|err| format!("line {}: {}", line_no, err))?;
+                        let index = if index < 0 {
+                            vertices.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        vertices
+                            .get(usize::try_from(index).map_err(|_| {
+                                format!("line {}: vertex index out of range", line_no)
+                            })?)
+                            .copied()
+                            .ok_or_else(|| format!("line {}: vertex index out of range", line_no))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                if face.len() < 3 {
+                    return Err(format!("line {}: face has less than 3 vertices", line_no));
+                }
+                for i in 1..face.len() - 1 {
+                    triangles.push(Triangle([face[0], face[i], face[i + 1]]));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+pub fn parse_stl(bytes: &[u8]) -> Result<Vec<Triangle>, String> {
+    // Binary STL can also start with `solid`, so check the size first
+    if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if bytes.len() == 84 + count * 50 {
+            let read = |pos: usize| {
+                f32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+            };
+            let read_vec = |pos: usize| Vec3::new(read(pos), read(pos + 4), read(pos + 8));
+            return Ok((0..count)
+                .map(|i| {
+                    // Skip normal, it is recalculated in shader
+                    let pos = 84 + i * 50 + 12;
+                    Triangle([read_vec(pos), read_vec(pos + 12), read_vec(pos + 24)])
+                })
+                .collect());
+        }
+    }
+
+    let text = std::str::from_utf8(bytes).map_err(|_| "STL file is not binary nor text")?;
+    let mut vertices = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        if words.next() == Some("vertex") {
+            let x = parse_f32(words.next(), line_no + 1)?;
+            let y = parse_f32(words.next(), line_no + 1)?;
+            let z = parse_f32(words.next(), line_no + 1)?;
+            vertices.push(Vec3::new(x, y, z));
+        }
+    }
+    if vertices.len() % 3 != 0 {
+        return Err("count of vertices in STL is not divisible by 3".to_owned());
+    }
+    Ok(vertices
+        .chunks_exact(3)
+        .map(|v| Triangle([v[0], v[1], v[2]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_quad() {
+        let text = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1/1/1 2/2/1 3/3/1 -1//1\n";
+        let triangles = parse_obj(text).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[1].0[2], Vec3::new(0., 1., 0.));
+    }
+
+    #[test]
+    fn bvh_miss_links() {
+        let triangles = (0..37)
+            .map(|i| {
+                let o = Vec3::new(i as f32, (i * 7 % 5) as f32, 0.);
+                Triangle([o, o + Vec3::new(1., 0., 0.), o + Vec3::new(0., 1., 0.)])
+            })
+            .collect();
+        let mesh = Mesh::from_triangles(triangles).unwrap();
+
+        assert_eq!(mesh.nodes[0].miss as usize, mesh.nodes.len());
+        let mut covered = vec![false; mesh.triangles.len()];
+        for (i, node) in mesh.nodes.iter().enumerate() {
+            assert!(node.miss as usize > i);
+            assert!(node.triangles_count as usize <= MESH_LEAF_SIZE);
+            let is_leaf = node.triangles_count != 0;
+            assert_eq!(is_leaf, node.miss as usize == i + 1);
+            for t in node.triangles_start..node.triangles_start + node.triangles_count {
+                let t = &mesh.triangles[t as usize];
+                assert!(t.min().cmpge(node.min).all() && t.max().cmple(node.max).all());
+                covered[(t.0[0].x) as usize] = true;
+            }
+        }
+        assert!(covered.iter().all(|x| *x));
+
+        let packed = mesh.pack();
+        assert_eq!(
+            packed.bytes.len(),
+            packed.width as usize * packed.height as usize * 4
+        );
+    }
+
+    #[test]
+    fn ray_intersection() {
+        let text =
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\nv 0 0 2\nv 1 0 2\nv 0 1 2\nf 5 6 7\n";
+        let mesh = Mesh::from_triangles(parse_obj(text).unwrap()).unwrap();
+        let d = Vec3::new(0., 0., 1.);
+        assert_eq!(mesh.intersect(Vec3::new(0.25, 0.5, -1.), d), Some(1.));
+        assert_eq!(mesh.intersect(Vec3::new(0.25, 0.5, 1.), d), Some(1.));
+        assert_eq!(mesh.intersect(Vec3::new(0.75, 0.5, 1.), d), None);
+        assert_eq!(mesh.intersect(Vec3::new(2., 0.5, -1.), d), None);
+    }
+}
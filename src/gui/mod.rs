@@ -2,21 +2,25 @@ pub mod animation;
 pub mod combo_box;
 pub mod common;
 pub mod easing;
+pub mod fog;
 pub mod glsl;
 pub mod intersection_material;
 pub mod material;
 pub mod matrix;
+pub mod mesh;
 pub mod object;
 pub mod scene;
 pub mod scene_serialized;
 #[macro_use]
 pub mod storage;
 pub mod storage2;
+pub mod teleport;
 pub mod texture;
 pub mod uniform;
 pub mod video;
 
 pub mod camera;
+pub mod camera_path;
 pub mod eng_rus;
 pub mod id_tools;
 pub mod scenes;
@@ -60,6 +60,47 @@ pub enum Object {
         #[serde(default)]
         in_subspace: SubspaceType,
     },
+    Mesh {
+        path: String, // .obj or .stl file, loaded as texture
+        kind: ObjectType,
+        material: String, // name of material, not used for portals: whole surface teleports
+
+        #[serde(default)]
+        in_subspace: SubspaceType,
+    },
+    Sdf {
+        kind: ObjectType,
+        sdf_code: SdfCode, // gets position in object coordinates, must return distance and set material
+        max_steps: usize,
+        epsilon: f64,
+
+        #[serde(default)]
+        in_subspace: SubspaceType,
+    },
+    Csg {
+        op: CsgOp,
+        a: Option<ObjectId>, // operands must be closed surfaces, inside is determined by count of hits
+        b: Option<ObjectId>,
+    },
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CsgOp {
+    #[default]
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    pub fn glsl_name(&self) -> &'static str {
+        use CsgOp::*;
+        match self {
+            Union => "CSG_UNION",
+            Intersection => "CSG_INTERSECTION",
+            Difference => "CSG_DIFFERENCE",
+        }
+    }
 }
 
 impl Default for ObjectType {
@@ -97,6 +138,29 @@ impl ComboBoxChoosable for SubspaceType {
     }
 }
 
+impl ComboBoxChoosable for CsgOp {
+    fn variants() -> &'static [&'static str] {
+        &["Union", "Intersection", "Difference"]
+    }
+    fn get_number(&self) -> usize {
+        use CsgOp::*;
+        match self {
+            Union => 0,
+            Intersection => 1,
+            Difference => 2,
+        }
+    }
+    fn set_number(&mut self, number: usize) {
+        use CsgOp::*;
+        *self = match number {
+            0 => Union,
+            1 => Intersection,
+            2 => Difference,
+            _ => unreachable!(),
+        };
+    }
+}
+
 impl ComboBoxChoosable for ObjectType {
     fn variants() -> &'static [&'static str] {
         &["Simple", "Portal"]
@@ -120,7 +184,7 @@ impl ComboBoxChoosable for ObjectType {
 
 impl ComboBoxChoosable for Object {
     fn variants() -> &'static [&'static str] {
-        &["Debug", "Flat", "Complex"]
+        &["Debug", "Flat", "Complex", "Mesh", "SDF", "CSG"]
     }
     fn get_number(&self) -> usize {
         use Object::*;
@@ -128,6 +192,9 @@ impl ComboBoxChoosable for Object {
             DebugMatrix { .. } => 0,
             Flat { .. } => 1,
             Complex { .. } => 2,
+            Mesh { .. } => 3,
+            Sdf { .. } => 4,
+            Csg { .. } => 5,
         }
     }
     fn set_number(&mut self, number: usize) {
@@ -144,6 +211,24 @@ impl ComboBoxChoosable for Object {
                 intersect: Default::default(),
                 in_subspace: Default::default(),
             },
+            3 => Mesh {
+                path: String::new(),
+                kind: Default::default(),
+                material: "grid_gray".to_owned(),
+                in_subspace: Default::default(),
+            },
+            4 => Sdf {
+                kind: Default::default(),
+                sdf_code: Default::default(),
+                max_steps: 100,
+                epsilon: 0.0001,
+                in_subspace: Default::default(),
+            },
+            5 => Csg {
+                op: Default::default(),
+                a: None,
+                b: None,
+            },
             _ => unreachable!(),
         };
     }
@@ -211,7 +296,7 @@ impl StorageElem2 for Object {
         &mut self,
         ui: &mut Ui,
         input: &mut Self::Input,
-        _: &mut InlineHelper<Self>,
+        inline_helper: &mut InlineHelper<Self>,
         data_id: egui::Id,
         self_id: Self::IdWrapper,
     ) -> WhatChanged {
@@ -219,6 +304,23 @@ impl StorageElem2 for Object {
         ui.separator();
 
         use Object::*;
+        if let Csg { op, a, b } = self {
+            changed.shader |= egui_combo_label(ui, "Op:", 45., op);
+            ui.separator();
+            ui.label(
+                "Named operands are drawn by themselves too, use inline ones to see only the result.",
+            );
+            for (label, operand, data_id) in
+                [("A:", a, data_id.with("a")), ("B:", b, data_id.with("b"))]
+            {
+                let operand_changed = inline_helper.inline(label, 45., operand, ui, input, data_id);
+                // Operands are inlined into shader code, so any change of them requires recompilation
+                changed.shader |= operand_changed.uniform || operand_changed.shader;
+                changed |= operand_changed;
+            }
+            return changed;
+        }
+
         let (errors, input) = input;
         let has_errors = errors.get(self_id).is_some();
         match self {
@@ -369,6 +471,101 @@ impl StorageElem2 for Object {
                     egui_errors(ui, local_errors);
                 }
             }
+            Mesh {
+                path,
+                kind,
+                material,
+                in_subspace,
+            } => {
+                changed.shader |= egui_combo_label(ui, "Subspace:", 45., in_subspace);
+                ui.separator();
+                changed.shader |= egui_combo_label(ui, "Kind:", 45., kind);
+                changed |= kind.egui(ui, input, data_id.with(0));
+                ui.separator();
+                ui.horizontal(|ui| {
+                    egui_label(ui, "File:", 45.);
+                    changed.shader |= ui.text_edit_singleline(path).changed();
+                });
+                if matches!(kind, ObjectType::Simple { .. }) {
+                    ui.horizontal(|ui| {
+                        egui_label(ui, "Material:", 45.);
+                        egui_with_red_field(ui, has_errors, |ui| {
+                            changed.shader |= ui.text_edit_singleline(material).changed();
+                        });
+                    });
+                }
+                if let Some(local_errors) = errors.get(self_id) {
+                    egui_errors(ui, local_errors);
+                }
+            }
+            Sdf {
+                kind,
+                sdf_code,
+                max_steps,
+                epsilon,
+                in_subspace,
+            } => {
+                changed.shader |= egui_combo_label(ui, "Subspace:", 45., in_subspace);
+                ui.separator();
+                changed.shader |= egui_combo_label(ui, "Kind:", 45., kind);
+                changed |= kind.egui(ui, input, data_id.with(0));
+                ui.separator();
+                ui.horizontal(|ui| {
+                    egui_label(ui, "Steps:", 45.);
+                    changed.shader |= check_changed(max_steps, |x| {
+                        drop(ui.add(DragValue::new(x).range(1..=10000)))
+                    });
+                });
+                ui.horizontal(|ui| {
+                    egui_label(ui, "Epsilon:", 45.);
+                    changed.shader |= check_changed(epsilon, |x| {
+                        drop(
+                            ui.add(
+                                DragValue::new(x)
+                                    .speed(0.00001)
+                                    .range(0.0000001..=1.0)
+                                    .max_decimals(7),
+                            ),
+                        )
+                    });
+                });
+                ui.separator();
+                ui.horizontal_wrapped(|ui| {
+                    ui.spacing_mut().item_spacing.x = 0.;
+
+                    ui.add(Label::new(
+                        egui::RichText::new("float ").color(COLOR_TYPE).monospace(),
+                    ));
+                    ui.add(Label::new(
+                        egui::RichText::new("sdf").color(COLOR_FUNCTION).monospace(),
+                    ));
+                    ui.add(Label::new(egui::RichText::new("(").monospace()));
+                    ui.add(Label::new(
+                        egui::RichText::new("vec3 ").color(COLOR_TYPE).monospace(),
+                    ));
+                    ui.add(Label::new(egui::RichText::new("p, ").monospace()));
+                    if matches!(kind, ObjectType::Portal { .. }) {
+                        ui.add(Label::new(
+                            egui::RichText::new("bool ").color(COLOR_TYPE).monospace(),
+                        ));
+                        ui.add(Label::new(egui::RichText::new("first, ").monospace()));
+                    }
+                    ui.add(Label::new(
+                        egui::RichText::new("out int ")
+                            .color(COLOR_TYPE)
+                            .monospace(),
+                    ));
+                    ui.add(Label::new(egui::RichText::new("material) {").monospace()));
+                });
+                egui_with_red_field(ui, has_errors, |ui| {
+                    changed |= sdf_code.0.egui(ui);
+                });
+                ui.add(Label::new(egui::RichText::new("}").monospace()));
+                if let Some(local_errors) = errors.get(self_id) {
+                    egui_errors(ui, local_errors);
+                }
+            }
+            Csg { .. } => {} // Handled above, because operands need the whole input
         }
 
         changed
@@ -380,11 +577,18 @@ impl StorageElem2 for Object {
 
     fn remove<F: FnMut(Self::IdWrapper, &mut Self::Input)>(
         &self,
-        _: F,
-        (_, (matrices, input)): &mut Self::Input,
+        mut f: F,
+        input: &mut Self::Input,
     ) {
         use Object::*;
         use ObjectType::*;
+        if let Csg { op: _, a, b } = self {
+            for id in a.iter().chain(b.iter()) {
+                f(*id, input);
+            }
+            return;
+        }
+        let (_, (matrices, input)) = input;
         match self {
             DebugMatrix(a)
             | Flat {
@@ -392,6 +596,12 @@ impl StorageElem2 for Object {
             }
             | Complex {
                 kind: Simple(a), ..
+            }
+            | Mesh {
+                kind: Simple(a), ..
+            }
+            | Sdf {
+                kind: Simple(a), ..
             } => {
                 if let Some(id) = a {
                     matrices.remove_as_field(*id, input);
@@ -402,6 +612,12 @@ impl StorageElem2 for Object {
             }
             | Complex {
                 kind: Portal(a, b), ..
+            }
+            | Mesh {
+                kind: Portal(a, b), ..
+            }
+            | Sdf {
+                kind: Portal(a, b), ..
             } => {
                 if let Some(id) = a {
                     matrices.remove_as_field(*id, input);
@@ -410,12 +626,13 @@ impl StorageElem2 for Object {
                     matrices.remove_as_field(*id, input);
                 }
             }
+            Csg { .. } => {}
         }
     }
 
     fn errors_count<F: FnMut(Self::IdWrapper) -> usize>(
         &self,
-        _: F,
+        mut f: F,
         (errors, (matrices, input)): &Self::Input,
         self_id: Self::IdWrapper,
     ) -> usize {
@@ -429,24 +646,34 @@ impl StorageElem2 for Object {
         use ObjectType::*;
         result += match self {
             DebugMatrix(a) => a.map(|id| matrices.errors_inline(id, input)).unwrap_or(1),
-            Flat { kind, .. } | Complex { kind, .. } => match kind {
-                Simple(a) => a.map(|id| matrices.errors_inline(id, input)).unwrap_or(1),
-                Portal(a, b) => {
-                    a.map(|id| matrices.errors_inline(id, input)).unwrap_or(1)
-                        + b.map(|id| matrices.errors_inline(id, input)).unwrap_or(1)
+            Flat { kind, .. } | Complex { kind, .. } | Mesh { kind, .. } | Sdf { kind, .. } => {
+                match kind {
+                    Simple(a) => a.map(|id| matrices.errors_inline(id, input)).unwrap_or(1),
+                    Portal(a, b) => {
+                        a.map(|id| matrices.errors_inline(id, input)).unwrap_or(1)
+                            + b.map(|id| matrices.errors_inline(id, input)).unwrap_or(1)
+                    }
                 }
-            },
+            }
+            Csg { op: _, a, b } => a.map(&mut f).unwrap_or(1) + b.map(&mut f).unwrap_or(1),
         };
 
         result
     }
 
-    fn duplicate_inline<F>(&self, _map_self: &mut F, input: &mut Self::Input) -> Self
+    fn duplicate_inline<F>(&self, map_self: &mut F, input: &mut Self::Input) -> Self
     where
         F: FnMut(Self::IdWrapper, &mut Self::Input) -> Self::IdWrapper,
     {
         use Object::*;
         use ObjectType::*;
+        if let Csg { op, a, b } = self {
+            return Csg {
+                op: *op,
+                a: a.map(|id| map_self(id, input)),
+                b: b.map(|id| map_self(id, input)),
+            };
+        }
         let (_, (matrices, uniforms_input)) = input;
         use crate::gui::unique_id::UniqueId;
         use std::collections::BTreeMap;
@@ -519,6 +746,77 @@ impl StorageElem2 for Object {
                     in_subspace,
                 }
             }
+            Mesh {
+                path,
+                kind,
+                material,
+                in_subspace,
+            } => {
+                let kind = match kind {
+                    Simple(a) => Simple(a.map(|id| {
+                        matrices.duplicate_as_field_with_visited(id, uniforms_input, &mut m_visited)
+                    })),
+                    Portal(a, b) => Portal(
+                        a.map(|id| {
+                            matrices.duplicate_as_field_with_visited(
+                                id,
+                                uniforms_input,
+                                &mut m_visited,
+                            )
+                        }),
+                        b.map(|id| {
+                            matrices.duplicate_as_field_with_visited(
+                                id,
+                                uniforms_input,
+                                &mut m_visited,
+                            )
+                        }),
+                    ),
+                };
+                Mesh {
+                    path,
+                    kind,
+                    material,
+                    in_subspace,
+                }
+            }
+            Sdf {
+                kind,
+                sdf_code,
+                max_steps,
+                epsilon,
+                in_subspace,
+            } => {
+                let kind = match kind {
+                    Simple(a) => Simple(a.map(|id| {
+                        matrices.duplicate_as_field_with_visited(id, uniforms_input, &mut m_visited)
+                    })),
+                    Portal(a, b) => Portal(
+                        a.map(|id| {
+                            matrices.duplicate_as_field_with_visited(
+                                id,
+                                uniforms_input,
+                                &mut m_visited,
+                            )
+                        }),
+                        b.map(|id| {
+                            matrices.duplicate_as_field_with_visited(
+                                id,
+                                uniforms_input,
+                                &mut m_visited,
+                            )
+                        }),
+                    ),
+                };
+                Sdf {
+                    kind,
+                    sdf_code,
+                    max_steps,
+                    epsilon,
+                    in_subspace,
+                }
+            }
+            x @ Csg { .. } => x,
         }
     }
 }
@@ -11,16 +11,21 @@ use super::scene_serialized::{
 };
 use crate::code_generation::*;
 use crate::gui::animation::*;
-use crate::gui::camera::Cam;
+use crate::gui::camera::{default_panini_param, default_view_angle, Cam};
+use crate::gui::camera_path::{decimate_recording, CameraPath, CameraWaypoint, PathInterpolation};
 use crate::gui::common::*;
 use crate::gui::eng_rus::EngRusText;
+use crate::gui::fog::*;
 use crate::gui::intersection_material::*;
 use crate::gui::material::*;
 use crate::gui::matrix::*;
+use crate::gui::mesh::{mesh_height_name, mesh_texture_name};
 use crate::gui::object::*;
+use crate::gui::teleport::{CpuObject, CpuShape};
 use crate::gui::texture::*;
 use crate::gui::uniform::*;
 use crate::gui::video::*;
+use crate::projection::Projection;
 use crate::shader_error_parser::*;
 
 use egui::*;
@@ -37,6 +42,15 @@ pub struct CamSettings {
     pub beta: f64,
     pub r: f64,
     pub offset_after_material: f64,
+
+    #[serde(default)]
+    pub projection: Projection,
+
+    #[serde(default = "default_view_angle")]
+    pub view_angle: f64,
+
+    #[serde(default = "default_panini_param")]
+    pub panini_param: f64,
 }
 
 impl Default for CamSettings {
@@ -47,6 +61,9 @@ impl Default for CamSettings {
             beta: 0.,
             r: 3.5,
             offset_after_material: 0.000025,
+            projection: Projection::Perspective,
+            view_angle: default_view_angle(),
+            panini_param: default_panini_param(),
         }
     }
 }
@@ -82,6 +99,9 @@ pub struct Scene {
 
     pub cameras: Storage2<Cam>,
 
+    #[serde(default)]
+    pub camera_paths: Storage2<CameraPath>,
+
     pub textures: Storage2<TextureName>,
 
     #[serde(default)]
@@ -120,8 +140,14 @@ pub struct Scene {
     #[serde(skip)]
     prev_t_raw: f64,
 
+    #[serde(skip)]
+    prev_cam_matrix: Option<(DMat4, bool)>,
+
     #[serde(default)]
     pub skybox: Option<String>,
+
+    #[serde(default)]
+    pub fog: SubspacesFog,
 }
 
 // In case of panic
@@ -261,7 +287,11 @@ impl Scene {
 
         changed |= self.cameras.egui(ui, &mut self.matrices, "Cameras");
 
-        changed |= self.materials.egui(ui, &mut data.errors, "Materials");
+        with_swapped!(x => (self.cameras, self.matrices);
+            changed |= self.camera_paths.egui(ui, &mut x, "Camera paths"));
+
+        with_swapped!(x => (data.errors, self.textures);
+            changed |= self.materials.egui(ui, &mut x, "Materials"));
 
         changed |=
             self.intersection_materials
@@ -282,6 +312,10 @@ impl Scene {
             );
         });
 
+        ui.collapsing("Fog", |ui| {
+            changed |= self.fog.egui(ui);
+        });
+
         changed |= self.library.egui(ui, &mut data.errors, "User GLSL code");
 
         ui.collapsing("Filter to animation stages", |ui| {
@@ -313,7 +347,7 @@ impl Scene {
                 .animation_stages
                 .egui(ui, &mut x, "Animation stages"));
 
-        with_swapped!(x => (self.animation_stages.visible_elements_vec(), self.animations.visible_elements_vec(), self.cameras, self.animations_filters, self.user_uniforms, self.matrices, self.uniforms, data.formulas_cache);
+        with_swapped!(x => (self.animation_stages.visible_elements_vec(), self.animations.visible_elements_vec(), self.cameras, self.camera_paths, self.animations_filters, self.user_uniforms, self.matrices, self.uniforms, data.formulas_cache);
             changed |= self
                 .animations
                 .egui(ui, &mut x, "Animations"));
@@ -374,7 +408,8 @@ impl Scene {
             self.matrices.errors_count_all(&x))
             + with_swapped!(x => (data.errors, self.matrices, self.uniforms, data.formulas_cache);
                 self.objects.errors_count_all(&x))
-            + self.materials.errors_count_all(&data.errors)
+            + with_swapped!(x => (data.errors, self.textures);
+                self.materials.errors_count_all(&x))
             + self.intersection_materials.errors_count_all(&data.errors)
             + self.library.errors_count_all(&data.errors)
             + if let Some(local_errors) = data.errors.get::<()>(()) {
@@ -408,9 +443,271 @@ impl Scene {
             }
         }
 
+        for (tag, _, object) in self.tagged_objects() {
+            if let Object::Mesh { .. } = object {
+                result.push(mesh_texture_name(&tag));
+            }
+        }
+
+        result
+    }
+
+    /// Portals with both matrices, in the order of their `teleport_{tag}_1_M` and
+    /// `teleport_{tag}_2_M` materials.
+    fn portals<'a>(
+        &'a self,
+        tagged_objects: &'a [(String, ObjectId, Object)],
+    ) -> Vec<(&'a str, MatrixName<'a>, MatrixName<'a>)> {
+        use Object::*;
+        use ObjectType::*;
+        tagged_objects
+            .iter()
+            .filter_map(|(tag, _, object)| match object {
+                Flat {
+                    kind: Portal(first, second),
+                    ..
+                }
+                | Complex {
+                    kind: Portal(first, second),
+                    ..
+                }
+                | Mesh {
+                    kind: Portal(first, second),
+                    ..
+                }
+                | Sdf {
+                    kind: Portal(first, second),
+                    ..
+                } => Some((
+                    tag.as_str(),
+                    Object::get_name((*first)?, &self.matrices)?,
+                    Object::get_name((*second)?, &self.matrices)?,
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Shape of flat object on CPU and whether it teleports, from preset of `is_inside` code.
+    /// `None` if `is_inside` never returns inside.
+    fn flat_cpu_shape(
+        &self,
+        is_inside: &IsInsideCode,
+        formulas_cache: &FormulasCache,
+        has_is_inside_square: bool,
+    ) -> Option<(CpuShape, bool)> {
+        let eval = |x: &str| -> Option<f64> {
+            x.parse().ok().or_else(|| {
+                let id = self.uniforms.find_id(x.strip_suffix("_u")?)?;
+                Some(self.uniforms.get(id, formulas_cache)?.into())
+            })
+        };
+        let shape = match is_inside.preset() {
+            Some(IsInsidePreset::Disk {
+                radius_sq,
+                material,
+            }) => eval(&radius_sq).map(|radius_sq| {
+                let radius = radius_sq.max(0.).sqrt();
+                (CpuShape::Disk { radius }, material)
+            }),
+            Some(IsInsidePreset::Square {
+                size_x,
+                size_y,
+                material,
+            }) if has_is_inside_square => eval(&size_x).zip(eval(&size_y)).map(|(x, y)| {
+                let shape = CpuShape::Rectangle {
+                    half_width: x,
+                    half_height: y,
+                };
+                (shape, material)
+            }),
+            _ => None,
+        };
+        match shape {
+            Some((_, material)) if material == "NOT_INSIDE" => None,
+            Some((shape, material)) if material == "TELEPORT" => Some((shape, true)),
+            Some((shape, material)) if material.ends_with("_M") => Some((shape, false)),
+            _ => Some((CpuShape::UnknownPlane, false)),
+        }
+    }
+
+    /// Objects for camera teleportation on CPU, teleport matrices are the same as in
+    /// `set_uniforms`. Objects defined by shader code are `CpuShape::Unknown`, and flat objects
+    /// are `CpuShape::UnknownPlane` unless their `is_inside` is a preset, segments that reach them
+    /// are traced by `teleport_external_ray` in shader. `None` if the whole scene needs shader.
+    /// Called once per frame, it evaluates formulas of all matrices.
+    pub fn cpu_objects(&mut self, data: &mut Data) -> Option<Vec<CpuObject>> {
+        if self
+            .intersection_materials
+            .visible_elements()
+            .next()
+            .is_some()
+        {
+            return None;
+        }
+        self.compile_all_formulas(&data.formulas_cache);
+
+        // Formulas of uniforms are evaluated before matrices borrow them
+        let has_is_inside_square = self.library.visible_elements().any(|(id, _)| {
+            self.library
+                .get_original(id)
+                .map(LibraryCode::defines_is_inside_square)
+                .unwrap_or(false)
+        });
+        let flat_shapes = self
+            .objects
+            .visible_elements()
+            .filter_map(|(id, _)| match self.objects.get_original(id)? {
+                Object::Flat { is_inside, .. } => Some((
+                    id,
+                    self.flat_cpu_shape(is_inside, &data.formulas_cache, has_is_inside_square),
+                )),
+                _ => None,
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let objects = &self.objects;
+        let uniforms = &mut self.uniforms;
+        let matrices = &self.matrices;
+        let mut get = |id: Option<MatrixId>| -> Option<DMat4> {
+            let id = id?;
+            with_swapped!(x => (*uniforms, data.formulas_cache); matrices.get(id, &x))
+        };
+
+        use Object::*;
+        use ObjectType::*;
+        let mut result = Vec::new();
+        // Operands of CSG are never needed, so tags are just positions like in `tagged_objects`
+        for (pos, (id, _)) in objects.visible_elements().enumerate() {
+            let tag = pos.to_string();
+            match objects.get_original(id)? {
+                DebugMatrix(matrix) => result.push(CpuObject {
+                    shape: CpuShape::DebugAxes,
+                    matrix: get(*matrix)?,
+                    in_subspace: SubspaceType::Both,
+                    teleport: None,
+                }),
+                Mesh {
+                    kind: Simple(matrix),
+                    in_subspace,
+                    ..
+                } => result.push(CpuObject {
+                    shape: CpuShape::Mesh(tag.clone()),
+                    matrix: get(*matrix)?,
+                    in_subspace: in_subspace.clone(),
+                    teleport: None,
+                }),
+                Mesh {
+                    kind: Portal(a, b),
+                    in_subspace,
+                    ..
+                } => {
+                    let (ma, mb) = (get(*a)?, get(*b)?);
+                    for (matrix, teleport) in [(ma, mb * ma.inverse()), (mb, ma * mb.inverse())] {
+                        result.push(CpuObject {
+                            shape: CpuShape::Mesh(tag.clone()),
+                            matrix,
+                            in_subspace: in_subspace.clone(),
+                            teleport: Some(teleport),
+                        });
+                    }
+                }
+                Flat {
+                    kind, in_subspace, ..
+                } => {
+                    let Some((shape, teleports)) = flat_shapes.get(&id).cloned().flatten() else {
+                        continue;
+                    };
+                    match kind {
+                        // Same as `process_plane_intersection`, teleport does nothing there
+                        Simple(_) if teleports => {}
+                        Simple(matrix) => result.push(CpuObject {
+                            shape,
+                            matrix: get(*matrix)?,
+                            in_subspace: in_subspace.clone(),
+                            teleport: None,
+                        }),
+                        Portal(a, b) => {
+                            let (ma, mb) = (get(*a)?, get(*b)?);
+                            for (matrix, teleport) in
+                                [(ma, mb * ma.inverse()), (mb, ma * mb.inverse())]
+                            {
+                                result.push(CpuObject {
+                                    shape: shape.clone(),
+                                    matrix,
+                                    in_subspace: in_subspace.clone(),
+                                    teleport: teleports.then_some(teleport),
+                                });
+                            }
+                        }
+                    }
+                }
+                Complex { in_subspace, .. } | Sdf { in_subspace, .. } => result.push(CpuObject {
+                    shape: CpuShape::Unknown,
+                    matrix: DMat4::IDENTITY,
+                    in_subspace: in_subspace.clone(),
+                    teleport: None,
+                }),
+                Csg { .. } => result.push(CpuObject {
+                    shape: CpuShape::Unknown,
+                    matrix: DMat4::IDENTITY,
+                    in_subspace: SubspaceType::Both,
+                    teleport: None,
+                }),
+            }
+        }
+        Some(result)
+    }
+
+    /// Objects that need generated code, parents go before their operands. Visible objects are tagged by their position, operands of CSG are tagged by tag of parent with `_a` or `_b` suffix.
+    pub fn tagged_objects(&self) -> Vec<(String, ObjectId, Object)> {
+        let mut result = Vec::new();
+        for (pos, (id, _)) in self.objects.visible_elements().enumerate() {
+            self.tag_objects(pos.to_string(), id, 0, &mut result);
+        }
         result
     }
 
+    /// Returns `false` and adds nothing if object is CSG with missing operand, such object is
+    /// skipped in generated code, and error is shown on it.
+    fn tag_objects(
+        &self,
+        tag: String,
+        id: ObjectId,
+        depth: usize,
+        result: &mut Vec<(String, ObjectId, Object)>,
+    ) -> bool {
+        let object = match self.objects.get(id, &()) {
+            Some(object) => object,
+            None => return false,
+        };
+        let operands = match &object {
+            Object::Csg { a, b, .. } => {
+                vec![(format!("{}_a", tag), *a), (format!("{}_b", tag), *b)]
+            }
+            _ => vec![],
+        };
+        let len = result.len();
+        result.push((tag, id, object));
+
+        // Named operands can reference each other in a cycle, in this case code is not generated
+        if !operands.is_empty() && depth >= MAX_CSG_DEPTH {
+            result.truncate(len);
+            return false;
+        }
+        for (tag, id) in operands {
+            let added = match id {
+                Some(id) => self.tag_objects(tag, id, depth + 1, result),
+                None => false,
+            };
+            if !added {
+                result.truncate(len);
+                return false;
+            }
+        }
+        true
+    }
+
     pub fn compile_all_formulas(&self, cache: &FormulasCache) {
         for id in self.uniforms.all_ids() {
             if let AnyUniform::Formula(f) | AnyUniform::FormulaInt(f) =
@@ -424,12 +721,12 @@ impl Scene {
     pub fn uniforms(&self, data: &Data) -> Option<Vec<macroquad::prelude::UniformDesc>> {
         self.compile_all_formulas(&data.formulas_cache);
 
+        let tagged_objects = self.tagged_objects();
         let mut result = Vec::new();
         use Object::*;
         use ObjectType::*;
-        for (id, _) in self.objects.visible_elements() {
-            let object = self.objects.get(id, &()).unwrap();
-            match &object {
+        for (_, _, object) in &tagged_objects {
+            match object {
                 DebugMatrix(matrix) => {
                     let matrix = Object::get_name((*matrix)?, &self.matrices).unwrap();
                     result.push(matrix.normal_name());
@@ -444,7 +741,9 @@ impl Scene {
                     kind,
                     intersect: _,
                     in_subspace: _,
-                } => match kind {
+                }
+                | Mesh { kind, .. }
+                | Sdf { kind, .. } => match kind {
                     Simple(matrix) => {
                         let matrix = Object::get_name((*matrix)?, &self.matrices)?;
                         result.push(matrix.normal_name());
@@ -463,6 +762,7 @@ impl Scene {
                         }
                     }
                 },
+                Csg { .. } => {}
             }
         }
 
@@ -479,6 +779,12 @@ impl Scene {
             .map(|name| (name, UniformType::Mat4))
             .collect::<Vec<_>>();
 
+        for (tag, _, object) in &tagged_objects {
+            if let Object::Mesh { .. } = object {
+                result.push((mesh_height_name(tag), UniformType::Float1));
+            }
+        }
+
         for (id, name) in self.uniforms.visible_elements() {
             let name = format!("{}_u", name);
             match self.uniforms.get(id, &data.formulas_cache) {
@@ -503,9 +809,13 @@ impl Scene {
             ("_left_eye_in_subspace".to_owned(), UniformType::Int1),
             ("_right_eye_in_subspace".to_owned(), UniformType::Int1),
             ("_resolution".to_owned(), UniformType::Float2),
+            ("_tile_offset".to_owned(), UniformType::Float2),
             ("_ray_tracing_depth".to_owned(), UniformType::Int1),
             ("_aa_count".to_owned(), UniformType::Int1),
             ("_aa_start".to_owned(), UniformType::Int1),
+            ("_output_mode".to_owned(), UniformType::Int1),
+            ("_output_scale".to_owned(), UniformType::Float1),
+            ("_aux_pass".to_owned(), UniformType::Int1),
             ("_draw_side_by_side".to_owned(), UniformType::Int1),
             ("_offset_after_material".to_owned(), UniformType::Float1),
             ("_draw_anaglyph".to_owned(), UniformType::Int1),
@@ -521,9 +831,13 @@ impl Scene {
             ("_t_start".to_owned(), UniformType::Float1),
             ("_t_end".to_owned(), UniformType::Float1),
             ("_view_angle".to_owned(), UniformType::Float1),
-            ("_use_panini_projection".to_owned(), UniformType::Int1),
-            ("_use_360_camera".to_owned(), UniformType::Int1),
-            ("_use_180_camera".to_owned(), UniformType::Int1),
+            ("_vr_format".to_owned(), UniformType::Int1),
+            ("_stereo_layout".to_owned(), UniformType::Int1),
+            ("_eye_distance".to_owned(), UniformType::Float1),
+            ("_aperture".to_owned(), UniformType::Float1),
+            ("_focus_distance".to_owned(), UniformType::Float1),
+            ("_projection".to_owned(), UniformType::Int1),
+            ("_ortho_size".to_owned(), UniformType::Float1),
             ("_angle_color_disable".to_owned(), UniformType::Int1),
             ("_darken_by_distance".to_owned(), UniformType::Int1),
             ("_grid_disable".to_owned(), UniformType::Int1),
@@ -533,6 +847,7 @@ impl Scene {
             ("_external_ray_a".to_owned(), UniformType::Float3),
             ("_external_ray_b".to_owned(), UniformType::Float3),
         ]);
+        result.extend(SubspacesFog::uniforms());
 
         let result = result
             .into_iter()
@@ -545,17 +860,16 @@ impl Scene {
     pub fn set_uniforms(&mut self, material: &mut macroquad::material::Material, data: &mut Data) {
         self.compile_all_formulas(&data.formulas_cache);
 
-        let objects = &self.objects;
+        let tagged_objects = self.tagged_objects();
         let uniforms = &mut self.uniforms;
         let matrices = &self.matrices;
-        let passed_matrices = self
-            .objects
-            .visible_elements()
-            .filter_map(|(id, _)| {
+        let passed_matrices = tagged_objects
+            .iter()
+            .filter_map(|(_, _, object)| {
                 use Object::*;
                 use ObjectType::*;
                 Some(
-                    match &objects.get(id, &())? {
+                    match object {
                         DebugMatrix(matrix) => vec![(*matrix)?],
                         Flat {
                             kind,
@@ -566,10 +880,13 @@ impl Scene {
                             kind,
                             intersect: _,
                             in_subspace: _,
-                        } => match kind {
+                        }
+                        | Mesh { kind, .. }
+                        | Sdf { kind, .. } => match kind {
                             Simple(matrix) => vec![(*matrix)?],
                             Portal(a, b) => vec![(*a)?, (*b)?],
                         },
+                        Csg { .. } => vec![],
                     }
                     .into_iter()
                     .filter_map(|id| Some((id, Object::get_name(id, matrices)?))),
@@ -591,11 +908,11 @@ impl Scene {
             }
         }
 
-        let teleport_matrices = self.objects.visible_elements().filter_map(|(id, _)| {
+        let teleport_matrices = tagged_objects.iter().filter_map(|(_, _, object)| {
             use Object::*;
             use ObjectType::*;
-            match &objects.get(id, &())? {
-                DebugMatrix(_) => None,
+            match object {
+                DebugMatrix(_) | Csg { .. } => None,
                 Flat {
                     kind,
                     is_inside: _,
@@ -605,7 +922,9 @@ impl Scene {
                     kind,
                     intersect: _,
                     in_subspace: _,
-                } => match kind {
+                }
+                | Mesh { kind, .. }
+                | Sdf { kind, .. } => match kind {
                     Simple(_) => None,
                     Portal(a, b) => {
                         let a = (*a)?;
@@ -654,6 +973,8 @@ impl Scene {
                 }
             }
         }
+
+        self.fog.set_uniforms(material);
     }
 }
 
@@ -690,8 +1011,155 @@ impl Scene {
         Some(result)
     }
 
+    /// Code that intersects ray `r` with object and writes the nearest hit into `i`, uses variables declared in `scene_intersect`.
+    fn object_intersection_code(
+        &self,
+        tag: &str,
+        object: &Object,
+        result: &mut StringStorage,
+    ) -> Option<()> {
+        use Object::*;
+        use ObjectType::*;
+        match object {
+            DebugMatrix(matrix) => {
+                let matrix = Object::get_name((*matrix)?, &self.matrices)?;
+                result.add_string(format!(
+                    "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray = normalize_ray(transformed_ray);",
+                    matrix.inverse_name()
+                ));
+                result.add_string("ihit = debug_intersect(transformed_ray);\nihit.hit.t /= len;\n");
+                result.add_string(format!(
+                    "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(adjugate({}) * i.hit.n); }}\n\n",
+                    matrix.inverse_name()
+                ));
+            }
+            Flat {
+                kind,
+                is_inside: _,
+                in_subspace,
+            } => {
+                match in_subspace {
+                    SubspaceType::Normal => result.add_string("if (r.in_subspace == false) {"),
+                    SubspaceType::Subspace => result.add_string("if (r.in_subspace == true) {"),
+                    SubspaceType::Both => {}
+                }
+                match kind {
+                    Simple(matrix) => {
+                        let matrix = Object::get_name((*matrix)?, &self.matrices)?;
+                        result.add_string(format!(
+                            "normal = -get_normal({});\n",
+                            matrix.normal_name()
+                        ));
+                        result.add_string(format!(
+                            "hit = plane_intersect(r, {}, get_normal({}));\n",
+                            matrix.inverse_name(),
+                            matrix.normal_name()
+                        ));
+                        result.add_string(format!(
+                            "if (nearer(i, hit)) {{ i = process_plane_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal))); }}\n\n",
+                            tag
+                        ));
+                    }
+                    Portal(a, b) => {
+                        let mut add = |matrix: &MatrixName, first, material| {
+                            result.add_string(format!(
+                                "normal = {}get_normal({});\n",
+                                if first { "-" } else { "" },
+                                matrix.normal_name()
+                            ));
+                            result.add_string(format!(
+                                "hit = plane_intersect(r, {}, normal);\n",
+                                matrix.inverse_name()
+                            ));
+                            result.add_string(format!(
+                                "if (nearer(i, hit)) {{ i = process_portal_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), {}), {}); }}\n\n",
+                                tag, first, material
+                            ));
+                        };
+                        let a = Object::get_name((*a)?, &self.matrices)?;
+                        let b = Object::get_name((*b)?, &self.matrices)?;
+                        add(&a, true, format!("teleport_{}_1_M", tag));
+                        add(&b, false, format!("teleport_{}_2_M", tag));
+                    }
+                };
+                match in_subspace {
+                    SubspaceType::Normal | SubspaceType::Subspace => result.add_string("}"),
+                    SubspaceType::Both => {}
+                }
+            }
+            Complex {
+                kind,
+                intersect: _,
+                in_subspace,
+            }
+            | Mesh {
+                kind, in_subspace, ..
+            }
+            | Sdf {
+                kind, in_subspace, ..
+            } => {
+                match in_subspace {
+                    SubspaceType::Normal => result.add_string("if (r.in_subspace == false) {"),
+                    SubspaceType::Subspace => result.add_string("if (r.in_subspace == true) {"),
+                    SubspaceType::Both => {}
+                }
+                match kind {
+                    Simple(matrix) => {
+                        let matrix = Object::get_name((*matrix)?, &self.matrices)?;
+                        result.add_string(format!(
+                            "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray = normalize_ray(transformed_ray);",
+                            matrix.inverse_name()
+                        ));
+                        result.add_string(format!(
+                            "ihit = intersect_{}(transformed_ray);\nihit.hit.t /= len;\n",
+                            tag,
+                        ));
+                        result.add_string(format!(
+                            "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(adjugate({}) * i.hit.n); }}\n\n",
+                            matrix.normal_name()
+                        ));
+                    }
+                    Portal(a, b) => {
+                        let mut add = |matrix: &MatrixName, first, material| {
+                            result.add_string(format!(
+                                "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray = normalize_ray(transformed_ray);",
+                                matrix.inverse_name()
+                            ));
+                            result.add_string(format!(
+                                "ihit = intersect_{}(transformed_ray, {});\nihit.hit.t /= len;\n",
+                                tag, first
+                            ));
+                            result.add_string(format!(
+                                "if (nearer(i, ihit) && ihit.material != NOT_INSIDE) {{ if (ihit.material == TELEPORT) {{ ihit.material = {}; }} if (ihit.material == TELEPORT_SUBSPACE) {{ ihit.material = {}; ihit.in_subspace = true; }} i = ihit; i.hit.n = normalize(adjugate({}) * i.hit.n); }}\n\n",
+                                material,
+                                material,
+                                matrix.normal_name()
+                            ));
+                        };
+                        let a = Object::get_name((*a)?, &self.matrices)?;
+                        let b = Object::get_name((*b)?, &self.matrices)?;
+                        add(&a, true, format!("teleport_{}_1_M", tag));
+                        add(&b, false, format!("teleport_{}_2_M", tag));
+                    }
+                };
+                match in_subspace {
+                    SubspaceType::Normal | SubspaceType::Subspace => result.add_string("}"),
+                    SubspaceType::Both => {}
+                }
+            }
+            Csg { .. } => {
+                result.add_string(format!(
+                    "ihit = csg_{}(r);\nif (nearer(i, ihit)) {{ i = ihit; }}\n\n",
+                    tag
+                ));
+            }
+        }
+        Some(())
+    }
+
     pub fn generate_shader_code(&self, data: &Data) -> Option<StringStorage> {
         let mut storages: BTreeMap<String, StringStorage> = BTreeMap::new();
+        let tagged_objects = self.tagged_objects();
 
         storages.insert(
             "uniforms".to_owned(),
@@ -714,6 +1182,12 @@ impl Scene {
                 result.add_string(format!("uniform sampler2D {};\n", TextureName::name(&name)));
             }
 
+            for (tag, _, object) in &tagged_objects {
+                if let Object::Mesh { .. } = object {
+                    result.add_string(format!("uniform sampler2D {};\n", mesh_texture_name(tag)));
+                }
+            }
+
             result
         });
 
@@ -762,12 +1236,25 @@ impl Scene {
                     Refract {
                         refractive_index,
                         add_to_color,
-                    } => {
+                        abbe_number: None,
+                        fresnel,
+                    } if *fresnel == 0.0 => {
                         material_processing.add_string(format!(
                             "return material_refract(hit, r, vec3({:e}, {:e}, {:e}), {:e});\n",
                             add_to_color[0], add_to_color[1], add_to_color[2], refractive_index,
                         ));
                     }
+                    Refract {
+                        refractive_index,
+                        add_to_color,
+                        abbe_number,
+                        fresnel,
+                    } => {
+                        material_processing.add_string(format!(
+                            "return material_refract_dispersion(hit, r, vec3({:e}, {:e}, {:e}), {:e}, {:e}, {:e});\n",
+                            add_to_color[0], add_to_color[1], add_to_color[2], refractive_index, abbe_number.unwrap_or(0.0), fresnel,
+                        ));
+                    }
                     x @ Complex { .. } => {
                         let code = match x {
                             Complex { code } => code,
@@ -776,42 +1263,74 @@ impl Scene {
                         material_processing.add_identifier_string(id, &code.0 .0);
                         material_processing.add_string("\n");
                     }
-                };
-            }
-            for (pos, first, second) in self
-                .objects
-                .visible_elements()
-                .map(|(id, _)| self.objects.get(id, &()).unwrap())
-                .enumerate()
-                .filter_map(|(pos, x)| match x {
-                    Object::DebugMatrix { .. }
-                    | Object::Flat {
-                        kind: ObjectType::Simple { .. },
-                        ..
+                    Textured {
+                        texture,
+                        uv_scale,
+                        uv_offset,
+                        normal_coef,
+                    } => {
+                        let texture = self.textures.get_name((*texture)?)??;
+                        material_processing.add_string(format!(
+                            "return material_textured(hit, r, {}, vec2({:e}, {:e}), vec2({:e}, {:e}), {:e});\n",
+                            TextureName::name(texture), uv_scale[0], uv_scale[1], uv_offset[0], uv_offset[1], normal_coef,
+                        ));
                     }
-                    | Object::Complex {
-                        kind: ObjectType::Simple { .. },
-                        ..
-                    } => None,
-                    Object::Flat {
-                        kind: ObjectType::Portal(first, second),
-                        ..
+                    Checker {
+                        color_a,
+                        color_b,
+                        scale,
+                        normal_coef,
+                    } => {
+                        material_processing.add_string(format!(
+                            "return material_checker(hit, r, vec3({:e}, {:e}, {:e}), vec3({:e}, {:e}, {:e}), {:e}, {:e});\n",
+                            color_a[0], color_a[1], color_a[2], color_b[0], color_b[1], color_b[2], scale, normal_coef,
+                        ));
                     }
-                    | Object::Complex {
-                        kind: ObjectType::Portal(first, second),
-                        ..
-                    } => Some((pos, first, second)),
-                })
-                .filter_map(|(pos, first, second)| {
-                    Some((
-                        pos,
-                        Object::get_name(first?, &self.matrices)?,
-                        Object::get_name(second?, &self.matrices)?,
-                    ))
-                })
-            {
-                let name_m_1 = format!("teleport_{}_1_M", pos);
-                let name_m_2 = format!("teleport_{}_2_M", pos);
+                    Stripes {
+                        color_a,
+                        color_b,
+                        scale,
+                        width,
+                        normal_coef,
+                    } => {
+                        material_processing.add_string(format!(
+                            "return material_stripes(hit, r, vec3({:e}, {:e}, {:e}), vec3({:e}, {:e}, {:e}), {:e}, {:e}, {:e});\n",
+                            color_a[0], color_a[1], color_a[2], color_b[0], color_b[1], color_b[2], scale, width, normal_coef,
+                        ));
+                    }
+                    Noise {
+                        color_a,
+                        color_b,
+                        scale,
+                        octaves,
+                        normal_coef,
+                    } => {
+                        material_processing.add_string(format!(
+                            "return material_noise(hit, r, vec3({:e}, {:e}, {:e}), vec3({:e}, {:e}, {:e}), {:e}, {}, {:e});\n",
+                            color_a[0], color_a[1], color_a[2], color_b[0], color_b[1], color_b[2], scale, octaves, normal_coef,
+                        ));
+                    }
+                    Pbr {
+                        albedo,
+                        roughness,
+                        metallic,
+                        emission,
+                    } => {
+                        material_processing.add_string(format!(
+                            "return material_pbr(hit, r, vec3({:e}, {:e}, {:e}), {:e}, {:e}, vec3({:e}, {:e}, {:e}));\n",
+                            albedo[0], albedo[1], albedo[2], roughness, metallic, emission[0], emission[1], emission[2],
+                        ));
+                    }
+                };
+            }
+            // Portal materials go last, so auxiliary passes can tell them from user materials
+            material_defines.add_string(format!(
+                "#define PORTAL_MATERIAL_START (USER_MATERIAL_OFFSET + {})\n",
+                counter
+            ));
+            for (tag, first, second) in self.portals(&tagged_objects) {
+                let name_m_1 = format!("teleport_{}_1_M", tag);
+                let name_m_2 = format!("teleport_{}_2_M", tag);
 
                 material_defines.add_string(format!(
                     "#define {} (USER_MATERIAL_OFFSET + {})\n",
@@ -849,161 +1368,191 @@ impl Scene {
             use ObjectType::*;
             let mut result = StringStorage::default();
 
-            for (pos, (id, _)) in self.objects.visible_elements().enumerate() {
-                let object = self.objects.get(id, &()).unwrap();
+            // Named object can be both visible and operand of CSG, but its lines can be identified only once
+            let mut identified = BTreeSet::new();
+            let mut add_user_code = |result: &mut StringStorage, id: ObjectId, code: &str| {
+                if identified.insert(id) {
+                    result.add_identifier_string(id, code);
+                } else {
+                    result.add_string(code);
+                }
+            };
+
+            // Operands go before their parents, because parents call them
+            for (tag, id, object) in tagged_objects.iter().rev() {
+                let id = *id;
                 match object {
                     DebugMatrix(_) => {}
                     Flat { kind, is_inside, in_subspace: _ } => {
                         if matches!(kind, Portal { .. }) {
                             result.add_string(format!(
                                 "int is_inside_{}(vec4 pos, float x, float y, bool back, bool first) {{\n",
-                                pos
+                                tag
                             ));
                         } else {
-                            result.add_string(format!("int is_inside_{}(vec4 pos, float x, float y, bool back) {{\n", pos));
+                            result.add_string(format!("int is_inside_{}(vec4 pos, float x, float y, bool back) {{\n", tag));
                         }
-                        result.add_identifier_string(id, &is_inside.0.0);
+                        add_user_code(&mut result, id, &is_inside.0.0);
                         result.add_string("\n}\n");
                     }
                     Complex { kind, intersect, in_subspace: _ } => {
                         if matches!(kind, Portal { .. }) {
                             result.add_string(format!(
                                 "SceneIntersection intersect_{}(Ray r, bool first) {{\n",
-                                pos
+                                tag
                             ));
                         } else {
-                            result.add_string(format!("SceneIntersection intersect_{}(Ray r) {{\n", pos));
+                            result.add_string(format!("SceneIntersection intersect_{}(Ray r) {{\n", tag));
                         }
-                        result.add_identifier_string(id, &intersect.0.0);
+                        add_user_code(&mut result, id, &intersect.0.0);
                         result.add_string("\n}\n");
                     }
-                }
-            }
-            result
-        });
-
-        storages.insert("intersections".to_owned(), {
-            use Object::*;
-            use ObjectType::*;
-            let mut result = StringStorage::default();
+                    Mesh { kind, material, .. } => {
+                        if matches!(kind, Portal { .. }) {
+                            result.add_string(format!(
+                                "SceneIntersection intersect_{}(Ray r, bool first) {{\n",
+                                tag
+                            ));
+                            result.add_string(format!("return mesh_intersect({}, {}, r, TELEPORT);", mesh_texture_name(tag), mesh_height_name(tag)));
+                        } else {
+                            result.add_string(format!("SceneIntersection intersect_{}(Ray r) {{\n", tag));
+                            result.add_string(format!("return mesh_intersect({}, {}, r, {}_M);", mesh_texture_name(tag), mesh_height_name(tag), material));
+                        }
+                        result.add_string("\n}\n");
+                    }
+                    Sdf { kind, sdf_code, max_steps, epsilon, .. } => {
+                        let first = if matches!(kind, Portal { .. }) { "first, " } else { "" };
+                        if matches!(kind, Portal { .. }) {
+                            result.add_string(format!("float sdf_{}(vec3 p, bool first, out int material) {{\n", tag));
+                        } else {
+                            result.add_string(format!("float sdf_{}(vec3 p, out int material) {{\n", tag));
+                        }
+                        add_user_code(&mut result, id, &sdf_code.0.0);
+                        result.add_string("\n}\n");
 
-            for (pos, (id, _)) in self.objects.visible_elements().enumerate() {
-                let object = self.objects.get(id, &()).unwrap();
-                match object {
-                    DebugMatrix(matrix) => {
-                        let matrix = Object::get_name(matrix?, &self.matrices)?;
+                        if matches!(kind, Portal { .. }) {
+                            result.add_string(format!("SceneIntersection intersect_{}(Ray r, bool first) {{\n", tag));
+                        } else {
+                            result.add_string(format!("SceneIntersection intersect_{}(Ray r) {{\n", tag));
+                        }
                         result.add_string(format!(
-                            "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray = normalize_ray(transformed_ray);",
-                            matrix.inverse_name()
+                            r#"int material = NOT_INSIDE;
+int material_normal = NOT_INSIDE;
+// Start a bit ahead, so ray that leaves this surface doesn't hit it again
+float t = {eps:e} * 2.;
+for (int k = 0; k < {steps}; k++) {{
+    vec3 p = r.o.xyz + r.d.xyz * t;
+    float d = abs(sdf_{pos}(p, {first}material));
+    if (d < {eps:e}) {{
+        if (material != NOT_INSIDE) {{
+            vec2 e = vec2({eps:e}, -{eps:e});
+            vec3 n = e.xyy * sdf_{pos}(p + e.xyy, {first}material_normal)
+                   + e.yyx * sdf_{pos}(p + e.yyx, {first}material_normal)
+                   + e.yxy * sdf_{pos}(p + e.yxy, {first}material_normal)
+                   + e.xxx * sdf_{pos}(p + e.xxx, {first}material_normal);
+            return SceneIntersection(material, SurfaceIntersection(true, t, atan(p.z, p.x), atan(length(p.xz), p.y), normalize(n)), false);
+        }}
+        // Hole in surface, step through it
+        d = {eps:e} * 2.;
+    }}
+    t += d;
+    if (t > SDF_MAX_DISTANCE) break;
+}}
+return scene_intersection_none;
+}}
+"#,
+                            eps = epsilon,
+                            steps = max_steps,
+                            pos = tag,
+                            first = first,
                         ));
-                        result.add_string("ihit = debug_intersect(transformed_ray);\nihit.hit.t /= len;\n");
+                    }
+                    Csg { op, .. } => {
+                        // Operands are always tagged, see `tag_objects`
                         result.add_string(format!(
-                            "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(adjugate({}) * i.hit.n); }}\n\n",
-                            matrix.inverse_name()
+                            r#"SceneIntersection csg_{tag}(Ray r) {{
+// Ray can start inside of operand, this is found by parity of count of its hits
+bool inside_a = false;
+bool inside_b = false;
+float t = 0.;
+SceneIntersection h = object_{tag}_a(r);
+for (int k = 0; k < CSG_MAX_HITS; k++) {{
+    if (!h.hit.hit) break;
+    inside_a = !inside_a;
+    t += h.hit.t + CSG_OFFSET;
+    h = object_{tag}_a(offset_ray(r, t));
+}}
+t = 0.;
+h = object_{tag}_b(r);
+for (int k = 0; k < CSG_MAX_HITS; k++) {{
+    if (!h.hit.hit) break;
+    inside_b = !inside_b;
+    t += h.hit.t + CSG_OFFSET;
+    h = object_{tag}_b(offset_ray(r, t));
+}}
+
+// Walk through hits of both operands in order, the first hit that changes inside state of result is its surface
+float ta = 0.;
+float tb = 0.;
+SceneIntersection ha = object_{tag}_a(r);
+SceneIntersection hb = object_{tag}_b(r);
+for (int k = 0; k < CSG_MAX_HITS * 2; k++) {{
+    if (!ha.hit.hit && !hb.hit.hit) break;
+    bool before = csg_inside(inside_a, inside_b, {op});
+    if (ha.hit.hit && (!hb.hit.hit || ta + ha.hit.t < tb + hb.hit.t)) {{
+        inside_a = !inside_a;
+        if (csg_inside(inside_a, inside_b, {op}) != before) {{
+            ha.hit.t += ta;
+            return ha;
+        }}
+        ta += ha.hit.t + CSG_OFFSET;
+        ha = object_{tag}_a(offset_ray(r, ta));
+    }} else {{
+        inside_b = !inside_b;
+        if (csg_inside(inside_a, inside_b, {op}) != before) {{
+            hb.hit.t += tb;
+            // Inside of subtracted operand is outside of result
+            if ({op} == CSG_DIFFERENCE) hb.hit.n = -hb.hit.n;
+            return hb;
+        }}
+        tb += hb.hit.t + CSG_OFFSET;
+        hb = object_{tag}_b(offset_ray(r, tb));
+    }}
+}}
+return scene_intersection_none;
+}}
+"#,
+                            tag = tag,
+                            op = op.glsl_name(),
                         ));
                     }
-                    Flat { kind, is_inside: _, in_subspace } => {
-                        match in_subspace {
-                            SubspaceType::Normal => result.add_string("if (r.in_subspace == false) {"),
-                            SubspaceType::Subspace => result.add_string("if (r.in_subspace == true) {"),
-                            SubspaceType::Both => {},
-                        }
-                        match kind {
-                            Simple(matrix) => {
-                                let matrix = Object::get_name(matrix?, &self.matrices)?;
-                                result.add_string(format!(
-                                    "normal = -get_normal({});\n",
-                                    matrix.normal_name()
-                                ));
-                                result.add_string(format!(
-                                    "hit = plane_intersect(r, {}, get_normal({}));\n",
-                                    matrix.inverse_name(),
-                                    matrix.normal_name()
-                                ));
-                                result.add_string(format!(
-                                    "if (nearer(i, hit)) {{ i = process_plane_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal))); }}\n\n",
-                                    pos
-                                ));
-                            }
-                            Portal(a, b) => {
-                                let mut add = |matrix: &MatrixName, first, material| {
-                                    result.add_string(format!(
-                                        "normal = {}get_normal({});\n",
-                                        if first { "-" } else { "" },
-                                        matrix.normal_name()
-                                    ));
-                                    result.add_string(format!(
-                                        "hit = plane_intersect(r, {}, normal);\n",
-                                        matrix.inverse_name()
-                                    ));
-                                    result.add_string(format!(
-                                        "if (nearer(i, hit)) {{ i = process_portal_intersection(i, hit, is_inside_{}(r.o + r.d * hit.t, hit.u, hit.v, is_collinear(hit.n, normal), {}), {}); }}\n\n",
-                                        pos, first, material
-                                    ));
-                                };
-                                let a = Object::get_name(a?, &self.matrices)?;
-                                let b = Object::get_name(b?, &self.matrices)?;
-                                add(&a, true, format!("teleport_{}_1_M", pos));
-                                add(&b, false, format!("teleport_{}_2_M", pos));
-                            }
-                        };
-                        match in_subspace {
-                            SubspaceType::Normal | SubspaceType::Subspace => result.add_string("}"),
-                            SubspaceType::Both => {},
-                        }
-                    },
-                    Complex { kind, intersect: _, in_subspace } => {
-                        match in_subspace {
-                            SubspaceType::Normal => result.add_string("if (r.in_subspace == false) {"),
-                            SubspaceType::Subspace => result.add_string("if (r.in_subspace == true) {"),
-                            SubspaceType::Both => {},
-                        }
-                        match kind {
-                            Simple(matrix) => {
-                                let matrix = Object::get_name(matrix?, &self.matrices)?;
-                                result.add_string(format!(
-                                    "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray = normalize_ray(transformed_ray);",
-                                    matrix.inverse_name()
-                                ));
-                                result.add_string(format!(
-                                    "ihit = intersect_{}(transformed_ray);\nihit.hit.t /= len;\n",
-                                    pos,
-                                ));
-                                result.add_string(format!(
-                                    "if (nearer(i, ihit)) {{ i = ihit; i.hit.n = normalize(adjugate({}) * i.hit.n); }}\n\n",
-                                    matrix.normal_name()
-                                ));
-                            }
-                            Portal(a, b) => {
-                                let mut add = |matrix: &MatrixName, first, material| {
-                                    result.add_string(format!(
-                                        "transformed_ray = transform({}, r);\nlen = length(transformed_ray.d);\ntransformed_ray = normalize_ray(transformed_ray);",
-                                        matrix.inverse_name()
-                                    ));
-                                    result.add_string(format!(
-                                        "ihit = intersect_{}(transformed_ray, {});\nihit.hit.t /= len;\n",
-                                        pos, first
-                                    ));
-                                    result.add_string(format!(
-                                        "if (nearer(i, ihit) && ihit.material != NOT_INSIDE) {{ if (ihit.material == TELEPORT) {{ ihit.material = {}; }} if (ihit.material == TELEPORT_SUBSPACE) {{ ihit.material = {}; ihit.in_subspace = true; }} i = ihit; i.hit.n = normalize(adjugate({}) * i.hit.n); }}\n\n",
-                                        material,
-                                        material,
-                                        matrix.normal_name()
-                                    ));
-                                };
-                                let a = Object::get_name(a?, &self.matrices)?;
-                                let b = Object::get_name(b?, &self.matrices)?;
-                                add(&a, true, format!("teleport_{}_1_M", pos));
-                                add(&b, false, format!("teleport_{}_2_M", pos));
-                            }
-                        };
-                        match in_subspace {
-                            SubspaceType::Normal | SubspaceType::Subspace => result.add_string("}"),
-                            SubspaceType::Both => {},
-                        }
-                    },
                 }
-                result.add_string("\n");
+
+                // Operand is called as function, which has the same code as visible object has in `scene_intersect`
+                if is_operand_tag(tag) {
+                    result.add_string(format!("SceneIntersection object_{}(Ray r) {{\n", tag));
+                    result.add_string("SceneIntersection i = scene_intersection_none;\nSceneIntersection ihit = scene_intersection_none;\nSurfaceIntersection hit = intersection_none;\nvec3 normal = vec3(0.);\nfloat len = 1.;\nRay transformed_ray = ray_none;\n");
+                    self.object_intersection_code(tag, object, &mut result)?;
+                    result.add_string("return i;\n}\n");
+                }
+            }
+            result
+        });
+
+        storages.insert("intersections".to_owned(), {
+            let mut result = StringStorage::default();
+            // Object ids start from 1, 0 means that nothing is hit
+            for (pos, (tag, _, object)) in tagged_objects
+                .iter()
+                .filter(|(tag, _, _)| !is_operand_tag(tag))
+                .enumerate()
+            {
+                result.add_string("previous_t = i.hit.t;\n");
+                self.object_intersection_code(tag, object, &mut result)?;
+                result.add_string(format!(
+                    "if (i.hit.t < previous_t) {{ scene_object_id = {}; }}\n\n",
+                    pos + 1
+                ));
             }
             result
         });
@@ -1025,11 +1574,17 @@ impl Scene {
 
         storages.insert("intersection_material_processing".to_owned(), {
             let mut result = StringStorage::default();
+            // Ids continue after objects from `intersections`
+            let objects_count = tagged_objects
+                .iter()
+                .filter(|(tag, _, _)| !is_operand_tag(tag))
+                .count();
             for (pos, (_, _)) in self.intersection_materials.visible_elements().enumerate() {
                 result.add_string(format!("hit = intersect_material_{}(r);\n", pos));
-                result.add_string(
-                    "if (nearer(result.scene.hit, hit.scene.hit)) { result = hit; }\n\n",
-                );
+                result.add_string(format!(
+                    "if (nearer(result.scene.hit, hit.scene.hit)) {{ result = hit; material_object_id = {}; }}\n\n",
+                    objects_count + pos + 1
+                ));
             }
             result
         });
@@ -1109,12 +1664,55 @@ impl Scene {
         Some(res)
     }
 
+    /// Whether some material traces one color channel per sample.
+    pub fn has_dispersion(&self) -> bool {
+        self.materials.visible_elements().any(|(id, _)| {
+            matches!(
+                self.materials.get_original(id),
+                Some(Material::Refract {
+                    abbe_number: Some(abbe_number),
+                    ..
+                }) if *abbe_number > 0.
+            )
+        })
+    }
+
+    /// Meshes refer to materials by name, unknown name would break compilation of the whole shader,
+    /// so it is reported on the object instead.
+    fn unknown_mesh_materials(&self) -> Vec<(ObjectId, String)> {
+        self.tagged_objects()
+            .into_iter()
+            .filter_map(|(_, id, object)| match object {
+                Object::Mesh {
+                    kind: ObjectType::Simple(_),
+                    material,
+                    ..
+                } if self.materials.find_id(&material).is_none() => {
+                    Some((id, format!("unknown material `{}`", material)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn get_new_material(
         &self,
         data: &Data,
     ) -> Option<Result<macroquad::prelude::Material, (String, String, ShaderErrors)>> {
         let code = self.generate_shader_code(data)?;
 
+        let unknown_materials = self.unknown_mesh_materials();
+        if !unknown_materials.is_empty() {
+            let mut errors: ShaderErrors = Default::default();
+            let mut error_message = String::new();
+            for (id, message) in unknown_materials {
+                error_message += &message;
+                error_message.push('\n');
+                errors.push_t(id, (1, message));
+            }
+            return Some(Err((code.storage, error_message, errors)));
+        }
+
         use macroquad::prelude::load_material;
         use macroquad::prelude::MaterialParams;
         use std::borrow::Cow;
@@ -1301,7 +1899,9 @@ impl Scene {
     }
 
     pub fn get_start_cam(&self, anim: &RealAnimation, id: RealAnimationId) -> Option<CameraId> {
-        if anim.use_prev_cam {
+        if anim.use_cam_path {
+            self.camera_paths.get_original(anim.cam_path?)?.first_cam()
+        } else if anim.use_prev_cam {
             for (a, b) in self
                 .animations
                 .visible_elements()
@@ -1328,7 +1928,9 @@ impl Scene {
     }
 
     pub fn get_end_cam(&self, anim: &RealAnimation, id: RealAnimationId) -> Option<CameraId> {
-        if anim.use_start_cam_as_end {
+        if anim.use_cam_path {
+            self.camera_paths.get_original(anim.cam_path?)?.last_cam()
+        } else if anim.use_start_cam_as_end {
             self.get_start_cam(anim, id)
         } else if let Some(get_end_cam) = anim.use_any_cam_as_end {
             let any_id = anim.cam_any_end?;
@@ -1343,6 +1945,58 @@ impl Scene {
         }
     }
 
+    /// Saves camera states with their times in seconds as camera path with waypoints on frames of
+    /// `decimate_recording`, and adds animation that moves along it. Returns name of the animation.
+    pub fn add_camera_recording(&mut self, keyframes: &[(f64, CalculatedCam)]) -> Option<String> {
+        let (start, _) = keyframes.first()?;
+        let (end, _) = keyframes.last()?;
+        if keyframes.len() < 2 || end <= start {
+            return None;
+        }
+        let keyframes = decimate_recording(keyframes);
+
+        let waypoints = keyframes
+            .iter()
+            .zip(
+                keyframes
+                    .iter()
+                    .skip(1)
+                    .map(Some)
+                    .chain(std::iter::once(None)),
+            )
+            .map(|((time, cam), next)| CameraWaypoint {
+                cam: Some(self.cameras.insert_inline(Cam::from_calculated(cam))),
+                duration: next.map(|(next_time, _)| next_time - time).unwrap_or(1.),
+            })
+            .collect();
+
+        let name = (1..)
+            .map(|i| format!("recording {}", i))
+            .find(|name| {
+                self.camera_paths.find_id(name).is_none() && self.animations.find_id(name).is_none()
+            })
+            .unwrap();
+        let path = self.camera_paths.insert_named_with_order(
+            name.clone(),
+            CameraPath {
+                waypoints,
+                interpolation: PathInterpolation::Linear,
+                constant_speed: false,
+            },
+        );
+        self.animations.insert_named_with_order(
+            name.clone(),
+            RealAnimation {
+                duration: end - start,
+                animation_stage: self.current_stage,
+                use_cam_path: true,
+                cam_path: Some(path),
+                ..Default::default()
+            },
+        );
+        Some(name)
+    }
+
     pub fn total_animation_duration(&self) -> f64 {
         self.animations
             .visible_elements()
@@ -1441,45 +2095,57 @@ impl Scene {
 
             if !disable_cam_interp || apply_once {
                 let animation = self.animations.get_original(id).unwrap();
-                let cam_start = self.get_start_cam(animation, id);
-                let cam_end = self.get_end_cam(animation, id);
-                if let Some((cam1id, cam2id)) = cam_start.zip(cam_end) {
-                    let cam1 = with_swapped!(x => (self.uniforms, data.formulas_cache);
-                        self.cameras.get_original(cam1id).unwrap().get(&self.matrices, &x).unwrap());
-                    let cam2 = with_swapped!(x => (self.uniforms, data.formulas_cache);
-                        self.cameras.get_original(cam2id).unwrap().get(&self.matrices, &x).unwrap());
-
-                    let t_raw = data.formulas_cache.get_time() % 1.;
-                    let t = if let Some(opt_uid) = animation.cam_easing_uniform {
-                        if let Some(uid) = opt_uid {
-                            if let Some(value) = self.uniforms.get(uid, &data.formulas_cache) {
-                                let mut v: f64 = value.into();
-                                if !v.is_finite() {
-                                    v = 0.0;
-                                }
-                                v.clamp(0.0, 1.0)
-                            } else {
-                                animation.cam_easing.ease(t_raw)
-                            }
-                        } else {
-                            animation.cam_easing.ease(t_raw)
+                let t_raw = data.formulas_cache.get_time() % 1.;
+                let t = if let Some(Some(uid)) = animation.cam_easing_uniform {
+                    if let Some(value) = self.uniforms.get(uid, &data.formulas_cache) {
+                        let mut v: f64 = value.into();
+                        if !v.is_finite() {
+                            v = 0.0;
                         }
+                        v.clamp(0.0, 1.0)
                     } else {
                         animation.cam_easing.ease(t_raw)
-                    };
+                    }
+                } else {
+                    animation.cam_easing.ease(t_raw)
+                };
 
-                    let override_matrix = t_raw < self.prev_t_raw || t_raw == 0.;
-
-                    let cam = CalculatedCam {
-                        look_at: cam1.look_at.lerp(cam2.look_at, t),
-                        alpha: lerp(cam1.alpha..=cam2.alpha, t),
-                        beta: lerp(cam1.beta..=cam2.beta, t),
-                        r: lerp(cam1.r..=cam2.r, t),
-                        in_subspace: cam1.in_subspace,
-                        free_movement: cam1.free_movement,
-                        matrix: cam1.matrix,
-                        override_matrix,
-                    };
+                let cam = if animation.use_cam_path {
+                    animation
+                        .cam_path
+                        .and_then(|path_id| self.camera_paths.get_original(path_id))
+                        .and_then(|path| {
+                            let cams = with_swapped!(x => (self.uniforms, data.formulas_cache);
+                                path.get_cams(&self.cameras, &self.matrices, &x))?;
+                            path.sample(&cams, t)
+                        })
+                } else {
+                    let cam_start = self.get_start_cam(animation, id);
+                    let cam_end = self.get_end_cam(animation, id);
+                    cam_start.zip(cam_end).map(|(cam1id, cam2id)| {
+                        let cam1 = with_swapped!(x => (self.uniforms, data.formulas_cache);
+                            self.cameras.get_original(cam1id).unwrap().get(&self.matrices, &x).unwrap());
+                        let cam2 = with_swapped!(x => (self.uniforms, data.formulas_cache);
+                            self.cameras.get_original(cam2id).unwrap().get(&self.matrices, &x).unwrap());
+                        CalculatedCam {
+                            look_at: cam1.look_at.lerp(cam2.look_at, t),
+                            alpha: lerp(cam1.alpha..=cam2.alpha, t),
+                            beta: lerp(cam1.beta..=cam2.beta, t),
+                            r: lerp(cam1.r..=cam2.r, t),
+                            aperture: lerp(cam1.aperture..=cam2.aperture, t),
+                            focus_distance: lerp(cam1.focus_distance..=cam2.focus_distance, t),
+                            view_angle: lerp(cam1.view_angle..=cam2.view_angle, t),
+                            panini_param: lerp(cam1.panini_param..=cam2.panini_param, t),
+                            ..cam1
+                        }
+                    })
+                };
+
+                if let Some(mut cam) = cam {
+                    // Recorded cameras change matrix when they are teleported
+                    let teleported = self.prev_cam_matrix != Some((cam.matrix, cam.in_subspace));
+                    cam.override_matrix = t_raw < self.prev_t_raw || t_raw == 0. || teleported;
+                    self.prev_cam_matrix = Some((cam.matrix, cam.in_subspace));
 
                     memory
                         .data
@@ -1642,6 +2308,14 @@ impl Scene {
     }
 }
 
+/// Operands of CSG have suffix in tag, visible objects have only position.
+fn is_operand_tag(tag: &str) -> bool {
+    tag.contains('_')
+}
+
+/// Maximum nesting of CSG operands, it prevents infinite recursion when named operands reference each other.
+const MAX_CSG_DEPTH: usize = 8;
+
 const FRAGMENT_SHADER: &str = include_str!("../frag.glsl");
 
 pub const LIBRARY: &str = include_str!("../library.glsl");
@@ -1659,12 +2333,13 @@ uniform mat4 Projection;
 
 uniform vec2 Center;
 uniform vec2 _resolution;
+uniform vec2 _tile_offset; // position of the drawn tile inside the image of size `_resolution`
 
 void main() {
     vec4 res = Projection * Model * vec4(position, 1);
 
     float coef = min(_resolution.x, _resolution.y);
-    uv_screen = (position.xy - _resolution/2.) / coef * 2.;
+    uv_screen = (position.xy + _tile_offset - _resolution/2.) / coef * 2.;
     uv = position.xy;
 
     gl_Position = res;
@@ -1684,12 +2359,13 @@ uniform mat4 Projection;
 
 uniform vec2 Center;
 uniform vec2 _resolution;
+uniform vec2 _tile_offset; // position of the drawn tile inside the image of size `_resolution`
 
 void main() {
     vec4 res = Projection * Model * vec4(position, 1);
 
     float coef = min(_resolution.x, _resolution.y);
-    uv_screen = (position.xy - _resolution/2.) / coef * 2.;
+    uv_screen = (position.xy + _tile_offset - _resolution/2.) / coef * 2.;
     uv = position.xy;
 
     gl_Position = res;
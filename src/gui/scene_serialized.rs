@@ -1,14 +1,21 @@
 use crate::gui::scene::Scene;
+use crate::projection::Projection;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-use super::camera::{Cam as OldCam, CamLookAt, CameraId};
+use super::camera::{
+    default_focus_distance, default_panini_param, default_view_angle, Cam as OldCam, CamLookAt,
+    CameraId,
+};
+use super::camera_path::{
+    CameraPath as OldCameraPath, CameraPathId, CameraWaypoint, PathInterpolation,
+};
 use super::eng_rus::EngRusText;
 use super::glsl::LibraryCode;
 use super::intersection_material::IntersectionMaterial as OldIntersectionMaterial;
 use super::material::Material as OldMaterial;
 use super::matrix::{Matrix as OldMatrix, MatrixId};
-use super::object::{Object as OldObject, ObjectType as OldObjectType};
+use super::object::{Object as OldObject, ObjectId, ObjectType as OldObjectType};
 use super::scene::CamSettings;
 use super::storage2::Storage2;
 use super::storage2::Wrapper;
@@ -47,6 +54,21 @@ enum UniformRef {
     Inline(Box<AnyUniform>),
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct CameraWaypointSer {
+    cam: Option<CamRef>,
+    duration: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct CameraPathSer {
+    waypoints: Vec<CameraWaypointSer>,
+    #[serde(default)]
+    interpolation: PathInterpolation,
+    #[serde(default)]
+    constant_speed: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct VideoSer {
     path: String,
@@ -138,6 +160,7 @@ enum Matrix {
 enum CamLookAtSer {
     MatrixCenter(Option<MatrixRef>),
     Coordinate(glam::DVec3),
+    MatrixFrame(Option<MatrixRef>, glam::DVec3),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -152,6 +175,16 @@ struct Cam {
     free_movement: bool,
     #[serde(default)]
     matrix: glam::DMat4,
+    #[serde(default)]
+    aperture: f64,
+    #[serde(default = "default_focus_distance")]
+    focus_distance: f64,
+    #[serde(default)]
+    projection: Projection,
+    #[serde(default = "default_view_angle")]
+    view_angle: f64,
+    #[serde(default = "default_panini_param")]
+    panini_param: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -182,10 +215,99 @@ enum Object {
         #[serde(default)]
         in_subspace: super::object::SubspaceType,
     },
+    Mesh {
+        path: String,
+        kind: ObjectType,
+        material: String,
+        #[serde(default)]
+        in_subspace: super::object::SubspaceType,
+    },
+    Sdf {
+        kind: ObjectType,
+        sdf_code: super::glsl::SdfCode,
+        max_steps: usize,
+        epsilon: f64,
+        #[serde(default)]
+        in_subspace: super::object::SubspaceType,
+    },
+    Csg {
+        op: super::object::CsgOp,
+        a: Option<ObjectRef>,
+        b: Option<ObjectRef>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ObjectRef {
+    Named(String),
+    Inline(Box<Object>),
 }
 
-// Materials, intersections, library are reused
-type Material = OldMaterial;
+// Materials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Material {
+    Simple {
+        color: [f64; 3],
+        normal_coef: f64,
+        grid: bool,
+        grid_scale: f64,
+        grid_coef: f64,
+        #[serde(default)]
+        grid2: bool,
+        #[serde(default)]
+        grid3: bool,
+    },
+    Reflect {
+        add_to_color: [f64; 3],
+    },
+    Refract {
+        refractive_index: f64,
+        add_to_color: [f64; 3],
+
+        #[serde(default)]
+        abbe_number: Option<f64>,
+
+        #[serde(default)]
+        fresnel: f64,
+    },
+    Complex {
+        code: super::glsl::MaterialCode,
+    },
+    Textured {
+        texture: Option<String>,
+        uv_scale: [f64; 2],
+        uv_offset: [f64; 2],
+        normal_coef: f64,
+    },
+    Checker {
+        color_a: [f64; 3],
+        color_b: [f64; 3],
+        scale: f64,
+        normal_coef: f64,
+    },
+    Stripes {
+        color_a: [f64; 3],
+        color_b: [f64; 3],
+        scale: f64,
+        width: f64,
+        normal_coef: f64,
+    },
+    Noise {
+        color_a: [f64; 3],
+        color_b: [f64; 3],
+        scale: f64,
+        octaves: usize,
+        normal_coef: f64,
+    },
+    Pbr {
+        albedo: [f64; 3],
+        roughness: f64,
+        metallic: f64,
+        emission: [f64; 3],
+    },
+}
+
+// Intersections, library are reused
 type IntersectionMaterial = OldIntersectionMaterial;
 
 // ---------- Current stage helpers ----------
@@ -400,6 +522,285 @@ fn obj_type_from_ser(
 }
 
 // ---------- Camera helpers ----------
+fn obj_id_to_ref(
+    id: Option<ObjectId>,
+    objects: &Storage2<OldObject>,
+    matrices: &Storage2<OldMatrix>,
+    uniforms: &Storage2<OldAnyUniform>,
+    visited_inline: &mut std::collections::BTreeSet<usize>,
+) -> Option<ObjectRef> {
+    let id = id?;
+    match objects.get_name(id) {
+        Some(Some(name)) => Some(ObjectRef::Named(name.to_owned())),
+        Some(None) => {
+            let uid = id.un_wrap().to_string().parse::<usize>().unwrap_or(0);
+            if visited_inline.contains(&uid) {
+                return None;
+            }
+            visited_inline.insert(uid);
+            let val = objects.get_original(id)?.clone();
+            let inner = object_to_ser(&val, objects, matrices, uniforms, visited_inline);
+            visited_inline.remove(&uid);
+            Some(ObjectRef::Inline(Box::new(inner)))
+        }
+        None => None,
+    }
+}
+
+fn object_to_ser(
+    o: &OldObject,
+    objects: &Storage2<OldObject>,
+    matrices: &Storage2<OldMatrix>,
+    uniforms: &Storage2<OldAnyUniform>,
+    visited_inline: &mut std::collections::BTreeSet<usize>,
+) -> Object {
+    match o {
+        OldObject::DebugMatrix(a) => {
+            Object::DebugMatrix(mat_id_to_ref(*a, matrices, visited_inline, uniforms))
+        }
+        OldObject::Flat {
+            kind,
+            is_inside,
+            in_subspace,
+        } => Object::Flat {
+            kind: obj_type_to_ser(kind, matrices, uniforms),
+            is_inside: is_inside.clone(),
+            in_subspace: in_subspace.clone(),
+        },
+        OldObject::Complex {
+            kind,
+            intersect,
+            in_subspace,
+        } => Object::Complex {
+            kind: obj_type_to_ser(kind, matrices, uniforms),
+            intersect: intersect.clone(),
+            in_subspace: in_subspace.clone(),
+        },
+        OldObject::Mesh {
+            path,
+            kind,
+            material,
+            in_subspace,
+        } => Object::Mesh {
+            path: path.clone(),
+            kind: obj_type_to_ser(kind, matrices, uniforms),
+            material: material.clone(),
+            in_subspace: in_subspace.clone(),
+        },
+        OldObject::Sdf {
+            kind,
+            sdf_code,
+            max_steps,
+            epsilon,
+            in_subspace,
+        } => Object::Sdf {
+            kind: obj_type_to_ser(kind, matrices, uniforms),
+            sdf_code: sdf_code.clone(),
+            max_steps: *max_steps,
+            epsilon: *epsilon,
+            in_subspace: in_subspace.clone(),
+        },
+        OldObject::Csg { op, a, b } => Object::Csg {
+            op: *op,
+            a: obj_id_to_ref(*a, objects, matrices, uniforms, visited_inline),
+            b: obj_id_to_ref(*b, objects, matrices, uniforms, visited_inline),
+        },
+    }
+}
+
+fn material_to_ser(m: &OldMaterial, textures: &Storage2<TextureName>) -> Material {
+    use OldMaterial as OM;
+    match m.clone() {
+        OM::Simple {
+            color,
+            normal_coef,
+            grid,
+            grid_scale,
+            grid_coef,
+            grid2,
+            grid3,
+        } => Material::Simple {
+            color,
+            normal_coef,
+            grid,
+            grid_scale,
+            grid_coef,
+            grid2,
+            grid3,
+        },
+        OM::Reflect { add_to_color } => Material::Reflect { add_to_color },
+        OM::Refract {
+            refractive_index,
+            add_to_color,
+            abbe_number,
+            fresnel,
+        } => Material::Refract {
+            refractive_index,
+            add_to_color,
+            abbe_number,
+            fresnel,
+        },
+        OM::Complex { code } => Material::Complex { code },
+        OM::Textured {
+            texture,
+            uv_scale,
+            uv_offset,
+            normal_coef,
+        } => Material::Textured {
+            texture: texture
+                .and_then(|id| textures.get_name(id))
+                .and_then(|x| x)
+                .map(|s| s.to_owned()),
+            uv_scale,
+            uv_offset,
+            normal_coef,
+        },
+        OM::Checker {
+            color_a,
+            color_b,
+            scale,
+            normal_coef,
+        } => Material::Checker {
+            color_a,
+            color_b,
+            scale,
+            normal_coef,
+        },
+        OM::Stripes {
+            color_a,
+            color_b,
+            scale,
+            width,
+            normal_coef,
+        } => Material::Stripes {
+            color_a,
+            color_b,
+            scale,
+            width,
+            normal_coef,
+        },
+        OM::Noise {
+            color_a,
+            color_b,
+            scale,
+            octaves,
+            normal_coef,
+        } => Material::Noise {
+            color_a,
+            color_b,
+            scale,
+            octaves,
+            normal_coef,
+        },
+        OM::Pbr {
+            albedo,
+            roughness,
+            metallic,
+            emission,
+        } => Material::Pbr {
+            albedo,
+            roughness,
+            metallic,
+            emission,
+        },
+    }
+}
+
+fn material_from_ser(m: Material, textures: &Storage2<TextureName>) -> OldMaterial {
+    use OldMaterial as OM;
+    match m {
+        Material::Simple {
+            color,
+            normal_coef,
+            grid,
+            grid_scale,
+            grid_coef,
+            grid2,
+            grid3,
+        } => OM::Simple {
+            color,
+            normal_coef,
+            grid,
+            grid_scale,
+            grid_coef,
+            grid2,
+            grid3,
+        },
+        Material::Reflect { add_to_color } => OM::Reflect { add_to_color },
+        Material::Refract {
+            refractive_index,
+            add_to_color,
+            abbe_number,
+            fresnel,
+        } => OM::Refract {
+            refractive_index,
+            add_to_color,
+            abbe_number,
+            fresnel,
+        },
+        Material::Complex { code } => OM::Complex { code },
+        Material::Textured {
+            texture,
+            uv_scale,
+            uv_offset,
+            normal_coef,
+        } => OM::Textured {
+            texture: texture.and_then(|name| textures.find_id(&name)),
+            uv_scale,
+            uv_offset,
+            normal_coef,
+        },
+        Material::Checker {
+            color_a,
+            color_b,
+            scale,
+            normal_coef,
+        } => OM::Checker {
+            color_a,
+            color_b,
+            scale,
+            normal_coef,
+        },
+        Material::Stripes {
+            color_a,
+            color_b,
+            scale,
+            width,
+            normal_coef,
+        } => OM::Stripes {
+            color_a,
+            color_b,
+            scale,
+            width,
+            normal_coef,
+        },
+        Material::Noise {
+            color_a,
+            color_b,
+            scale,
+            octaves,
+            normal_coef,
+        } => OM::Noise {
+            color_a,
+            color_b,
+            scale,
+            octaves,
+            normal_coef,
+        },
+        Material::Pbr {
+            albedo,
+            roughness,
+            metallic,
+            emission,
+        } => OM::Pbr {
+            albedo,
+            roughness,
+            metallic,
+            emission,
+        },
+    }
+}
+
 fn cam_to_ser(
     c: &OldCam,
     matrices_s: &Storage2<OldMatrix>,
@@ -416,6 +817,13 @@ fn cam_to_ser(
             ))
         }
         CamLookAt::Coordinate(v) => CamLookAtSer::Coordinate(v),
+        CamLookAt::MatrixFrame(mid, offset) => {
+            let mut visited_inline = Default::default();
+            CamLookAtSer::MatrixFrame(
+                mat_id_to_ref(mid, matrices_s, &mut visited_inline, uniforms_s),
+                offset,
+            )
+        }
     };
     Cam {
         look_at,
@@ -425,6 +833,11 @@ fn cam_to_ser(
         in_subspace: c.in_subspace,
         free_movement: c.free_movement,
         matrix: c.matrix,
+        aperture: c.aperture,
+        focus_distance: c.focus_distance,
+        projection: c.projection,
+        view_angle: c.view_angle,
+        panini_param: c.panini_param,
     }
 }
 
@@ -485,6 +898,10 @@ fn cam_from_ser(
         CamLookAtSer::MatrixCenter(opt) => CamLookAt::MatrixCenter(
             opt.and_then(|r| matrix_ref_to_id(r, matrices, uniforms, mat_name_to_id)),
         ),
+        CamLookAtSer::MatrixFrame(opt, offset) => CamLookAt::MatrixFrame(
+            opt.and_then(|r| matrix_ref_to_id(r, matrices, uniforms, mat_name_to_id)),
+            offset,
+        ),
     };
     c.alpha = data.alpha;
     c.beta = data.beta;
@@ -492,6 +909,11 @@ fn cam_from_ser(
     c.in_subspace = data.in_subspace;
     c.free_movement = data.free_movement;
     c.matrix = data.matrix;
+    c.aperture = data.aperture;
+    c.focus_distance = data.focus_distance;
+    c.projection = data.projection;
+    c.view_angle = data.view_angle;
+    c.panini_param = data.panini_param;
     c
 }
 
@@ -578,6 +1000,10 @@ struct RealAnimationSer {
     cam_easing: super::easing::Easing,
     #[serde(default)]
     cam_easing_uniform: Option<UniformRef>,
+    #[serde(default)]
+    use_cam_path: bool,
+    #[serde(default)]
+    cam_path: Option<String>,
 }
 
 impl Default for RealAnimationSer {
@@ -597,6 +1023,8 @@ impl Default for RealAnimationSer {
             cam_any_end: None,
             cam_easing: super::easing::Easing::Linear,
             cam_easing_uniform: None,
+            use_cam_path: false,
+            cam_path: None,
         }
     }
 }
@@ -616,6 +1044,8 @@ pub struct SerializedScene {
     matrices: SerStorage<Matrix>,
     objects: SerStorage<Object>,
     cameras: SerStorage<Cam>,
+    #[serde(default)]
+    camera_paths: SerStorage<CameraPathSer>,
     textures: SerStorage<TextureName>,
     materials: SerStorage<Material>,
     #[serde(default)]
@@ -643,6 +1073,8 @@ pub struct SerializedScene {
     use_time: bool,
     #[serde(default)]
     skybox: Option<String>,
+    #[serde(default)]
+    fog: super::fog::SubspacesFog,
 }
 
 // Helpers: name resolvers for storages
@@ -695,36 +1127,9 @@ pub(crate) fn serialize_scene_new_format(scene: &super::scene::Scene) -> Seriali
         objects_s
             .visible_elements()
             .map(|(id, name)| {
+                let mut visited_inline = Default::default();
                 let o = objects_s.get_original(id).unwrap();
-                let data = match o {
-                    OldObject::DebugMatrix(a) => {
-                        let mut visited_inline = Default::default();
-                        Object::DebugMatrix(mat_id_to_ref(
-                            *a,
-                            matrices_s,
-                            &mut visited_inline,
-                            uniforms_s,
-                        ))
-                    }
-                    OldObject::Flat {
-                        kind,
-                        is_inside,
-                        in_subspace,
-                    } => Object::Flat {
-                        kind: obj_type_to_ser(kind, matrices_s, uniforms_s),
-                        is_inside: is_inside.clone(),
-                        in_subspace: in_subspace.clone(),
-                    },
-                    OldObject::Complex {
-                        kind,
-                        intersect,
-                        in_subspace,
-                    } => Object::Complex {
-                        kind: obj_type_to_ser(kind, matrices_s, uniforms_s),
-                        intersect: intersect.clone(),
-                        in_subspace: in_subspace.clone(),
-                    },
-                };
+                let data = object_to_ser(o, objects_s, matrices_s, uniforms_s, &mut visited_inline);
                 Named {
                     name: name.to_owned(),
                     data,
@@ -748,6 +1153,33 @@ pub(crate) fn serialize_scene_new_format(scene: &super::scene::Scene) -> Seriali
             .collect(),
     );
 
+    // camera paths
+    let camera_paths = SerStorage(
+        scene
+            .camera_paths
+            .visible_elements()
+            .map(|(id, name)| {
+                let p = scene.camera_paths.get_original(id).unwrap();
+                let waypoints = p
+                    .waypoints
+                    .iter()
+                    .map(|w| CameraWaypointSer {
+                        cam: cam_id_to_ref(w.cam, cameras_s, matrices_s, uniforms_s),
+                        duration: w.duration,
+                    })
+                    .collect();
+                Named {
+                    name: name.to_owned(),
+                    data: CameraPathSer {
+                        waypoints,
+                        interpolation: p.interpolation,
+                        constant_speed: p.constant_speed,
+                    },
+                }
+            })
+            .collect(),
+    );
+
     // textures/materials/intersections/library
     let textures = SerStorage(
         textures_s
@@ -763,7 +1195,7 @@ pub(crate) fn serialize_scene_new_format(scene: &super::scene::Scene) -> Seriali
             .visible_elements()
             .map(|(id, name)| Named {
                 name: name.to_owned(),
-                data: materials_s.get_original(id).unwrap().clone(),
+                data: material_to_ser(materials_s.get_original(id).unwrap(), textures_s),
             })
             .collect(),
     );
@@ -813,6 +1245,7 @@ pub(crate) fn serialize_scene_new_format(scene: &super::scene::Scene) -> Seriali
         matrices,
         objects,
         cameras,
+        camera_paths,
         textures,
         materials,
         intersection_materials,
@@ -827,6 +1260,7 @@ pub(crate) fn serialize_scene_new_format(scene: &super::scene::Scene) -> Seriali
         animations: SerStorage(Vec::new()),
         use_time: *use_time,
         skybox: skybox.clone(),
+        fog: scene.fog.clone(),
     };
 
     // Append the rest of data formerly added in serialize_all
@@ -1091,6 +1525,12 @@ pub(crate) fn serialize_scene_new_format(scene: &super::scene::Scene) -> Seriali
                     .map(|s| s.to_owned()),
                 cam_easing: a.cam_easing.clone(),
                 cam_easing_uniform,
+                use_cam_path: a.use_cam_path,
+                cam_path: a
+                    .cam_path
+                    .and_then(|id| scene.camera_paths.get_name(id))
+                    .and_then(|x| x)
+                    .map(|s| s.to_owned()),
             },
         });
     }
@@ -1105,6 +1545,7 @@ pub(crate) fn deserialize_scene_new_format(ser: SerializedScene, scene: &mut sup
     scene.cam = ser.cam.clone();
     scene.use_time = ser.use_time;
     scene.skybox = ser.skybox.clone();
+    scene.fog = ser.fog.clone();
 
     // textures
     for Named { name, data } in ser.textures.0.clone().into_iter() {
@@ -1135,42 +1576,25 @@ pub(crate) fn deserialize_scene_new_format(ser: SerializedScene, scene: &mut sup
         scene.matrices.set(id, value);
     }
 
-    // objects
+    // objects: two-pass, because CSG references other objects
+    let mut obj_name_to_id = BTreeMap::new();
+    for Named { name, .. } in ser.objects.0.iter() {
+        let id = scene
+            .objects
+            .insert_named_with_order(name.clone(), Default::default());
+        obj_name_to_id.insert(name.clone(), id);
+    }
     for Named { name, data } in ser.objects.0.clone().into_iter() {
-        let old = match data {
-            Object::DebugMatrix(a) => OldObject::DebugMatrix(a.and_then(|r| {
-                matrix_ref_to_id(r, &mut scene.matrices, &mut scene.uniforms, &mat_name_to_id)
-            })),
-            Object::Flat {
-                kind,
-                is_inside,
-                in_subspace,
-            } => OldObject::Flat {
-                kind: obj_type_from_ser(
-                    kind,
-                    &mut scene.matrices,
-                    &mut scene.uniforms,
-                    &mat_name_to_id,
-                ),
-                is_inside,
-                in_subspace,
-            },
-            Object::Complex {
-                kind,
-                intersect,
-                in_subspace,
-            } => OldObject::Complex {
-                kind: obj_type_from_ser(
-                    kind,
-                    &mut scene.matrices,
-                    &mut scene.uniforms,
-                    &mat_name_to_id,
-                ),
-                intersect,
-                in_subspace,
-            },
-        };
-        scene.objects.insert_named_with_order(name, old);
+        let id = *obj_name_to_id.get(&name).unwrap();
+        let value = object_from_ser(
+            data,
+            &mut scene.objects,
+            &mut scene.matrices,
+            &mut scene.uniforms,
+            &mat_name_to_id,
+            &obj_name_to_id,
+        );
+        scene.objects.set(id, value);
     }
 
     // cameras
@@ -1186,7 +1610,8 @@ pub(crate) fn deserialize_scene_new_format(ser: SerializedScene, scene: &mut sup
 
     // materials/intersections/library
     for Named { name, data } in ser.materials.0.clone().into_iter() {
-        scene.materials.insert_named_with_order(name, data);
+        let material = material_from_ser(data, &scene.textures);
+        scene.materials.insert_named_with_order(name, material);
     }
     for Named { name, data } in ser.intersection_materials.0.clone().into_iter() {
         scene
@@ -1226,6 +1651,37 @@ pub(crate) fn deserialize_scene_new_format(ser: SerializedScene, scene: &mut sup
         .map(|(id, name)| (name.to_owned(), id))
         .collect();
 
+    // camera paths
+    let mut path_by_name: BTreeMap<String, CameraPathId> = BTreeMap::new();
+    for Named { name, data } in ser.camera_paths.0.clone().into_iter() {
+        let p = OldCameraPath {
+            waypoints: data
+                .waypoints
+                .into_iter()
+                .map(|w| CameraWaypoint {
+                    cam: match w.cam {
+                        None => None,
+                        Some(CamRef::Named(n)) => cam_by_name.get(&n).copied(),
+                        Some(CamRef::Inline(boxed)) => {
+                            let c = cam_from_ser(
+                                &boxed,
+                                &mut scene.matrices,
+                                &mut scene.uniforms,
+                                &mat_by_name,
+                            );
+                            Some(scene.cameras.insert_inline(c))
+                        }
+                    },
+                    duration: w.duration,
+                })
+                .collect(),
+            interpolation: data.interpolation,
+            constant_speed: data.constant_speed,
+        };
+        let id = scene.camera_paths.insert_named_with_order(name.clone(), p);
+        path_by_name.insert(name, id);
+    }
+
     // Animations filters
     for (name, v) in ser.animations_filters.uniforms.into_iter() {
         if let Some(id) = uni_by_name.get(&name).copied() {
@@ -1469,6 +1925,8 @@ pub(crate) fn deserialize_scene_new_format(ser: SerializedScene, scene: &mut sup
             .cam_easing_uniform
             .and_then(|u| uniform_ref_to_id(u, &mut scene.uniforms))
             .map(|id| Some(id));
+        a.use_cam_path = data.use_cam_path;
+        a.cam_path = data.cam_path.and_then(|n| path_by_name.get(&n).copied());
         scene.animations.set(id, a);
     }
 
@@ -1493,6 +1951,110 @@ fn uniform_ref_to_id(u: UniformRef, uniforms: &mut Storage2<OldAnyUniform>) -> O
     }
 }
 
+fn object_ref_to_id(
+    r: ObjectRef,
+    objects: &mut Storage2<OldObject>,
+    matrices: &mut Storage2<OldMatrix>,
+    uniforms: &mut Storage2<OldAnyUniform>,
+    mat_name_to_id: &BTreeMap<String, MatrixId>,
+    obj_name_to_id: &BTreeMap<String, ObjectId>,
+) -> Option<ObjectId> {
+    match r {
+        ObjectRef::Named(n) => obj_name_to_id.get(&n).copied(),
+        ObjectRef::Inline(bx) => {
+            let val = object_from_ser(
+                *bx,
+                objects,
+                matrices,
+                uniforms,
+                mat_name_to_id,
+                obj_name_to_id,
+            );
+            Some(objects.insert_inline(val))
+        }
+    }
+}
+
+fn object_from_ser(
+    data: Object,
+    objects: &mut Storage2<OldObject>,
+    matrices: &mut Storage2<OldMatrix>,
+    uniforms: &mut Storage2<OldAnyUniform>,
+    mat_name_to_id: &BTreeMap<String, MatrixId>,
+    obj_name_to_id: &BTreeMap<String, ObjectId>,
+) -> OldObject {
+    match data {
+        Object::DebugMatrix(a) => OldObject::DebugMatrix(
+            a.and_then(|r| matrix_ref_to_id(r, matrices, uniforms, mat_name_to_id)),
+        ),
+        Object::Flat {
+            kind,
+            is_inside,
+            in_subspace,
+        } => OldObject::Flat {
+            kind: obj_type_from_ser(kind, matrices, uniforms, mat_name_to_id),
+            is_inside,
+            in_subspace,
+        },
+        Object::Complex {
+            kind,
+            intersect,
+            in_subspace,
+        } => OldObject::Complex {
+            kind: obj_type_from_ser(kind, matrices, uniforms, mat_name_to_id),
+            intersect,
+            in_subspace,
+        },
+        Object::Mesh {
+            path,
+            kind,
+            material,
+            in_subspace,
+        } => OldObject::Mesh {
+            path,
+            kind: obj_type_from_ser(kind, matrices, uniforms, mat_name_to_id),
+            material,
+            in_subspace,
+        },
+        Object::Sdf {
+            kind,
+            sdf_code,
+            max_steps,
+            epsilon,
+            in_subspace,
+        } => OldObject::Sdf {
+            kind: obj_type_from_ser(kind, matrices, uniforms, mat_name_to_id),
+            sdf_code,
+            max_steps,
+            epsilon,
+            in_subspace,
+        },
+        Object::Csg { op, a, b } => OldObject::Csg {
+            op,
+            a: a.and_then(|x| {
+                object_ref_to_id(
+                    x,
+                    objects,
+                    matrices,
+                    uniforms,
+                    mat_name_to_id,
+                    obj_name_to_id,
+                )
+            }),
+            b: b.and_then(|x| {
+                object_ref_to_id(
+                    x,
+                    objects,
+                    matrices,
+                    uniforms,
+                    mat_name_to_id,
+                    obj_name_to_id,
+                )
+            }),
+        },
+    }
+}
+
 fn matrix_ref_to_id(
     r: MatrixRef,
     mats: &mut Storage2<OldMatrix>,
@@ -0,0 +1,363 @@
+use crate::gui::mesh::Mesh;
+use crate::gui::object::SubspaceType;
+use glam::{DMat4, DVec3, DVec4};
+use std::collections::BTreeMap;
+
+/// Same as `max_camera_teleports` in `teleport_external_ray` in shader.
+const MAX_CAMERA_TELEPORTS: usize = 10;
+
+/// Radius of axes in `debug_intersect`.
+const DEBUG_AXIS_RADIUS: f64 = 0.03;
+
+/// Geometry that is known without shader code, so camera teleportation can be traced on CPU.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuShape {
+    /// Mesh of object with this tag
+    Mesh(String),
+
+    /// Axes of `DebugMatrix`, same as `debug_intersect` in shader
+    DebugAxes,
+
+    /// Disk on plane `z = 0` of flat object
+    Disk { radius: f64 },
+
+    /// Rectangle `|x| < half_width`, `|y| < half_height` on plane `z = 0` of flat object
+    Rectangle { half_width: f64, half_height: f64 },
+
+    /// Plane `z = 0` of flat object with arbitrary `is_inside` code, segment that crosses it is
+    /// traced on GPU
+    UnknownPlane,
+
+    /// Object defined by shader code, every segment is traced on GPU
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct CpuObject {
+    pub shape: CpuShape,
+    pub matrix: DMat4, // from object coordinates to world
+    pub in_subspace: SubspaceType,
+
+    /// Teleport matrix of portal side, `None` for solid objects
+    pub teleport: Option<DMat4>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuTeleport {
+    /// Product of teleport matrices of all crossed portals, `None` if no portal is crossed
+    pub teleport: Option<DMat4>,
+
+    /// Part of the segment from 0 to 1 before the first solid object, `None` if it is not
+    /// encountered
+    pub encounter_at: Option<f64>,
+}
+
+fn point(matrix: DMat4, p: DVec3) -> DVec3 {
+    (matrix * DVec4::from((p, 1.))).truncate()
+}
+
+fn vector(matrix: DMat4, v: DVec3) -> DVec3 {
+    (matrix * DVec4::from((v, 0.))).truncate()
+}
+
+/// Same as `cap` in `library.glsl`, `d` is normalized.
+fn capsule_intersect(o: DVec3, d: DVec3, pa: DVec3, pb: DVec3, radius: f64) -> Option<f64> {
+    let ba = pb - pa;
+    let oa = o - pa;
+    let baba = ba.dot(ba);
+    let bard = ba.dot(d);
+    let baoa = ba.dot(oa);
+    let rdoa = d.dot(oa);
+    let oaoa = oa.dot(oa);
+    let a = baba - bard * bard;
+    let b = baba * rdoa - baoa * bard;
+    let c = baba * oaoa - baoa * baoa - radius * radius * baba;
+    let h = b * b - a * c;
+    if h < 0. {
+        return None;
+    }
+    let t = (-b - h.sqrt()) / a;
+    let y = baoa + t * bard;
+    if y > 0. && y < baba {
+        return Some(t);
+    }
+    let oc = if y <= 0. { oa } else { o - pb };
+    let b = d.dot(oc);
+    let c = oc.dot(oc) - radius * radius;
+    let h = b * b - c;
+    if h > 0. {
+        Some(-b - h.sqrt())
+    } else {
+        None
+    }
+}
+
+impl CpuObject {
+    fn is_visible(&self, in_subspace: bool) -> bool {
+        match self.in_subspace {
+            SubspaceType::Normal => !in_subspace,
+            SubspaceType::Subspace => in_subspace,
+            SubspaceType::Both => true,
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        !matches!(self.shape, CpuShape::UnknownPlane | CpuShape::Unknown)
+    }
+
+    /// Distance along normalized `d` to the nearest hit with positive distance. Unknown objects
+    /// are hit where they can be.
+    fn intersect(&self, meshes: &BTreeMap<String, Mesh>, o: DVec3, d: DVec3) -> Option<f64> {
+        let inverse = self.matrix.inverse();
+        let local_o = point(inverse, o);
+        let local_d = vector(inverse, d);
+        let len = local_d.length();
+        let local_d = local_d / len;
+        // Same as `plane_intersect_normalized`
+        let plane = || {
+            let t = -local_o.z / local_d.z;
+            (t > 0.).then(|| (t, local_o + local_d * t))
+        };
+        let t = match &self.shape {
+            CpuShape::Mesh(tag) => meshes
+                .get(tag)?
+                .intersect(local_o.as_f32(), local_d.as_f32())
+                .map(f64::from),
+            CpuShape::DebugAxes => [DVec3::X, DVec3::Y, DVec3::Z]
+                .iter()
+                .filter_map(|axis| {
+                    capsule_intersect(local_o, local_d, DVec3::ZERO, *axis, DEBUG_AXIS_RADIUS)
+                })
+                .filter(|t| *t > 0.)
+                .min_by(|a, b| a.total_cmp(b)),
+            CpuShape::Disk { radius } => plane()
+                .filter(|(_, p)| p.x * p.x + p.y * p.y < radius * radius)
+                .map(|(t, _)| t),
+            CpuShape::Rectangle {
+                half_width,
+                half_height,
+            } => plane()
+                .filter(|(_, p)| p.x.abs() < *half_width && p.y.abs() < *half_height)
+                .map(|(t, _)| t),
+            CpuShape::UnknownPlane => plane().map(|(t, _)| t),
+            CpuShape::Unknown => Some(0.),
+        }?;
+        Some(t / len)
+    }
+}
+
+/// Same as `teleport_external_ray` in shader: moves from `a` to `b` through portals, and stops at
+/// the first solid object. Teleport matrices are exact, unlike finite differences of GPU results.
+/// `None` if the segment can reach object that is known only to shader.
+pub fn teleport_segment(
+    objects: &[CpuObject],
+    meshes: &BTreeMap<String, Mesh>,
+    a: DVec3,
+    b: DVec3,
+    in_subspace: bool,
+    offset_after_material: f64,
+) -> Option<CpuTeleport> {
+    let mut result = CpuTeleport {
+        teleport: None,
+        encounter_at: None,
+    };
+    let len = (b - a).length();
+    if len == 0. {
+        return Some(result);
+    }
+
+    let mut o = a;
+    let mut d = (b - a) / len;
+    let mut tmul = 1. / len;
+    let mut all_t = 0.;
+    for _ in 0..MAX_CAMERA_TELEPORTS {
+        let nearest = objects
+            .iter()
+            .filter(|object| object.is_visible(in_subspace))
+            .filter_map(|object| Some((object.intersect(meshes, o, d)?, object)))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+        let (t, object) = match nearest {
+            Some(nearest) if nearest.0 * tmul + all_t < 1. => nearest,
+            _ => break,
+        };
+        if !object.is_known() {
+            return None;
+        }
+
+        o += d * t;
+        all_t += t * tmul;
+        let teleport = match object.teleport {
+            Some(teleport) => teleport,
+            None => {
+                result.encounter_at = Some(all_t);
+                break;
+            }
+        };
+
+        // Same as `material_teleport`
+        o = point(teleport, o);
+        d = vector(teleport, d);
+        o += d * offset_after_material;
+        let len = d.length();
+        d /= len;
+        tmul /= len;
+        result.teleport = Some(teleport * result.teleport.unwrap_or(DMat4::IDENTITY));
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::mesh::parse_obj;
+
+    #[test]
+    fn mesh_portal() {
+        let quad = "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3 4\n";
+        let mut meshes = BTreeMap::new();
+        meshes.insert(
+            "0".to_owned(),
+            Mesh::from_triangles(parse_obj(quad).unwrap()).unwrap(),
+        );
+
+        let first = DMat4::from_translation(DVec3::new(0., 0., 1.));
+        let second = DMat4::from_translation(DVec3::new(10., 0., 0.));
+        let portal = |matrix: DMat4, teleport: DMat4| CpuObject {
+            shape: CpuShape::Mesh("0".to_owned()),
+            matrix,
+            in_subspace: SubspaceType::Normal,
+            teleport: Some(teleport),
+        };
+        let objects = [
+            portal(first, second * first.inverse()),
+            portal(second, first * second.inverse()),
+        ];
+
+        let result = teleport_segment(
+            &objects,
+            &meshes,
+            DVec3::new(0., 0., 0.),
+            DVec3::new(0., 0., 2.),
+            false,
+            1e-4,
+        )
+        .unwrap();
+        assert_eq!(result.teleport, Some(second * first.inverse()));
+        assert_eq!(result.encounter_at, None);
+
+        // Portal is not reached
+        let result = teleport_segment(
+            &objects,
+            &meshes,
+            DVec3::new(0., 0., 0.),
+            DVec3::new(0., 0., 0.5),
+            false,
+            1e-4,
+        )
+        .unwrap();
+        assert_eq!(result.teleport, None);
+
+        // Portal is only in normal space
+        let result = teleport_segment(
+            &objects,
+            &meshes,
+            DVec3::new(0., 0., 0.),
+            DVec3::new(0., 0., 2.),
+            true,
+            1e-4,
+        )
+        .unwrap();
+        assert_eq!(result.teleport, None);
+    }
+
+    #[test]
+    fn debug_axes_stop_camera() {
+        let objects = [CpuObject {
+            shape: CpuShape::DebugAxes,
+            matrix: DMat4::IDENTITY,
+            in_subspace: SubspaceType::Both,
+            teleport: None,
+        }];
+        let result = teleport_segment(
+            &objects,
+            &BTreeMap::new(),
+            DVec3::new(0.5, 0., -1.),
+            DVec3::new(0.5, 0., 1.),
+            false,
+            1e-4,
+        )
+        .unwrap();
+        // X axis is hit at z = -radius, segment has length 2
+        let at = result.encounter_at.unwrap();
+        assert!((at - (1. - DEBUG_AXIS_RADIUS) / 2.).abs() < 1e-9);
+        assert_eq!(result.teleport, None);
+    }
+
+    #[test]
+    fn flat_portal() {
+        let first = DMat4::from_translation(DVec3::new(0., 0., 1.));
+        let second = DMat4::from_translation(DVec3::new(10., 0., 0.));
+        let portal = |matrix: DMat4, teleport: DMat4| CpuObject {
+            shape: CpuShape::Rectangle {
+                half_width: 1.,
+                half_height: 2.,
+            },
+            matrix,
+            in_subspace: SubspaceType::Normal,
+            teleport: Some(teleport),
+        };
+        let wall = CpuObject {
+            shape: CpuShape::Disk { radius: 1. },
+            matrix: DMat4::from_translation(DVec3::new(0., 0., 3.)),
+            in_subspace: SubspaceType::Both,
+            teleport: None,
+        };
+        let mut objects = vec![
+            portal(first, second * first.inverse()),
+            portal(second, first * second.inverse()),
+            wall,
+        ];
+        let segment = |objects: &[CpuObject], x: f64| {
+            teleport_segment(
+                objects,
+                &BTreeMap::new(),
+                DVec3::new(x, 1.5, 0.),
+                DVec3::new(x, 1.5, 4.),
+                false,
+                1e-4,
+            )
+        };
+
+        let result = segment(&objects, 0.).unwrap();
+        assert_eq!(result.teleport, Some(second * first.inverse()));
+        assert_eq!(result.encounter_at, None);
+
+        // Outside of the rectangle, then the disk is hit at z = 3
+        let result = segment(&objects, 1.5).unwrap();
+        assert_eq!(result.teleport, None);
+        assert_eq!(result.encounter_at, None);
+        let result = segment(&objects, 0.5).unwrap();
+        assert_eq!(result.teleport, Some(second * first.inverse()));
+        let result = teleport_segment(
+            &objects,
+            &BTreeMap::new(),
+            DVec3::new(0., 0.5, 2.),
+            DVec3::new(0., 0.5, 4.),
+            false,
+            1e-4,
+        )
+        .unwrap();
+        assert_eq!(result.encounter_at, Some(0.5));
+
+        // Plane with shader code matters only when the segment crosses it
+        objects.push(CpuObject {
+            shape: CpuShape::UnknownPlane,
+            matrix: DMat4::from_translation(DVec3::new(0., 0., -1.)),
+            in_subspace: SubspaceType::Normal,
+            teleport: None,
+        });
+        assert!(segment(&objects, 0.).is_some());
+        objects[3].matrix = DMat4::from_translation(DVec3::new(0., 0., 0.5));
+        assert!(segment(&objects, 0.).is_none());
+    }
+}
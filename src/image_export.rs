@@ -0,0 +1,470 @@
+use macroquad::texture::Image;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// File format of rendered frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum FrameFormat {
+    /// 8-bit PNG
+    #[default]
+    Png,
+
+    /// 16-bit PNG, without banding in dark gradients. Every frame is rendered twice
+    Png16,
+
+    /// OpenEXR with linear colors. Rendered to half float target on desktop OpenGL 3+, without
+    /// upper limit. Otherwise falls back to the 8-bit target rendered twice like `Png16`, then
+    /// colors are from 0 to `--hdr-max` and brighter ones are clipped
+    Exr,
+}
+
+impl FrameFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Png | FrameFormat::Png16 => "png",
+            FrameFormat::Exr => "exr",
+        }
+    }
+
+    /// Needs more than 8 bits per channel: float render target, or 8-bit one read twice with high
+    /// and low bytes of 16-bit value.
+    pub fn is_high_precision(self) -> bool {
+        self != FrameFormat::Png
+    }
+}
+
+/// Raw per-pixel data for compositing, written as separate EXR images near the frame. Values are
+/// taken at the first surface that is not a portal, so the pass shows what is seen through portals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum AuxPass {
+    /// Distance along the ray through all portals, 0 if nothing is hit
+    Depth,
+
+    /// Surface normal in the space where it's hit
+    Normal,
+
+    /// 1-based index of visible object, then of intersection material; 0 if nothing is hit
+    #[cfg_attr(not(target_arch = "wasm32"), value(name = "object_id"))]
+    ObjectId,
+
+    /// 1-based index of visible material, 0 for system and custom materials
+    #[cfg_attr(not(target_arch = "wasm32"), value(name = "material_id"))]
+    MaterialId,
+
+    /// How many portals the ray went through before hitting something
+    #[cfg_attr(not(target_arch = "wasm32"), value(name = "portal_hops"))]
+    PortalHops,
+}
+
+impl AuxPass {
+    pub fn name(self) -> &'static str {
+        match self {
+            AuxPass::Depth => "depth",
+            AuxPass::Normal => "normal",
+            AuxPass::ObjectId => "object_id",
+            AuxPass::MaterialId => "material_id",
+            AuxPass::PortalHops => "portal_hops",
+        }
+    }
+
+    /// Value of `_aux_pass` in shader.
+    pub fn shader_index(self) -> i32 {
+        match self {
+            AuxPass::Depth => 1,
+            AuxPass::Normal => 2,
+            AuxPass::ObjectId => 3,
+            AuxPass::MaterialId => 4,
+            AuxPass::PortalHops => 5,
+        }
+    }
+
+    /// Maximum value that is read back from the render target. Ids up to this value are decoded
+    /// exactly from 16 bits.
+    pub fn output_scale(self, depth_max: f32) -> f32 {
+        match self {
+            AuxPass::Depth => depth_max,
+            AuxPass::Normal => 1.0,
+            AuxPass::ObjectId | AuxPass::MaterialId | AuxPass::PortalHops => 4096.0,
+        }
+    }
+
+    /// Shader output is non-negative, so normals are packed into `0..1`. Ids are rounded to remove
+    /// the encoding error.
+    pub fn decode(self, image: &mut LinearImage) {
+        for px in &mut image.pixels {
+            match self {
+                AuxPass::Depth => {}
+                AuxPass::Normal => *px = px.map(|x| x * 2.0 - 1.0),
+                AuxPass::ObjectId | AuxPass::MaterialId | AuxPass::PortalHops => {
+                    *px = px.map(f32::round)
+                }
+            }
+        }
+    }
+}
+
+/// Linear colors, shader output before `sqrt`. Rows are in texture order: from bottom to top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearImage {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl LinearImage {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; width as usize * height as usize],
+        }
+    }
+
+    pub fn from_rgba8(image: &Image) -> Self {
+        let pixels = image
+            .bytes
+            .chunks_exact(4)
+            .map(|px| [0, 1, 2].map(|i| (px[i] as f32 / 255.0).powi(2)))
+            .collect();
+        Self {
+            width: image.width,
+            height: image.height,
+            pixels,
+        }
+    }
+
+    /// `bytes` are RGBA 32-bit floats of linear color, rendered with `_output_mode` 3.
+    pub fn from_rgba32f(width: u16, height: u16, bytes: &[u8]) -> Self {
+        let pixels = bytes
+            .chunks_exact(16)
+            .map(|px| {
+                [0, 1, 2].map(|i| f32::from_ne_bytes(px[i * 4..i * 4 + 4].try_into().unwrap()))
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// `high` and `low` are rendered with `_output_mode` 1 and 2, and `_output_scale` equal to
+    /// `scale`.
+    pub fn from_rgba8_passes(high: &Image, low: &Image, scale: f32) -> Self {
+        let pixels = high
+            .bytes
+            .chunks_exact(4)
+            .zip(low.bytes.chunks_exact(4))
+            .map(|(high, low)| {
+                [0, 1, 2].map(|i| {
+                    let encoded = (high[i] as f32 + low[i] as f32 / 255.0) / 255.0;
+                    encoded.powi(2) * scale
+                })
+            })
+            .collect();
+        Self {
+            width: high.width,
+            height: high.height,
+            pixels,
+        }
+    }
+
+    pub fn to_rgba8(&self) -> Image {
+        let bytes = self
+            .pixels
+            .iter()
+            .flat_map(|px| {
+                let [r, g, b] = px.map(|x| (x.max(0.0).sqrt().min(1.0) * 255.0).round() as u8);
+                [r, g, b, 255]
+            })
+            .collect();
+        Image {
+            bytes,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    fn to_rgb16(&self) -> Vec<u16> {
+        self.pixels
+            .iter()
+            .flat_map(|px| px.map(|x| (x.max(0.0).sqrt().min(1.0) * 65535.0).round() as u16))
+            .collect()
+    }
+
+    /// Copies `tile` so its first pixel lands at column `x` and row `row` of this image.
+    pub fn paste(&mut self, tile: &LinearImage, x: u16, row: u16) {
+        let (width, tile_width) = (self.width as usize, tile.width as usize);
+        for (i, tile_row) in tile.pixels.chunks_exact(tile_width).enumerate() {
+            let start = (row as usize + i) * width + x as usize;
+            self.pixels[start..start + tile_width].copy_from_slice(tile_row);
+        }
+    }
+
+    fn rows_from_top(&self) -> impl Iterator<Item = &[[f32; 3]]> {
+        self.pixels.chunks_exact(self.width as usize).rev()
+    }
+}
+
+/// Offsets of tiles along one axis of the image. The last tile is moved back to fit inside the
+/// image, so all tiles have the size of render target and overlap instead of being cut.
+pub fn tile_offsets(size: u32, tile: u32) -> Vec<u32> {
+    if size <= tile {
+        return vec![0];
+    }
+    let mut result = (0..size - tile).step_by(tile as usize).collect::<Vec<_>>();
+    result.push(size - tile);
+    result
+}
+
+fn write_error(path: &Path, err: &dyn std::fmt::Display) -> String {
+    format!("Failed to write `{}`: {err}", path.display())
+}
+
+fn create_file(path: &Path) -> Result<std::io::BufWriter<std::fs::File>, String> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent).map_err(|err| write_error(path, &err))?;
+        }
+    }
+    let file = std::fs::File::create(path).map_err(|err| write_error(path, &err))?;
+    Ok(std::io::BufWriter::new(file))
+}
+
+fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    depth: png::BitDepth,
+    data: &[u8],
+) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(create_file(path)?, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|err| write_error(path, &err))?;
+    writer
+        .write_image_data(data)
+        .map_err(|err| write_error(path, &err))?;
+    writer.finish().map_err(|err| write_error(path, &err))
+}
+
+/// Same as `Image::export_png` from macroquad, but returns error instead of panic, and creates
+/// parent directories. Render can run for hours, so one failed write must not kill it silently.
+pub fn save_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> Result<(), String> {
+    write_png(
+        path,
+        width,
+        height,
+        png::ColorType::Rgba,
+        png::BitDepth::Eight,
+        rgba,
+    )
+}
+
+/// Texture data is stored bottom-up, so image is flipped like `Image::export_png` does.
+pub fn save_image_png(path: &Path, image: &Image) -> Result<(), String> {
+    let row = image.width as usize * 4;
+    let flipped = image
+        .bytes
+        .chunks_exact(row)
+        .rev()
+        .flatten()
+        .copied()
+        .collect::<Vec<u8>>();
+    save_png(path, image.width as u32, image.height as u32, &flipped)
+}
+
+pub fn save_png16(path: &Path, image: &LinearImage) -> Result<(), String> {
+    let row = image.width as usize * 3;
+    let rgb = image.to_rgb16();
+    // PNG stores 16-bit samples in big endian
+    let bytes = rgb
+        .chunks_exact(row)
+        .rev()
+        .flatten()
+        .flat_map(|x| x.to_be_bytes())
+        .collect::<Vec<u8>>();
+    write_png(
+        path,
+        image.width as u32,
+        image.height as u32,
+        png::ColorType::Rgb,
+        png::BitDepth::Sixteen,
+        &bytes,
+    )
+}
+
+/// Single-part scanline OpenEXR without compression, with FLOAT channels.
+fn exr_bytes(image: &LinearImage) -> Vec<u8> {
+    fn attribute(result: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        result.extend(name.as_bytes());
+        result.push(0);
+        result.extend(kind.as_bytes());
+        result.push(0);
+        result.extend((value.len() as i32).to_le_bytes());
+        result.extend(value);
+    }
+
+    const FLOAT: i32 = 2;
+    // Channels are sorted by name, and stored in this order inside every scanline
+    let channels = [("B", 2), ("G", 1), ("R", 0)];
+
+    let (width, height) = (image.width as i32, image.height as i32);
+    let window = [0, 0, width - 1, height - 1]
+        .into_iter()
+        .flat_map(i32::to_le_bytes)
+        .collect::<Vec<u8>>();
+
+    let mut channel_list = Vec::new();
+    for (name, _) in channels {
+        channel_list.extend(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend(FLOAT.to_le_bytes());
+        channel_list.extend([0, 0, 0, 0]); // pLinear and reserved
+        channel_list.extend(1i32.to_le_bytes()); // xSampling
+        channel_list.extend(1i32.to_le_bytes()); // ySampling
+    }
+    channel_list.push(0);
+
+    let mut result = Vec::new();
+    result.extend([0x76, 0x2f, 0x31, 0x01]); // magic
+    result.extend(2i32.to_le_bytes()); // version, no flags
+    attribute(&mut result, "channels", "chlist", &channel_list);
+    attribute(&mut result, "compression", "compression", &[0]);
+    attribute(&mut result, "dataWindow", "box2i", &window);
+    attribute(&mut result, "displayWindow", "box2i", &window);
+    attribute(&mut result, "lineOrder", "lineOrder", &[0]); // increasing y
+    attribute(
+        &mut result,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut result, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut result,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    result.push(0);
+
+    let line_size = width as usize * channels.len() * 4;
+    let lines_start = result.len() + height as usize * 8;
+    for y in 0..height as usize {
+        let offset = lines_start + y * (8 + line_size);
+        result.extend((offset as u64).to_le_bytes());
+    }
+    for (y, row) in image.rows_from_top().enumerate() {
+        result.extend((y as i32).to_le_bytes());
+        result.extend((line_size as i32).to_le_bytes());
+        for (_, index) in channels {
+            for px in row {
+                result.extend(px[index].to_le_bytes());
+            }
+        }
+    }
+    result
+}
+
+pub fn save_exr(path: &Path, image: &LinearImage) -> Result<(), String> {
+    use std::io::Write;
+    let mut file = create_file(path)?;
+    file.write_all(&exr_bytes(image))
+        .and_then(|()| file.flush())
+        .map_err(|err| write_error(path, &err))
+}
+
+pub fn save_frame(path: &Path, image: &LinearImage, format: FrameFormat) -> Result<(), String> {
+    match format {
+        FrameFormat::Png => save_image_png(path, &image.to_rgba8()),
+        FrameFormat::Png16 => save_png16(path, image),
+        FrameFormat::Exr => save_exr(path, image),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_decoding() {
+        let image = |bytes: &[u8]| Image {
+            bytes: bytes.iter().flat_map(|x| [*x, *x, *x, 255]).collect(),
+            width: bytes.len() as u16,
+            height: 1,
+        };
+        let decoded =
+            LinearImage::from_rgba8_passes(&image(&[0, 128, 255]), &image(&[0, 51, 0]), 4.0);
+        let expected = [0.0, (128.2f32 / 255.0).powi(2) * 4.0, 4.0];
+        for (px, expected) in decoded.pixels.iter().zip(expected) {
+            assert!((px[0] - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn float_decoding() {
+        let bytes = [0.25f32, 20.0, 0.0, 1.0]
+            .iter()
+            .flat_map(|x| x.to_ne_bytes())
+            .collect::<Vec<_>>();
+        let decoded = LinearImage::from_rgba32f(1, 1, &bytes);
+        assert_eq!(decoded.pixels, [[0.25, 20.0, 0.0]]);
+    }
+
+    #[test]
+    fn aux_ids_are_exact() {
+        // Every id survives the `sqrt` and 16-bit encoding of the high precision passes
+        let scale = AuxPass::ObjectId.output_scale(0.0);
+        for id in 0..scale as u32 {
+            let encoded = ((id as f32 / scale).sqrt() * 65025.0).round() as u32;
+            let pass = |byte: u32| Image {
+                bytes: vec![byte as u8, 0, 0, 255],
+                width: 1,
+                height: 1,
+            };
+            let mut image =
+                LinearImage::from_rgba8_passes(&pass(encoded / 255), &pass(encoded % 255), scale);
+            AuxPass::ObjectId.decode(&mut image);
+            assert_eq!(image.pixels[0][0], id as f32);
+        }
+    }
+
+    #[test]
+    fn tiles() {
+        assert_eq!(tile_offsets(100, 200), [0]);
+        assert_eq!(tile_offsets(100, 100), [0]);
+        assert_eq!(tile_offsets(100, 50), [0, 50]);
+        assert_eq!(tile_offsets(100, 40), [0, 40, 60]);
+
+        let mut image = LinearImage::new(3, 3);
+        let mut tile = LinearImage::new(2, 2);
+        tile.pixels = vec![[1.0; 3], [2.0; 3], [3.0; 3], [4.0; 3]];
+        image.paste(&tile, 1, 1);
+        let red = image.pixels.iter().map(|px| px[0]).collect::<Vec<_>>();
+        assert_eq!(red, [0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 0.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn exr_layout() {
+        let mut image = LinearImage::new(3, 2);
+        image.pixels[0] = [1.0, 2.0, 3.0];
+        let bytes = exr_bytes(&image);
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+
+        // Offset table points to the first scanline, which is the top row, i.e. the last in texture
+        let line_size = 3 * 3 * 4;
+        let table = bytes.len() - 2 * 8 - 2 * (8 + line_size);
+        let first = u64::from_le_bytes(bytes[table..table + 8].try_into().unwrap()) as usize;
+        assert_eq!(first, table + 16);
+        let second = &bytes[first + 8 + line_size..];
+        assert_eq!(second[..4], 1i32.to_le_bytes());
+        // B channel of the first pixel of the bottom row
+        assert_eq!(second[8..12], 3f32.to_le_bytes());
+    }
+}
@@ -7,6 +7,22 @@ pub mod code_generation;
 
 pub mod shader_error_parser;
 
+pub mod encoder;
+
+pub mod render_manifest;
+
+pub mod progress;
+
+pub mod image_export;
+
+pub mod shutter;
+
+pub mod stereo;
+
+pub mod projection;
+
+pub mod walk;
+
 #[macro_export]
 macro_rules! error {
 	(format, $format_string:literal, $($args:expr),*) => {
@@ -292,26 +292,28 @@ vec3 color_add_weighted(vec3 a, vec3 b, float coef) {
 // ---------------------------------------------------------------------------
 
 uniform float _offset_after_material; // Normally should equals to 0.0001, but for mobile can be different
+uniform int _teleport_external_ray; // Equals to 1 when camera ray is traced instead of the image
 
 // Result after material processing.
 struct MaterialProcessing {
     bool is_final; // If this flag set to false, then next ray tracing will be proceed. Useful for: portals, glass, mirrors, etc.
     vec3 mul_to_color; // If is_final = true, then this color is multiplied to current color, otherwise this is the final color.
     Ray new_ray; // New ray if is_final = true.
+    vec3 emission; // Light emitted by surface, it is multiplied by current color and added to the result.
 };
 
 MaterialProcessing material_empty() {
-    return MaterialProcessing(true, vec3(0.), ray_none);    
+    return MaterialProcessing(true, vec3(0.), ray_none, vec3(0.));
 }
 
 // Shortcut for creating material with is_final = true.
 MaterialProcessing material_final(vec3 color) {
-    return MaterialProcessing(true, color, ray_none);    
+    return MaterialProcessing(true, color, ray_none, vec3(0.));
 }
 
 // Shortcut for creating material with is_final = false.
 MaterialProcessing material_next(vec3 mul_color, Ray new_ray) {
-    return MaterialProcessing(false, mul_color, new_ray);
+    return MaterialProcessing(false, mul_color, new_ray, vec3(0.));
 }
 
 // Function to easy write simple material.
@@ -343,6 +345,150 @@ MaterialProcessing material_simple(
     return material_simple2(hit, r, color, normal_coef, grid, grid_scale, grid_coef, false, false);
 }
 
+// Color with shading by normal, as in `material_simple2`.
+MaterialProcessing material_colored(SurfaceIntersection hit, Ray r, vec3 color, float normal_coef) {
+    color = color_add_weighted(color, color * color_normal(hit.n, r.d), normal_coef);
+    return material_final(color);
+}
+
+// Texture is repeated over surface coordinates.
+MaterialProcessing material_textured(
+    SurfaceIntersection hit, Ray r,
+    sampler2D tex, vec2 uv_scale, vec2 uv_offset, float normal_coef
+) {
+    vec2 uv = fract(vec2(hit.u, hit.v) * uv_scale + uv_offset);
+    return material_colored(hit, r, sqrvec(texture(tex, uv).rgb), normal_coef);
+}
+
+MaterialProcessing material_checker(
+    SurfaceIntersection hit, Ray r,
+    vec3 color_a, vec3 color_b, float scale, float normal_coef
+) {
+    vec2 cell = floor(vec2(hit.u, hit.v) * scale);
+    vec3 color = mod(cell.x + cell.y, 2.) < 0.5 ? color_a : color_b;
+    return material_colored(hit, r, color, normal_coef);
+}
+
+MaterialProcessing material_stripes(
+    SurfaceIntersection hit, Ray r,
+    vec3 color_a, vec3 color_b, float scale, float width, float normal_coef
+) {
+    vec3 color = fract(hit.u * scale) < width ? color_a : color_b;
+    return material_colored(hit, r, color, normal_coef);
+}
+
+// Maximum octaves of noise, must be the same as `MAX_NOISE_OCTAVES` in `material.rs`.
+#define NOISE_MAX_OCTAVES 8
+
+// Hash without sine, thanks Dave Hoskins: https://www.shadertoy.com/view/4djSRW
+float noise_hash(vec2 p) {
+    vec3 p3 = fract(vec3(p.xyx) * .1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+float value_noise(vec2 p) {
+    vec2 i = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f * f * (3. - 2. * f);
+    return mix(
+        mix(noise_hash(i), noise_hash(i + vec2(1., 0.)), u.x),
+        mix(noise_hash(i + vec2(0., 1.)), noise_hash(i + vec2(1., 1.)), u.x),
+        u.y
+    );
+}
+
+// Sum of octaves of value noise, lies in [0..1].
+float fractal_noise(vec2 p, int octaves) {
+    float sum = 0.;
+    float amplitude = 0.5;
+    float norm = 0.;
+    for (int k = 0; k < NOISE_MAX_OCTAVES; k++) {
+        if (k >= octaves) break;
+        sum += amplitude * value_noise(p);
+        norm += amplitude;
+        amplitude *= 0.5;
+        p *= 2.;
+    }
+    return sum / norm;
+}
+
+MaterialProcessing material_noise(
+    SurfaceIntersection hit, Ray r,
+    vec3 color_a, vec3 color_b, float scale, int octaves, float normal_coef
+) {
+    float value = fractal_noise(vec2(hit.u, hit.v) * scale, octaves);
+    return material_colored(hit, r, mix(color_a, color_b, value), normal_coef);
+}
+
+// Pseudo-random numbers for stochastic materials. Seed is set in `main` for every pixel and
+// antialiasing sample, so progressive rendering gets new numbers on each frame.
+vec3 random_seed = vec3(0.);
+
+// Color channel that is traced by dispersive materials for current sample: 0 is red, 1 is green, 2 is blue. It goes by samples, and count of samples is a multiple of 3 with dispersion, so every pixel gets all channels equally.
+int dispersion_channel = 1;
+bool dispersion_masked = false; // Other channels are multiplied by zero only once per traced ray, reset in `ray_tracing`
+
+void random_init(vec2 pixel, int sample_index) {
+    random_seed = vec3(pixel, float(sample_index));
+    dispersion_channel = int(mod(float(sample_index), 3.));
+}
+
+// Hash without sine, thanks Dave Hoskins: https://www.shadertoy.com/view/4djSRW
+float random_next() {
+    random_seed.z += 0.618034;
+    vec3 p3 = fract(random_seed * .1031);
+    p3 += dot(p3, p3.zyx + 31.32);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+// Cosine-weighted direction in hemisphere around `n`.
+vec3 random_cosine_direction(vec3 n) {
+    float u1 = random_next();
+    float u2 = random_next();
+    float phi = 2. * PI * u2;
+    float radius = sqrt(u1);
+    vec3 t = normalize(abs(n.x) > 0.5 ? cross(n, vec3(0., 1., 0.)) : cross(n, vec3(1., 0., 0.)));
+    vec3 b = cross(n, t);
+    return normalize(t * cos(phi) * radius + b * sin(phi) * radius + n * sqrt(1. - u1));
+}
+
+// Physically based material: one bounce is sampled as diffuse or specular (Schlick's fresnel),
+// so it needs antialiasing or progressive rendering to converge. Other materials act as light
+// sources for it, also as `emission`, which is visible through portals and mirrors.
+MaterialProcessing material_pbr(
+    SurfaceIntersection hit, Ray r,
+    vec3 albedo, float roughness, float metallic, vec3 emission
+) {
+    // Camera can't go through this surface
+    if (_teleport_external_ray == 1) return material_final(albedo);
+
+    vec3 n = normalize(hit.n);
+    vec3 d = normalize(r.d.xyz);
+    if (dot(n, d) > 0.) n = -n;
+
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    float cos_theta = clamp(-dot(d, n), 0., 1.);
+    vec3 fresnel = f0 + (1. - f0) * pow(1. - cos_theta, 5.);
+    float specular_chance = clamp((fresnel.r + fresnel.g + fresnel.b) / 3., 0.01, 0.99);
+
+    vec3 dir;
+    vec3 mul;
+    if (random_next() < specular_chance) {
+        dir = normalize(mix(reflect(d, n), random_cosine_direction(n), roughness * roughness));
+        mul = fresnel / specular_chance;
+    } else {
+        dir = random_cosine_direction(n);
+        mul = albedo * (1. - metallic) * (1. - fresnel) / (1. - specular_chance);
+    }
+
+    r.d = vec4(dir * length(r.d.xyz), 0.);
+    r.o += r.d * _offset_after_material;
+    MaterialProcessing m = material_next(mul, r);
+    m.emission = emission;
+    return m;
+}
+
 // Function to easy write reflect material.
 MaterialProcessing material_reflect(
     SurfaceIntersection hit, Ray r,
@@ -363,6 +509,45 @@ MaterialProcessing material_refract(
     return material_next(add_to_color, r);
 }
 
+// Refractive index for current `dispersion_channel`, by Cauchy's equation fitted to index at Fraunhofer d line and Abbe number. No dispersion if `abbe_number` is zero.
+float dispersion_index(float refractive_index, float abbe_number) {
+    if (abbe_number <= 0.) return refractive_index;
+
+    // Fraunhofer C, d and F lines, in micrometers
+    float c_line = 0.6563;
+    float d_line = 0.5876;
+    float f_line = 0.4861;
+    float b = (refractive_index - 1.) / abbe_number / (1. / sqr(f_line) - 1. / sqr(c_line));
+    float a = refractive_index - b / sqr(d_line);
+    float wavelength = dispersion_channel == 0 ? c_line : (dispersion_channel == 1 ? d_line : f_line);
+    return a + b / sqr(wavelength);
+}
+
+// Refraction with chromatic dispersion and fresnel reflection. `fresnel` is in 0..1, it multiplies the probability of reflection, so 0 is the same as `material_refract`.
+MaterialProcessing material_refract_dispersion(
+    SurfaceIntersection hit, Ray r,
+    vec3 add_to_color, float refractive_index, float abbe_number, float fresnel
+) {
+    float ri = dispersion_index(refractive_index, abbe_number);
+    if (abbe_number > 0. && !dispersion_masked) {
+        dispersion_masked = true;
+        vec3 mask = vec3(0.);
+        if (dispersion_channel == 0) { mask.r = 3.; } else
+        if (dispersion_channel == 1) { mask.g = 3.; } else
+        { mask.b = 3.; }
+        add_to_color *= mask;
+    }
+
+    float r0 = sqr((1. - ri) / (1. + ri));
+    float cos_theta = abs(dot(normalize(hit.n), normalize(r.d.xyz)));
+    float reflectance = r0 + (1. - r0) * pow(1. - cos_theta, 5.);
+    if (random_next() < reflectance * fresnel) {
+        return material_reflect(hit, r, add_to_color);
+    } else {
+        return material_refract(hit, r, add_to_color, ri);
+    }
+}
+
 MaterialProcessing material_teleport_transformed(Ray r,vec3 n) {
     // todo add add_gray_after_teleportation
     r.o += r.d * _offset_after_material;
@@ -524,6 +709,144 @@ SurfaceIntersection triangle(Ray r, vec3 v0, vec3 v1, vec3 v2) {
     return SurfaceIntersection(true, t, u, v, normalize_normal(cross(v1-v0, v2-v0), r.d.xyz));
 }
 
+// ---------------------------------------------------------------------------
+// Triangle meshes -----------------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// Must be the same as in `mesh.rs`.
+#define MESH_TEXTURE_WIDTH 1024.
+#define MESH_LEAF_SIZE 4
+#define MESH_MAX_STEPS 4096
+
+// Decodes little-endian f32 that is stored in RGBA8 texel. Inverse of `encode_float`.
+float decode_float(vec4 c) {
+    vec4 b = floor(c * 255. + 0.5);
+    float exponent = mod(b.a, 128.) * 2. + floor(b.b / 128.);
+    if (exponent == 0.) return 0.;
+    float mantissa = mod(b.b, 128.) * 65536. + b.g * 256. + b.r;
+    float s = b.a >= 128. ? -1. : 1.;
+    return s * exp2(exponent - 127.) * (1. + mantissa / 8388608.);
+}
+
+float mesh_fetch(sampler2D tex, float height, float index) {
+    float y = floor(index / MESH_TEXTURE_WIDTH);
+    float x = index - y * MESH_TEXTURE_WIDTH;
+    return decode_float(texture(tex, vec2((x + 0.5) / MESH_TEXTURE_WIDTH, (y + 0.5) / height)));
+}
+
+vec3 mesh_fetch_vec(sampler2D tex, float height, float index) {
+    return vec3(mesh_fetch(tex, height, index), mesh_fetch(tex, height, index + 1.), mesh_fetch(tex, height, index + 2.));
+}
+
+// Is ray intersects box nearer than `max_t`.
+bool box_hit(Ray r, vec3 bmin, vec3 bmax, float max_t) {
+    vec3 inv = 1. / r.d.xyz;
+    vec3 t1 = (bmin - r.o.xyz) * inv;
+    vec3 t2 = (bmax - r.o.xyz) * inv;
+    vec3 tmin = min(t1, t2);
+    vec3 tmax = max(t1, t2);
+    float t_near = max(max(tmin.x, tmin.y), tmin.z);
+    float t_far = min(min(tmax.x, tmax.y), tmax.z);
+    return t_near <= t_far && t_far > 0. && t_near < max_t;
+}
+
+// Traverses BVH of mesh, that is packed by `Mesh::pack`. BVH nodes are in depth-first order, so traversal doesn't need stack: when box is missed, go to the end of its subtree.
+SceneIntersection mesh_intersect(sampler2D tex, float height, Ray r, int material) {
+    SceneIntersection i = SceneIntersection(material, intersection_none, false);
+    if (height < 0.5) return scene_intersection_none; // Mesh is not loaded
+
+    float node_count = mesh_fetch(tex, height, 0.);
+    float triangles_offset = mesh_fetch(tex, height, 1.);
+    float node = 0.;
+    for (int k = 0; k < MESH_MAX_STEPS; k++) {
+        if (node >= node_count) break;
+        float base = 2. + node * 9.;
+        vec3 bmin = mesh_fetch_vec(tex, height, base);
+        vec3 bmax = mesh_fetch_vec(tex, height, base + 3.);
+        if (!box_hit(r, bmin, bmax, i.hit.t)) {
+            node = mesh_fetch(tex, height, base + 6.);
+            continue;
+        }
+
+        float start = mesh_fetch(tex, height, base + 7.);
+        float count = mesh_fetch(tex, height, base + 8.);
+        for (int j = 0; j < MESH_LEAF_SIZE; j++) {
+            if (float(j) >= count) break;
+            float t_base = triangles_offset + (start + float(j)) * 9.;
+            SurfaceIntersection hit = triangle(
+                r,
+                mesh_fetch_vec(tex, height, t_base),
+                mesh_fetch_vec(tex, height, t_base + 3.),
+                mesh_fetch_vec(tex, height, t_base + 6.)
+            );
+            if (nearer(i, hit)) {
+                i.hit = hit;
+            }
+        }
+        node += 1.;
+    }
+
+    if (!i.hit.hit) return scene_intersection_none;
+    return i;
+}
+
+// ---------------------------------------------------------------------------
+// Signed distance fields ----------------------------------------------------
+// ---------------------------------------------------------------------------
+
+// After this distance raymarching stops.
+#define SDF_MAX_DISTANCE 1000.
+
+// Primitives and operations, thanks iq: https://iquilezles.org/articles/distfunctions/
+float sd_sphere(vec3 p, float radius) {
+    return length(p) - radius;
+}
+
+float sd_box(vec3 p, vec3 size) {
+    vec3 q = abs(p) - size;
+    return length(max(q, 0.)) + min(max(q.x, max(q.y, q.z)), 0.);
+}
+
+// `t.x` is big radius, `t.y` is small radius, lies in plane xz.
+float sd_torus(vec3 p, vec2 t) {
+    vec2 q = vec2(length(p.xz) - t.x, p.y);
+    return length(q) - t.y;
+}
+
+float op_smooth_union(float d1, float d2, float k) {
+    float h = clamp(0.5 + 0.5 * (d2 - d1) / k, 0., 1.);
+    return mix(d2, d1, h) - k * h * (1. - h);
+}
+
+float op_smooth_subtraction(float d1, float d2, float k) {
+    float h = clamp(0.5 - 0.5 * (d2 + d1) / k, 0., 1.);
+    return mix(d2, -d1, h) + k * h * (1. - h);
+}
+
+// ---------------------------------------------------------------------------
+// Constructive solid geometry -----------------------------------------------
+// ---------------------------------------------------------------------------
+
+#define CSG_UNION 0
+#define CSG_INTERSECTION 1
+#define CSG_DIFFERENCE 2
+
+// Maximum hits of one operand that are considered.
+#define CSG_MAX_HITS 16
+
+// Offset after hit, so next search doesn't find the same surface.
+#define CSG_OFFSET 0.0001
+
+bool csg_inside(bool a, bool b, int op) {
+    if (op == CSG_UNION) {
+        return a || b;
+    } else if (op == CSG_INTERSECTION) {
+        return a && b;
+    } else {
+        return a && !b;
+    }
+}
+
 // Intersect ray with debug thing
 SceneIntersection debug_intersect(Ray r) {
     vec3 pa = vec3(0.);
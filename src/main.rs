@@ -5,25 +5,50 @@ use glam::Vec4Swizzles;
 use glam::{DMat4, DVec2, DVec3, DVec4};
 use macroquad::prelude::is_key_down;
 use macroquad::prelude::is_key_pressed;
-use macroquad::prelude::Image;
-use once_cell::sync::Lazy;
+use portal::encoder::Encoder;
+#[cfg(not(target_arch = "wasm32"))]
+use portal::encoder::EncoderPreset;
+use portal::gui::camera::default_focus_distance;
 use portal::gui::camera::CalculatedCam;
 use portal::gui::camera::CameraId;
 use portal::gui::camera::CurrentCam;
 use portal::gui::camera::OriginalCam;
 use portal::gui::eng_rus::EngRusSettings;
 use portal::gui::eng_rus::EngRusText;
+use portal::gui::mesh::Mesh;
 use portal::gui::scenes::ShowHiddenScenes;
+use portal::gui::teleport::{CpuObject, CpuTeleport};
 use portal::gui::uniform::AnyUniform;
 use portal::gui::uniform::ClampedValue;
+#[cfg(not(target_arch = "wasm32"))]
+use portal::image_export::save_exr;
+use portal::image_export::{
+    save_frame, save_image_png, tile_offsets, AuxPass, FrameFormat, LinearImage,
+};
+use portal::progress::{Progress, ProgressEvent, RenderOutcome};
+#[cfg(not(target_arch = "wasm32"))]
+use portal::progress::{ProgressFormat, RenderSummary};
+use portal::projection::Projection;
+#[cfg(not(target_arch = "wasm32"))]
+use portal::render_manifest::{scene_hash, FrameRange, Shard};
+use portal::render_manifest::{
+    FrameSelection, ProcessOwner, RenderManifest, RenderSettings, WorkDir,
+};
+use portal::shutter::Shutter;
+#[cfg(not(target_arch = "wasm32"))]
+use portal::shutter::{ShutterKernel, ShutterOffset};
+use portal::stereo::{AnaglyphMode, StereoLayout, VrFormat};
+use portal::walk::{align_up, fall_step};
 use portal::with_swapped;
+use std::collections::BTreeMap;
 use std::f64::consts::PI;
 
 use macroquad::prelude::{
-    clamp, clear_background, draw_rectangle, draw_texture_ex, get_screen_data,
-    gl_use_default_material, gl_use_material, is_mouse_button_down, mouse_position_local,
-    mouse_wheel, next_frame, screen_height, screen_width, set_default_camera, Conf,
-    DrawTextureParams, MouseButton, Texture2D, BLACK, WHITE,
+    clamp, clear_background, draw_rectangle, draw_texture_ex, get_frame_time, get_internal_gl,
+    get_screen_data, gl_use_default_material, gl_use_material, is_mouse_button_down,
+    is_mouse_button_pressed, mouse_position, mouse_position_local, mouse_wheel, next_frame,
+    screen_height, screen_width, set_default_camera, Conf, DrawTextureParams, MouseButton,
+    Texture2D, BLACK, WHITE,
 };
 use portal::gui::scene_serialized::{normalize_pretty_output, pretty_config, SerializedScene};
 use portal::gui::scenes::Scenes;
@@ -44,11 +69,12 @@ struct RotateAroundCam {
     mouse_sensitivity: f64,
     scale_factor: f64,
     view_angle: f64,
-    use_panini_projection: bool,
+    projection: Projection,
     panini_param: f64,
 
-    use_360_camera: bool,
-    use_180_camera: bool,
+    aperture: f64,
+    focus_distance: f64,
+    pick_focus: bool, // next click on canvas sets focus distance
 
     inverse_x: bool,
     inverse_y: bool,
@@ -71,6 +97,7 @@ struct RotateAroundCam {
 
     prev_cam_pos: DVec3,
     teleport_matrix: DMat4,
+    followed_matrix: Option<DMat4>, // last matrix of current camera with `CamLookAt::MatrixFrame`
     allow_teleport: bool,
     stop_at_objects: bool,
 
@@ -78,6 +105,12 @@ struct RotateAroundCam {
 
     free_movement: bool,
 
+    walk_mode: bool, // gravity and constant height above the floor, works with free movement
+    eye_height: f64,
+    gravity: f64,
+    vertical_speed: f64,
+    on_ground: bool,
+
     prev_view_angle: f64,
     zoom_mode: bool,
 
@@ -94,6 +127,9 @@ impl RotateAroundCam {
     const BETA_MIN: f64 = 0.01;
     const BETA_MAX: f64 = PI - 0.01;
 
+    /// Radians per second to return up vector of the camera to up of the world in walk mode.
+    const UP_ALIGN_SPEED: f64 = PI;
+
     fn new() -> Self {
         Self {
             look_at: DVec3::new(0., 0., 0.),
@@ -108,11 +144,12 @@ impl RotateAroundCam {
             scale_factor: 1.1,
             view_angle: deg2rad(90.),
 
-            use_panini_projection: false,
+            projection: Projection::Perspective,
             panini_param: 1.0,
 
-            use_360_camera: false,
-            use_180_camera: false,
+            aperture: 0.,
+            focus_distance: default_focus_distance(),
+            pick_focus: false,
 
             inverse_x: false,
             inverse_y: false,
@@ -132,6 +169,7 @@ impl RotateAroundCam {
             new_render_scale: None,
 
             teleport_matrix: DMat4::IDENTITY,
+            followed_matrix: None,
             allow_teleport: true,
             stop_at_objects: false,
             prev_cam_pos: Default::default(),
@@ -140,6 +178,12 @@ impl RotateAroundCam {
 
             free_movement: false,
 
+            walk_mode: false,
+            eye_height: 1.0,
+            gravity: 9.8,
+            vertical_speed: 0.,
+            on_ground: false,
+
             prev_view_angle: deg2rad(90.),
             zoom_mode: false,
 
@@ -163,6 +207,12 @@ impl RotateAroundCam {
             free_movement: self.free_movement,
             matrix: self.teleport_matrix,
             override_matrix: true,
+            aperture: self.aperture,
+            focus_distance: self.focus_distance,
+            projection: self.projection,
+            view_angle: self.view_angle,
+            panini_param: self.panini_param,
+            follows_matrix: self.followed_matrix.is_some(),
         }
     }
 
@@ -190,7 +240,7 @@ impl RotateAroundCam {
 
         let mouse_pos: DVec2 = glam::Vec2::from(<[f32; 2]>::from(mouse_position_local())).as_f64();
 
-        if is_mouse_button_down(MouseButton::Left) && mouse_over_canvas {
+        if is_mouse_button_down(MouseButton::Left) && mouse_over_canvas && !self.pick_focus {
             let size = mymax(screen_width().into(), screen_height().into());
             self.process_mouse_offset(
                 (mouse_pos.x - self.previous_mouse.x) * size,
@@ -208,6 +258,11 @@ impl RotateAroundCam {
             let move_speed = 0.03;
 
             let dir = self.get_pos_vec();
+            let dir = if self.walk_mode {
+                DVec3::new(dir.x, 0., dir.z).normalize() * self.r
+            } else {
+                dir
+            };
 
             let i = dir.normalize().cross(DVec3::new(0., 1., 0.)).normalize();
 
@@ -220,15 +275,24 @@ impl RotateAroundCam {
                 is_something_changed = true;
             }
 
-            if is_key_down(macroquad::input::KeyCode::Space) {
-                self.look_at.y += self.r * move_speed;
-                is_something_changed = true;
-            }
-            if is_key_down(macroquad::input::KeyCode::LeftShift)
-                || is_key_down(macroquad::input::KeyCode::RightShift)
-            {
-                self.look_at.y -= self.r * move_speed;
-                is_something_changed = true;
+            if self.walk_mode {
+                if is_key_pressed(macroquad::input::KeyCode::Space) && self.on_ground {
+                    // Jump to half of eye height
+                    self.vertical_speed = (self.gravity * self.eye_height).sqrt();
+                    self.on_ground = false;
+                    is_something_changed = true;
+                }
+            } else {
+                if is_key_down(macroquad::input::KeyCode::Space) {
+                    self.look_at.y += self.r * move_speed;
+                    is_something_changed = true;
+                }
+                if is_key_down(macroquad::input::KeyCode::LeftShift)
+                    || is_key_down(macroquad::input::KeyCode::RightShift)
+                {
+                    self.look_at.y -= self.r * move_speed;
+                    is_something_changed = true;
+                }
             }
 
             if is_key_down(macroquad::input::KeyCode::A) {
@@ -272,6 +336,11 @@ impl RotateAroundCam {
 
         self.previous_mouse = mouse_pos;
 
+        if self.walk_mode && self.free_movement && !self.on_ground {
+            // Falling continues without input
+            is_something_changed = true;
+        }
+
         is_something_changed
     }
 
@@ -310,6 +379,7 @@ impl RotateAroundCam {
         } else {
             self.look_at = self.look_at - self.get_pos_vec();
             self.free_movement = false;
+            self.walk_mode = false;
         }
     }
 
@@ -322,6 +392,9 @@ impl RotateAroundCam {
         self.alpha = s.alpha;
         self.beta = s.beta;
         self.r = s.r;
+        self.projection = s.projection;
+        self.view_angle = s.view_angle;
+        self.panini_param = s.panini_param;
 
         if self.free_movement {
             self.look_at = self.get_pos_vec() + self.look_at;
@@ -336,6 +409,9 @@ impl RotateAroundCam {
         cam_settings.alpha = self.alpha;
         cam_settings.beta = self.beta;
         cam_settings.r = self.r;
+        cam_settings.projection = self.projection;
+        cam_settings.view_angle = self.view_angle;
+        cam_settings.panini_param = self.panini_param;
     }
 }
 
@@ -427,6 +503,33 @@ impl RotateAroundCam {
             });
         });
         ui.label("Toggled by Q. Controls: WASD + Space (up) + Shift (down). Use wheel to control movement speed. Hold Ctrl to zoom. ");
+        ui.horizontal(|ui| {
+            ui.label("Walk mode:");
+            if check_changed(&mut self.walk_mode, |is_use| {
+                ui.add(egui::Checkbox::new(is_use, ""));
+            }) {
+                if self.walk_mode && !self.free_movement {
+                    self.change_free_movement();
+                }
+                self.on_ground = false;
+                self.vertical_speed = 0.;
+                changed = true;
+            }
+        });
+        if self.walk_mode && self.free_movement {
+            ui.label("Space jumps. Floor is searched below the camera, also through portals.");
+            ui.horizontal(|ui| {
+                ui.label("Eye height:");
+                changed |= check_changed(&mut self.eye_height, |height| {
+                    ui.add(DragValue::new(height).speed(0.01).range(0.01..=100.0));
+                });
+                ui.separator();
+                ui.label("Gravity:");
+                changed |= check_changed(&mut self.gravity, |gravity| {
+                    ui.add(DragValue::new(gravity).speed(0.1).range(0.0..=100.0));
+                });
+            });
+        }
         ui.separator();
         ui.horizontal(|ui| {
             ui.label("Stop camera at objects:");
@@ -543,68 +646,70 @@ impl RotateAroundCam {
 
         ui.separator();
 
-        ui.horizontal(|ui| {
-            ui.label("360 camera:");
-            changed |= check_changed(&mut self.use_360_camera, |is_use| {
-                ui.add(egui::Checkbox::new(is_use, ""));
+        egui::ComboBox::from_label("Projection")
+            .selected_text(self.projection.name())
+            .show_ui(ui, |ui| {
+                for projection in Projection::ALL {
+                    changed |= ui
+                        .selectable_value(&mut self.projection, projection, projection.name())
+                        .changed();
+                }
             });
-        });
+        match self.projection {
+            Projection::Equirectangular360 => {
+                ui.label("(Use 2:1 frame)");
+            }
+            Projection::Cubemap => {
+                ui.label("(Use 6:1 frame)");
+            }
+            _ => {}
+        }
 
-        ui.separator();
+        if self.projection == Projection::Panini {
+            ui.horizontal(|ui| {
+                ui.label("Panini parameter:");
+                changed |= check_changed(&mut self.panini_param, |param| {
+                    ui.add(egui::Slider::new(param, 0.0..=1.0));
+                });
+            });
+        }
 
-        ui.horizontal(|ui| {
-            ui.label("180 camera (VR180):");
-            changed |= check_changed(&mut self.use_180_camera, |is_use| {
-                ui.add(egui::Checkbox::new(is_use, ""));
+        if let Some((min, max)) = self.projection.view_angle_range() {
+            self.view_angle = clamp(self.view_angle, deg2rad(min), deg2rad(max));
+            changed |= check_changed(&mut self.view_angle, |m| {
+                let mut current = rad2deg(*m);
+                ui.add(
+                    egui::Slider::new(&mut current, min..=max)
+                        .text("View angle")
+                        .suffix("°")
+                        .clamping(egui::widgets::SliderClamping::Always),
+                );
+                *m = deg2rad(current);
             });
-        });
+        }
 
         ui.separator();
 
-        ui.horizontal(|ui| {
-            ui.label("Panini projection:");
-            changed |= check_changed(&mut self.use_panini_projection, |is_use| {
-                ui.add(egui::Checkbox::new(is_use, ""));
-            });
+        changed |= check_changed(&mut self.aperture, |aperture| {
+            ui.add(
+                egui::Slider::new(aperture, 0.0..=1.0)
+                    .logarithmic(true)
+                    .text("Aperture (0 disables depth of field)"),
+            );
         });
-
-        // Keep camera modes mutually exclusive.
-        // Panini is a regular-camera projection variant; 360/180 are equirectangular modes.
-        if self.use_panini_projection {
-            self.use_360_camera = false;
-            self.use_180_camera = false;
-        }
-        if self.use_360_camera {
-            self.use_panini_projection = false;
-            self.use_180_camera = false;
-        }
-        if self.use_180_camera {
-            self.use_panini_projection = false;
-            self.use_360_camera = false;
-        }
-        if !self.use_panini_projection {
-            self.view_angle = macroquad::math::clamp(self.view_angle, 0.0, deg2rad(140.0));
-        }
         ui.horizontal(|ui| {
-            let is_use = self.use_panini_projection;
-            ui.label("Panini parameter:");
-            changed |= check_changed(&mut self.panini_param, |param| {
-                ui.add_enabled_ui(is_use, |ui| {
-                    ui.add(egui::Slider::new(param, 0.0..=1.0));
-                });
+            ui.label("Focus distance:");
+            changed |= check_changed(&mut self.focus_distance, |focus| {
+                ui.add(
+                    DragValue::new(focus)
+                        .speed(0.01)
+                        .range(0.01..=1000.0)
+                        .min_decimals(0)
+                        .max_decimals(2),
+                );
             });
-        });
-
-        let is_use = self.use_panini_projection;
-        changed |= check_changed(&mut self.view_angle, |m| {
-            let mut current = rad2deg(*m);
-            ui.add(
-                egui::Slider::new(&mut current, if is_use { 2.0..=250.0 } else { 2.0..=140.0 })
-                    .text("View angle")
-                    .suffix("°")
-                    .clamping(egui::widgets::SliderClamping::Always),
-            );
-            *m = deg2rad(current);
+            ui.toggle_value(&mut self.pick_focus, "🎯 Pick on screen")
+                .on_hover_text("Next click on the scene sets focus distance to the clicked point");
         });
 
         ui.separator();
@@ -632,92 +737,82 @@ impl RotateAroundCam {
     }
 }
 
-/// ----------------------------------------------------------
-///  2¹ᐟγ  with γ = 2  ⇒  linear   = (srgb/255)²
-///  Our integer  "linear units"  are in   0 … 65 025 (=255²)
-///
-///  We therefore need two tables:
-///    • L_TO_S[c]   : 0 … 65 025  → 0 … 255   (√ + rounding)
-///    • S_TO_L[c]   : 0 … 255     → 0 … 65 025 (square)
-/// -----------------------------------------------------------
-
-/// 0 … 65 025  →  0 … 255  (rounded √)
-static L_TO_S: Lazy<[u8; 65_026]> = Lazy::new(|| {
-    let mut t = [0u8; 65_026];
-    for (i, v) in t.iter_mut().enumerate() {
-        *v = ((i as f32).sqrt() + 0.5) as u8;
-    }
-    t
-});
-
-/// 0 … 255  →  0 … 65 025  (square) – still fine as a genuine `const`
-const S_TO_L: [u16; 256] = {
-    let mut t = [0u16; 256];
-    let mut i = 0;
-    while i < 256 {
-        t[i] = (i * i) as u16;
-        i += 1;
-    }
-    t
-};
-
-pub fn average_images(mut images: Vec<Image>) -> Image {
-    use std::num::NonZeroUsize;
-
+/// Weighted average in linear space, weights are relative.
+pub fn average_images(mut images: Vec<(LinearImage, f64)>) -> LinearImage {
     assert!(!images.is_empty(), "empty slice");
 
     if images.len() == 1 {
-        return images.pop().unwrap();
+        return images.pop().unwrap().0;
     }
 
-    let w = images[0].width as usize;
-    let h = images[0].height as usize;
-    let px = NonZeroUsize::new(w * h).expect("zero-sized image");
-
-    // ---- Fast dimension check ------------------------------------------------
-    if !images
-        .iter()
-        .all(|im| im.width as usize == w && im.height as usize == h)
-    {
-        panic!("all images must have identical dimensions");
+    let mut accumulator = ImageAccumulator::new(images[0].0.width, images[0].0.height);
+    for (img, weight) in &images {
+        accumulator.add_weighted(img, *weight);
     }
+    accumulator.average()
+}
 
-    // ---- Accumulators (u32 → cannot overflow: 65 025 * N_images < 4 G) -------
-    let mut sum_r = vec![0u32; px.get()];
-    let mut sum_g = vec![0u32; px.get()];
-    let mut sum_b = vec![0u32; px.get()];
+/// Running sum of images in linear space, used for motion blur and progressive rendering. Sum is
+/// in floating point, so quantization happens only once, when the result is saved.
+pub struct ImageAccumulator {
+    width: u16,
+    height: u16,
+    sum: Vec<[f64; 3]>,
+    weight: f64,
+    count: u32,
+}
 
-    // ---- Hot loop ------------------------------------------------------------
-    for img in &images {
-        for (idx, px_rgba) in img.bytes.chunks_exact(4).enumerate() {
-            // Unsafe unchecked table read is another ~5 % but keep it safe for now
-            sum_r[idx] += S_TO_L[px_rgba[0] as usize] as u32;
-            sum_g[idx] += S_TO_L[px_rgba[1] as usize] as u32;
-            sum_b[idx] += S_TO_L[px_rgba[2] as usize] as u32;
+impl ImageAccumulator {
+    pub fn new(width: u16, height: u16) -> Self {
+        assert!(width != 0 && height != 0, "zero-sized image");
+        Self {
+            width,
+            height,
+            sum: vec![[0.0; 3]; width as usize * height as usize],
+            weight: 0.0,
+            count: 0,
         }
     }
 
-    let n = images.len() as u32;
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_same_size(&self, img: &LinearImage) -> bool {
+        img.width == self.width && img.height == self.height
+    }
 
-    // ---- Produce output ------------------------------------------------------
-    let mut out = vec![0u8; px.get() * 4];
+    pub fn add(&mut self, img: &LinearImage) {
+        self.add_weighted(img, 1.0);
+    }
 
-    out.chunks_exact_mut(4).enumerate().for_each(|(idx, dst)| {
-        // integer average in linear space
-        let lr = (sum_r[idx] / n) as usize;
-        let lg = (sum_g[idx] / n) as usize;
-        let lb = (sum_b[idx] / n) as usize;
+    pub fn add_weighted(&mut self, img: &LinearImage, weight: f64) {
+        assert!(
+            self.is_same_size(img),
+            "all images must have identical dimensions"
+        );
 
-        dst[0] = L_TO_S[lr]; // back to sRGB (γ=2 ⇒ √)
-        dst[1] = L_TO_S[lg];
-        dst[2] = L_TO_S[lb];
-        dst[3] = 255; // opaque
-    });
+        for (sum, px) in self.sum.iter_mut().zip(&img.pixels) {
+            for (sum, x) in sum.iter_mut().zip(px) {
+                *sum += *x as f64 * weight;
+            }
+        }
+        self.weight += weight;
+        self.count += 1;
+    }
 
-    Image {
-        bytes: out,
-        width: w as u16,
-        height: h as u16,
+    pub fn average(&self) -> LinearImage {
+        assert!(self.count != 0, "no images were added");
+
+        LinearImage {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .sum
+                .iter()
+                .map(|sum| sum.map(|x| (x / self.weight) as f32))
+                .collect(),
+        }
     }
 }
 
@@ -729,6 +824,67 @@ struct ViewportRect {
     height: f32,
 }
 
+enum Teleported {
+    No,
+    Yes(DMat4),
+    Failed, // portal is crossed, but matrix can't be found
+}
+
+/// Render target with half float colors, macroquad's render targets are always 8-bit. Only on
+/// desktop OpenGL 3+, where `RGBA16F` textures are always color-renderable.
+struct FloatRenderTarget {
+    render_pass: macroquad::miniquad::RenderPass,
+    texture: macroquad::miniquad::TextureId,
+    width: u32,
+    height: u32,
+}
+
+impl FloatRenderTarget {
+    fn new(width: u32, height: u32) -> Option<Self> {
+        use macroquad::miniquad::{Backend, TextureFormat, TextureParams};
+        let ctx = unsafe { get_internal_gl() }.quad_context;
+        let info = ctx.info();
+        let glsl = info.glsl_support;
+        if cfg!(target_arch = "wasm32")
+            || info.backend != Backend::OpenGl
+            || !(glsl.v130 || glsl.v150 || glsl.v330)
+        {
+            return None;
+        }
+        let texture = ctx.new_render_texture(TextureParams {
+            width,
+            height,
+            format: TextureFormat::RGBA16F,
+            ..Default::default()
+        });
+        Some(Self {
+            render_pass: ctx.new_render_pass(texture, None),
+            texture,
+            width,
+            height,
+        })
+    }
+
+    /// Rows go from the bottom, as in `get_texture_data`.
+    fn read(&self) -> LinearImage {
+        // miniquad reads `RGBA16F` textures with `GL_FLOAT`, so every channel takes 4 bytes
+        let mut bytes = vec![0; self.width as usize * self.height as usize * 16];
+        unsafe { get_internal_gl() }
+            .quad_context
+            .texture_read_pixels(self.texture, &mut bytes);
+        LinearImage::from_rgba32f(self.width as u16, self.height as u16, &bytes)
+    }
+}
+
+impl Drop for FloatRenderTarget {
+    fn drop(&mut self) {
+        // Texture is deleted with the render pass
+        unsafe { get_internal_gl() }
+            .quad_context
+            .delete_render_pass(self.render_pass);
+    }
+}
+
 struct SceneRenderer {
     scene: Scene,
     cam: RotateAroundCam,
@@ -742,12 +898,14 @@ struct SceneRenderer {
     aa_count: i32,
     aa_start: i32,
     draw_side_by_side: bool,
+    stereo_layout: StereoLayout,
+    vr_format: VrFormat,
     eye_distance: f64,
     swap_eyes: bool,
     draw_anaglyph: bool,
     anaglyph_p: f64,
     anaglyph_q: f64,
-    anaglyph_mode: bool,
+    anaglyph_mode: AnaglyphMode,
     draw_depth_map: bool,
     depth_map_min: f64,
     depth_map_max: f64,
@@ -756,17 +914,59 @@ struct SceneRenderer {
     black_border_disable: bool,
     darken_by_distance: bool,
     render_target: macroquad::prelude::RenderTarget,
+    float_render_target: Option<Option<FloatRenderTarget>>, // `None` until the first EXR frame
+    tile_offset: (f32, f32),                                // see `_tile_offset` in shader
     external_ray_render_target: macroquad::prelude::RenderTarget,
+    cpu_objects: Option<Option<Vec<CpuObject>>>, // `None` until `find_cpu_objects` in this frame
+    floor_probe: Option<(DVec3, f64, f64)>,      // camera position, scale and distance to the floor
     width: u32,
     height: u32,
     scene_name: String,
     current_fps: usize,
     current_motion_blur_frames: usize,
+    progressive: bool,
+    progressive_max_samples: u32,
+    accumulator: Option<ImageAccumulator>,
+    encoder: Encoder,
+    progress: Progress,
+    shutter: Shutter,
+    frame_format: FrameFormat,
+    hdr_max: f64,
+    output_mode: i32, // see `_output_mode` in shader
+    aux_pass: Option<AuxPass>,
+    depth_max: f64,
+    frame_selection: FrameSelection,
+    scene_hash: u64,
     texture_storage: Vec<Texture2D>,
+    meshes: BTreeMap<String, Mesh>, // by tag, for camera teleportation on CPU
+    camera_recording: Option<Vec<(f64, CalculatedCam)>>, // time in seconds and camera of every frame
+    projection_override: ProjectionOverride,
     #[cfg(not(target_arch = "wasm32"))]
     video_runtimes: Vec<VideoRuntime>,
 }
 
+/// Projection from command line, it wins over projection of scene cameras.
+#[derive(Debug, Clone, Copy, Default)]
+struct ProjectionOverride {
+    projection: Option<Projection>,
+    view_angle: Option<f64>, // radians
+    panini_param: Option<f64>,
+}
+
+impl ProjectionOverride {
+    fn apply(&self, cam: &mut RotateAroundCam) {
+        if let Some(projection) = self.projection {
+            cam.projection = projection;
+        }
+        if let Some(view_angle) = self.view_angle {
+            cam.view_angle = view_angle;
+        }
+        if let Some(panini_param) = self.panini_param {
+            cam.panini_param = panini_param;
+        }
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 struct VideoRuntime {
     /// Name of the video entry in Scene.videos; must match texture name.
@@ -1025,12 +1225,14 @@ impl SceneRenderer {
             aa_count: 1,
             aa_start: 0,
             draw_side_by_side: false,
+            stereo_layout: StereoLayout::SideBySide,
+            vr_format: VrFormat::None,
             eye_distance: 0.07,
             swap_eyes: false,
             draw_anaglyph: false,
             anaglyph_p: 0.29,
             anaglyph_q: 0.06,
-            anaglyph_mode: false,
+            anaglyph_mode: AnaglyphMode::Grayscale,
             draw_depth_map: false,
             depth_map_min: 0.,
             depth_map_max: 10.,
@@ -1039,13 +1241,33 @@ impl SceneRenderer {
             black_border_disable: false,
             darken_by_distance: true,
             render_target: macroquad::prelude::render_target(max_width, max_height),
-            external_ray_render_target: macroquad::prelude::render_target(2, 3),
+            float_render_target: None,
+            tile_offset: (0., 0.),
+            external_ray_render_target: macroquad::prelude::render_target(2, 4),
+            cpu_objects: None,
+            floor_probe: None,
             width: max_width,
             height: max_height,
             scene_name: scene_name.to_owned(),
             current_fps: 60,
             current_motion_blur_frames: 1,
+            progressive: false,
+            progressive_max_samples: 256,
+            accumulator: None,
+            encoder: Encoder::default(),
+            progress: Progress::default(),
+            shutter: Shutter::default(),
+            frame_format: FrameFormat::Png,
+            hdr_max: 16.0,
+            output_mode: 0,
+            aux_pass: None,
+            depth_max: 1000.0,
+            frame_selection: FrameSelection::default(),
+            projection_override: ProjectionOverride::default(),
+            scene_hash: 0,
             texture_storage: vec![],
+            meshes: BTreeMap::new(),
+            camera_recording: None,
             #[cfg(not(target_arch = "wasm32"))]
             video_runtimes: Vec::new(),
         };
@@ -1084,6 +1306,7 @@ impl SceneRenderer {
                     }
                 }
             }
+            self.reload_meshes().await;
             #[cfg(not(target_arch = "wasm32"))]
             {
                 // Rebuild video runtimes and immediately upload current frames
@@ -1097,6 +1320,46 @@ impl SceneRenderer {
         }
     }
 
+    async fn reload_meshes(&mut self) {
+        use portal::gui::mesh::{mesh_height_name, mesh_texture_name};
+        use portal::gui::object::Object;
+
+        let meshes = self
+            .scene
+            .tagged_objects()
+            .into_iter()
+            .filter_map(|(tag, _, object)| match object {
+                Object::Mesh { path, .. } => Some((tag, path)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for (tag, path) in meshes {
+            let mesh = match macroquad::file::load_file(&path).await {
+                Ok(bytes) => Mesh::load(&path, &bytes),
+                Err(err) => Err(format!("{:?}", err)),
+            };
+            match mesh {
+                Ok(mesh) => {
+                    let packed = mesh.pack();
+                    let texture = Texture2D::from_rgba8(packed.width, packed.height, &packed.bytes);
+                    texture.set_filter(macroquad::prelude::FilterMode::Nearest);
+                    self.material
+                        .set_texture(&mesh_texture_name(&tag), texture.clone());
+                    self.material
+                        .set_uniform(&mesh_height_name(&tag), packed.height as f32);
+                    self.texture_storage.push(texture);
+                    self.meshes.insert(tag, mesh);
+                }
+                Err(err) => {
+                    self.material.set_uniform(&mesh_height_name(&tag), 0.0f32);
+                    self.meshes.remove(&tag);
+                    portal::error!(format, "can't load mesh `{}`: {}", path, err);
+                }
+            }
+        }
+    }
+
     fn load_from_scene(
         &mut self,
         scene: Scene,
@@ -1138,27 +1401,11 @@ impl SceneRenderer {
             );
 
             let (teleported, _, change_subspace) =
-                self.teleport_external_ray(start_pos, direction_pos);
-            if let Some(new_pos) = teleported {
-                let mut res = |cam_teleport_dx: f64| -> Option<()> {
-                    result.0 = self.teleport_matrix(
-                        result.0,
-                        start_pos,
-                        direction_pos,
-                        new_pos,
-                        cam_teleport_dx,
-                    )?;
-                    if change_subspace {
-                        result.1 = !self.cam.in_subspace;
-                    }
-                    Some(())
-                };
-                if res(0.001) == None
-                    && res(0.0001) == None
-                    && res(0.00001) == None
-                    && res(0.000001) == None
-                {
-                    // ok
+                self.teleport_segment(result.0, start_pos, direction_pos);
+            if let Teleported::Yes(matrix) = teleported {
+                result.0 = matrix;
+                if change_subspace {
+                    result.1 = !self.cam.in_subspace;
                 }
             }
 
@@ -1171,6 +1418,108 @@ impl SceneRenderer {
         (self.cam.right_eye_matrix, self.cam.right_eye_in_subspace) = res2;
     }
 
+    /// Objects of `Scene::cpu_objects`, they are found once per frame, because camera is
+    /// teleported by several segments in a frame.
+    fn find_cpu_objects(&mut self) {
+        if self.cpu_objects.is_none() {
+            self.cpu_objects = Some(self.scene.cpu_objects(&mut self.data));
+        }
+    }
+
+    /// Segment from `a` to `b` traced on CPU, `None` if it can reach object with shader code.
+    fn cpu_teleport_segment(&mut self, a: DVec3, b: DVec3) -> Option<CpuTeleport> {
+        self.find_cpu_objects();
+        portal::gui::teleport::teleport_segment(
+            self.cpu_objects.as_ref()?.as_ref()?,
+            &self.meshes,
+            a,
+            b,
+            self.cam.in_subspace,
+            self.offset_after_material,
+        )
+    }
+
+    /// Teleports `matrix` that moves from `a` to `b`, also returns whether object is encountered
+    /// and whether subspace is changed. Portals of meshes and flat objects with preset
+    /// `is_inside` are crossed on CPU with exact matrices, if the segment reaches object with
+    /// shader code, then matrix is found by rays from GPU.
+    fn teleport_segment(&mut self, matrix: DMat4, a: DVec3, b: DVec3) -> (Teleported, bool, bool) {
+        if let Some(result) = self.cpu_teleport_segment(a, b) {
+            let teleported = match result.teleport {
+                Some(teleport) => Teleported::Yes(teleport * matrix),
+                None => Teleported::No,
+            };
+            return (teleported, result.encounter_at.is_some(), false);
+        }
+
+        let (teleported, encounter_at, change_subspace) = self.teleport_external_ray(a, b);
+        let teleported = match teleported {
+            Some(new_pos) => [0.001, 0.0001, 0.00001, 0.000001]
+                .into_iter()
+                .find_map(|dx| self.teleport_matrix(matrix, a, b, new_pos, dx))
+                .map(Teleported::Yes)
+                .unwrap_or(Teleported::Failed),
+            None => Teleported::No,
+        };
+        (teleported, encounter_at.is_some(), change_subspace)
+    }
+
+    /// Distance to the floor under camera at `pos`, by one segment on CPU or one ray from GPU.
+    /// Ray from GPU waits for reading of pixels, so it is skipped while camera stands still on the
+    /// floor, an animated floor with shader code is followed only after camera moves then.
+    fn probe_floor(&mut self, pos: DVec3, scale: f64, max: f64) -> Option<f64> {
+        let dir = -DVec3::Y * scale;
+        if let Some(result) = self.cpu_teleport_segment(pos, pos + dir * max) {
+            return result.encounter_at.map(|at| at * max);
+        }
+        if self.cam.on_ground {
+            if let Some((floor_pos, floor_scale, distance)) = self.floor_probe {
+                if floor_pos == pos && floor_scale == scale && distance <= max {
+                    return Some(distance);
+                }
+            }
+        }
+        self.teleport_external_ray(pos, pos + dir * max)
+            .1
+            .map(|at| at * max)
+    }
+
+    /// Walk mode: gravity and constant eye height above the floor under the camera. Distances are
+    /// in camera coordinates, so they are scaled together with the space by portals.
+    fn walk(&mut self, dt: f64) {
+        if !(self.cam.walk_mode && self.cam.free_movement) {
+            return;
+        }
+        let pos = self.cam.get_cam_pos();
+        self.cam.teleport_matrix = align_up(
+            self.cam.teleport_matrix,
+            pos,
+            RotateAroundCam::UP_ALIGN_SPEED * dt,
+        );
+
+        let scale = (self.cam.teleport_matrix * DVec4::new(0., 1., 0., 0.))
+            .truncate()
+            .length();
+        let step = fall_step(
+            self.cam.eye_height,
+            self.cam.gravity,
+            self.cam.vertical_speed,
+            dt,
+            |max| self.probe_floor(pos, scale, max),
+        );
+        self.cam.vertical_speed = step.vertical_speed;
+        self.cam.on_ground = step.on_ground;
+
+        // Gravity goes down in the world, even while up of the camera is not aligned yet
+        let delta = self.cam.teleport_matrix.inverse() * DVec4::new(0., step.dy * scale, 0., 0.);
+        self.cam.look_at += delta.truncate();
+        self.floor_probe = if step.on_ground {
+            Some((self.cam.get_cam_pos(), scale, self.cam.eye_height))
+        } else {
+            None
+        };
+    }
+
     fn teleport_matrix(
         &mut self,
         matrix: DMat4,
@@ -1226,40 +1575,24 @@ impl SceneRenderer {
         }
         let cam_pos = self.cam.get_cam_pos();
         let (teleported, encounter_object, change_subspace) =
-            self.teleport_external_ray(self.cam.prev_cam_pos, cam_pos);
+            self.teleport_segment(self.cam.teleport_matrix, self.cam.prev_cam_pos, cam_pos);
         if self.cam.stop_at_objects && encounter_object {
             self.cam = prev_cam.clone();
             return;
         }
-        if let Some(new_pos) = teleported {
-            if !self.cam.allow_teleport {
-                return;
-            }
-            let mut res = |cam_teleport_dx: f64| -> Option<()> {
-                self.cam.teleport_matrix = self.teleport_matrix(
-                    self.cam.teleport_matrix,
-                    self.cam.prev_cam_pos,
-                    cam_pos,
-                    new_pos,
-                    cam_teleport_dx,
-                )?;
-
+        match teleported {
+            Teleported::Yes(matrix) => {
+                if !self.cam.allow_teleport {
+                    return;
+                }
+                self.cam.teleport_matrix = matrix;
                 if change_subspace {
                     self.cam.in_subspace = !self.cam.in_subspace;
                 }
-
                 self.cam.prev_cam_pos = self.cam.get_cam_pos();
-                Some(())
-            };
-            if res(0.001) == None
-                && res(0.0001) == None
-                && res(0.00001) == None
-                && res(0.000001) == None
-            {
-                self.cam = prev_cam;
             }
-        } else {
-            self.cam.prev_cam_pos = cam_pos;
+            Teleported::Failed => self.cam = prev_cam,
+            Teleported::No => self.cam.prev_cam_pos = cam_pos,
         }
     }
 
@@ -1267,6 +1600,7 @@ impl SceneRenderer {
         self.cam.get_cam(&mut self.scene.cam);
         self.scene.cam.offset_after_material = self.offset_after_material;
         self.material.set_uniform("_resolution", (width, height));
+        self.material.set_uniform("_tile_offset", self.tile_offset);
         self.material
             .set_uniform("_camera", self.cam.get_matrix().as_f32());
         self.material
@@ -1291,20 +1625,42 @@ impl SceneRenderer {
             .set_uniform("_view_angle", self.cam.view_angle as f32);
         self.material
             .set_uniform("_panini_param", self.cam.panini_param as f32);
-        self.material.set_uniform(
-            "_use_panini_projection",
-            self.cam.use_panini_projection as i32,
-        );
         self.material
-            .set_uniform("_use_360_camera", self.cam.use_360_camera as i32);
+            .set_uniform("_aperture", self.cam.aperture as f32);
+        self.material
+            .set_uniform("_focus_distance", self.cam.focus_distance as f32);
         self.material
-            .set_uniform("_use_180_camera", self.cam.use_180_camera as i32);
+            .set_uniform("_projection", self.cam.projection.shader_index());
+        self.material.set_uniform(
+            "_ortho_size",
+            (self.cam.r * (self.cam.view_angle / 2.).tan()) as f32,
+        );
         self.material
             .set_uniform("_ray_tracing_depth", self.render_depth);
-        self.material.set_uniform("_aa_count", self.aa_count);
+        self.material
+            .set_uniform("_aa_count", self.effective_aa_count());
         self.material.set_uniform("_aa_start", self.aa_start);
+        self.material.set_uniform("_output_mode", self.output_mode);
+        self.material.set_uniform(
+            "_aux_pass",
+            self.aux_pass.map(AuxPass::shader_index).unwrap_or(0),
+        );
+        self.material
+            .set_uniform("_output_scale", self.output_scale());
         self.material
             .set_uniform("_draw_side_by_side", self.draw_side_by_side as i32);
+        self.material
+            .set_uniform("_stereo_layout", self.stereo_layout.shader_index());
+        self.material
+            .set_uniform("_vr_format", self.vr_format.shader_index());
+        self.material.set_uniform(
+            "_eye_distance",
+            if self.swap_eyes {
+                -self.eye_distance
+            } else {
+                self.eye_distance
+            } as f32,
+        );
         self.material
             .set_uniform("_draw_anaglyph", self.draw_anaglyph as i32);
         self.material
@@ -1312,7 +1668,7 @@ impl SceneRenderer {
         self.material
             .set_uniform("_anaglyph_q", self.anaglyph_q as f32);
         self.material
-            .set_uniform("_anaglyph_mode", self.anaglyph_mode as i32);
+            .set_uniform("_anaglyph_mode", self.anaglyph_mode.shader_index());
         self.material
             .set_uniform("_draw_depth_map", self.draw_depth_map as i32);
         self.material
@@ -1358,7 +1714,13 @@ impl SceneRenderer {
         self.material.set_uniform("_teleport_external_ray", 0);
     }
 
-    fn teleport_external_ray(&mut self, a: DVec3, b: DVec3) -> (Option<glam::DVec3>, bool, bool) {
+    /// Position after teleports, part of the segment before the encountered object, and whether
+    /// subspace is changed. Rows of the target are x, y, z and the part, see `main` in shader.
+    fn teleport_external_ray(
+        &mut self,
+        a: DVec3,
+        b: DVec3,
+    ) -> (Option<glam::DVec3>, Option<f64>, bool) {
         self.set_uniforms(0., 0.);
         self.material
             .set_uniform("_teleport_external_ray", 1 as i32);
@@ -1397,18 +1759,31 @@ impl SceneRenderer {
         let x = f32::from_le_bytes([arr[0 + 0], arr[0 + 1], arr[0 + 2], arr[0 + 4]]);
         let y = f32::from_le_bytes([arr[8 + 0], arr[8 + 1], arr[8 + 2], arr[8 + 4]]);
         let z = f32::from_le_bytes([arr[16 + 0], arr[16 + 1], arr[16 + 2], arr[16 + 4]]);
+        let encounter_at = f32::from_le_bytes([arr[24], arr[25], arr[26], arr[28]]);
+        let encounter_at = encounter_object.then_some(encounter_at.into());
         if !(x == 0.0 && y == 0.0 && z == 0.0) {
             return (
                 Some(DVec3::new(x.into(), y.into(), z.into())),
-                encounter_object,
+                encounter_at,
                 change_subspace,
             );
         } else {
-            return (None, encounter_object, change_subspace);
+            return (None, encounter_at, change_subspace);
         }
     }
 
     fn draw_texture(&mut self, width: f32, height: f32, flip_y: bool) {
+        self.draw_texture_to(width, height, flip_y, None);
+    }
+
+    /// `float_pass` replaces the render pass of `render_target`, it has the same size.
+    fn draw_texture_to(
+        &mut self,
+        width: f32,
+        height: f32,
+        flip_y: bool,
+        float_pass: Option<macroquad::miniquad::RenderPass>,
+    ) {
         let flip_y = if flip_y { -1. } else { 1. };
         self.scene.set_uniforms(&mut self.material, &mut self.data);
         self.set_uniforms(width, height);
@@ -1421,14 +1796,187 @@ impl SceneRenderer {
             render_target: Some(self.render_target.clone()),
             ..Default::default()
         });
+        if let Some(pass) = float_pass {
+            unsafe { get_internal_gl() }.quad_gl.render_pass(Some(pass));
+        }
         gl_use_material(&self.material);
-        draw_rectangle(0., 0., width, height, WHITE);
+        let size = self.render_target.texture.size();
+        draw_rectangle(0., 0., width.min(size.x), height.min(size.y), WHITE);
         gl_use_default_material();
         set_default_camera();
     }
 
+    fn output_scale(&self) -> f32 {
+        if let Some(pass) = self.aux_pass {
+            pass.output_scale(self.depth_max as f32)
+        } else if self.frame_format == FrameFormat::Exr {
+            self.hdr_max as f32
+        } else {
+            1.0
+        }
+    }
+
+    /// Draws the frame and reads it back. Frames larger than render target are drawn by tiles,
+    /// every tile sees the whole image through `_resolution` and `_tile_offset`, so projections
+    /// and side by side stereo are the same as without tiles.
+    fn draw_linear(&mut self, width: f32, height: f32) -> LinearImage {
+        let size = self.render_target.texture.size();
+        if width <= size.x && height <= size.y {
+            return self.draw_tile_linear(width, height);
+        }
+
+        let (tile_width, tile_height) = (size.x as u32, size.y as u32);
+        let mut result = LinearImage::new(width as u16, height as u16);
+        for y in tile_offsets(height as u32, tile_height) {
+            for x in tile_offsets(width as u32, tile_width) {
+                self.tile_offset = (x as f32, y as f32);
+                let tile = self.draw_tile_linear(width, height);
+                // Image is flipped while drawing, so tile with offset `y` ends at the row `height - y`
+                result.paste(&tile, x as u16, (height as u32 - y - tile_height) as u16);
+            }
+        }
+        self.tile_offset = (0., 0.);
+        result
+    }
+
+    fn draw_tile_linear(&mut self, width: f32, height: f32) -> LinearImage {
+        if !self.frame_format.is_high_precision() && self.aux_pass.is_none() {
+            self.draw_texture(width, height, true);
+            return LinearImage::from_rgba8(&self.render_target.texture.get_texture_data());
+        }
+        if self.frame_format == FrameFormat::Exr && self.aux_pass.is_none() {
+            if let Some(image) = self.draw_texture_float(width, height) {
+                return image;
+            }
+        }
+        self.draw_texture_16bit(width, height, true, self.output_scale())
+    }
+
+    /// Linear colors without upper limit, drawn once. Only for EXR, because half float has 11 bits
+    /// of precision, less than `Png16`. `None` if float render targets are not supported.
+    fn draw_texture_float(&mut self, width: f32, height: f32) -> Option<LinearImage> {
+        let size = self.render_target.texture.size();
+        let pass = self
+            .float_render_target
+            .get_or_insert_with(|| FloatRenderTarget::new(size.x as u32, size.y as u32))
+            .as_ref()?
+            .render_pass;
+        self.output_mode = 3;
+        self.draw_texture_to(width, height, true, Some(pass));
+        self.output_mode = 0;
+        self.float_render_target
+            .as_ref()
+            .and_then(Option::as_ref)
+            .map(FloatRenderTarget::read)
+    }
+
+    /// 8-bit render target is read twice for high precision formats without float render target,
+    /// auxiliary passes and progressive samples: first with high bytes of 16-bit values, then with
+    /// low bytes.
+    fn draw_texture_16bit(
+        &mut self,
+        width: f32,
+        height: f32,
+        flip_y: bool,
+        scale: f32,
+    ) -> LinearImage {
+        let [high, low] = [1, 2].map(|mode| {
+            self.output_mode = mode;
+            self.draw_texture(width, height, flip_y);
+            self.render_target.texture.get_texture_data()
+        });
+        self.output_mode = 0;
+        LinearImage::from_rgba8_passes(&high, &low, scale)
+    }
+
+    /// Draws raw values of auxiliary pass with one sample per pixel, because averaged ids and
+    /// normals are meaningless.
+    fn draw_aux_pass(&mut self, pass: AuxPass, width: f32, height: f32) -> LinearImage {
+        let (aa_count, aa_start) = (self.aa_count, self.aa_start);
+        self.aa_count = 1;
+        self.aa_start = 0;
+        self.aux_pass = Some(pass);
+        let mut image = self.draw_linear(width, height);
+        self.aux_pass = None;
+        self.aa_count = aa_count;
+        self.aa_start = aa_start;
+        pass.decode(&mut image);
+        image
+    }
+
+    /// Sets focus distance to depth of pixel `x`, `y` counted from the top left corner of the frame.
+    /// Returns `false` if nothing is hit there.
+    fn pick_focus(&mut self, x: f32, y: f32, width: f32, height: f32) -> bool {
+        let depth = self.draw_aux_pass(AuxPass::Depth, width, height);
+        // Rows of image go from the bottom
+        let value = (depth.height as usize)
+            .checked_sub(y as usize + 1)
+            .and_then(|row| depth.pixels.get(row * depth.width as usize + x as usize))
+            .map(|px| px[0])
+            .unwrap_or(0.0);
+        if value <= 0.0 {
+            return false;
+        }
+        self.cam.focus_distance = value as f64;
+        true
+    }
+
+    /// Dispersive materials trace one color channel per sample, so count of samples in one pass is
+    /// rounded up to a multiple of 3, then every pixel gets all channels equally and the boosted
+    /// channels are averaged before the result is clamped.
+    fn effective_aa_count(&self) -> i32 {
+        if self.aux_pass.is_none() && !self.data.disable_antialiasing && self.scene.has_dispersion()
+        {
+            (self.aa_count + 2) / 3 * 3
+        } else {
+            self.aa_count
+        }
+    }
+
+    /// Draws next `aa_count` samples and writes average of all samples since last reset into
+    /// `render_target`. Nothing in scene or camera should change between calls without
+    /// `reset_accumulation`.
+    fn draw_texture_progressive(&mut self, width: f32, height: f32) {
+        if self.accumulator.is_some() && !self.needs_more_samples() {
+            // Render target already has the average, reading back again would only cost time
+            return;
+        }
+        let sample = self.accumulator.as_ref().map(|a| a.count()).unwrap_or(0);
+        let aa_start = self.aa_start;
+        self.aa_start = aa_start + sample as i32 * self.effective_aa_count();
+        // Samples are kept with 16-bit precision, 8-bit ones would leave banding after averaging
+        let image = self.draw_texture_16bit(width, height, false, 1.0);
+        self.aa_start = aa_start;
+
+        if !self
+            .accumulator
+            .as_ref()
+            .map(|a| a.is_same_size(&image))
+            .unwrap_or(false)
+        {
+            self.accumulator = Some(ImageAccumulator::new(image.width, image.height));
+        }
+        let accumulator = self.accumulator.as_mut().unwrap();
+        accumulator.add(&image);
+        // Render target has low bytes of the last pass
+        self.render_target
+            .texture
+            .update(&accumulator.average().to_rgba8());
+    }
+
+    fn reset_accumulation(&mut self) {
+        self.accumulator = None;
+    }
+
+    fn needs_more_samples(&self) -> bool {
+        self.progressive
+            && self.accumulator.as_ref().map(|a| a.count()).unwrap_or(0)
+                < self.progressive_max_samples
+    }
+
     fn update(&mut self, memory: &mut egui::Memory, time: f64) {
         self.scene.update(memory, &mut self.data, time);
+        self.cpu_objects = None;
         if self.cam.send_camera_object_matrix {
             self.data
                 .formulas_cache
@@ -1469,6 +2017,16 @@ impl SceneRenderer {
             self.cam.teleport_matrix = calculated_cam.matrix;
             self.cam.in_subspace = calculated_cam.in_subspace;
             self.cam.free_movement = calculated_cam.free_movement;
+            self.cam.aperture = calculated_cam.aperture;
+            self.cam.focus_distance = calculated_cam.focus_distance;
+            self.cam.projection = calculated_cam.projection;
+            self.cam.view_angle = calculated_cam.view_angle;
+            self.cam.panini_param = calculated_cam.panini_param;
+            self.cam.followed_matrix = if current_cam.is_some() && calculated_cam.follows_matrix {
+                Some(calculated_cam.matrix)
+            } else {
+                None
+            };
 
             if self.cam.free_movement {
                 self.cam.look_at = self.cam.get_pos_vec() + self.cam.look_at;
@@ -1482,11 +2040,28 @@ impl SceneRenderer {
             if !self.cam.free_movement {
                 self.cam.look_at = calculated_cam.look_at;
             }
-        }
 
-        if memory
-            .data
-            .get_persisted::<bool>(egui::Id::new("do_not_teleport_one_frame"))
+            if calculated_cam.follows_matrix {
+                // Camera rides with the matrix, portals crossed after selecting the camera are kept
+                if let Some(followed) = self.cam.followed_matrix {
+                    if followed != calculated_cam.matrix {
+                        self.cam.teleport_matrix =
+                            self.cam.teleport_matrix * followed.inverse() * calculated_cam.matrix;
+                        self.cam.do_not_teleport_one_frame = true;
+                    }
+                } else {
+                    self.cam.teleport_matrix = calculated_cam.matrix;
+                    self.cam.do_not_teleport_one_frame = true;
+                }
+                self.cam.followed_matrix = Some(calculated_cam.matrix);
+            } else {
+                self.cam.followed_matrix = None;
+            }
+        }
+
+        if memory
+            .data
+            .get_persisted::<bool>(egui::Id::new("do_not_teleport_one_frame"))
             .unwrap_or(false)
         {
             self.cam.do_not_teleport_one_frame = true;
@@ -1504,6 +2079,11 @@ impl SceneRenderer {
             self.cam.r = override_cam.r;
             self.cam.look_at = override_cam.look_at;
             self.cam.free_movement = override_cam.free_movement;
+            self.cam.aperture = override_cam.aperture;
+            self.cam.focus_distance = override_cam.focus_distance;
+            self.cam.projection = override_cam.projection;
+            self.cam.view_angle = override_cam.view_angle;
+            self.cam.panini_param = override_cam.panini_param;
 
             if override_cam.override_matrix {
                 self.cam.teleport_matrix = override_cam.matrix;
@@ -1515,10 +2095,18 @@ impl SceneRenderer {
                 .data
                 .remove::<CalculatedCam>(egui::Id::new("OverrideCam"));
         }
+        self.projection_override.apply(&mut self.cam);
 
         if self.cam.get_matrix() != self.prev_cam.get_matrix() {
             self.teleport_camera(self.prev_cam.clone());
         }
+        if self.cam.walk_mode {
+            let before_walk = self.cam.clone();
+            self.walk((get_frame_time() as f64).min(0.1));
+            if self.cam.get_matrix() != before_walk.get_matrix() {
+                self.teleport_camera(before_walk);
+            }
+        }
         self.teleport_eye_matrices();
         self.prev_cam = self.cam.clone();
 
@@ -1527,6 +2115,12 @@ impl SceneRenderer {
             self.cam.get_calculated_cam(),
         );
 
+        if let Some(recording) = &mut self.camera_recording {
+            if recording.last().map(|(t, _)| *t < time).unwrap_or(true) {
+                recording.push((time, self.cam.get_calculated_cam()));
+            }
+        }
+
         if self.cam.send_camera_object_matrix {
             self.data
                 .formulas_cache
@@ -1543,6 +2137,47 @@ impl SceneRenderer {
         }
     }
 
+    fn egui_camera_recording(&mut self, ui: &mut Ui) -> WhatChanged {
+        let mut changed = WhatChanged::default();
+        let result_id = egui::Id::new("CameraRecordingResult");
+        match &self.camera_recording {
+            None => {
+                if ui.button("⏺ Record camera").clicked() {
+                    self.camera_recording = Some(Vec::new());
+                }
+                ui.label("Every frame is recorded, including teleports, and saved as camera path with keyframes where motion changes, and animation that moves along it.");
+            }
+            Some(recording) => {
+                let frames = recording.len();
+                let duration = recording
+                    .first()
+                    .zip(recording.last())
+                    .map(|((start, _), (end, _))| end - start)
+                    .unwrap_or(0.);
+                let mut stop = false;
+                ui.horizontal(|ui| {
+                    stop = ui.button("⏹ Stop and save").clicked();
+                    ui.label(format!("{} frames, {:.1} s", frames, duration));
+                });
+                if stop {
+                    let recording = self.camera_recording.take().unwrap();
+                    let result = match self.scene.add_camera_recording(&recording) {
+                        Some(name) => {
+                            changed.uniform = true;
+                            format!("Saved as animation `{}`", name)
+                        }
+                        None => "Recording is too short".to_owned(),
+                    };
+                    ui.memory_mut(|memory| memory.data.insert_temp(result_id, result));
+                }
+            }
+        }
+        if let Some(result) = ui.memory(|memory| memory.data.get_temp::<String>(result_id)) {
+            ui.label(result);
+        }
+        changed
+    }
+
     fn egui_rendering_settings(&mut self, ui: &mut Ui) -> WhatChanged {
         let mut changed = WhatChanged::default();
         ui.label("3D rendering options:");
@@ -1552,10 +2187,24 @@ impl SceneRenderer {
             changed.uniform |= ui
                 .checkbox(&mut self.draw_anaglyph, "Draw anaglyph")
                 .changed();
-            ui.label("By default anaglyph is grayscale for better visuals. Colorful anaglyph may produce a lot of ghosting.");
-            changed.uniform |= ui
-                .checkbox(&mut self.anaglyph_mode, "Colorful anaglyph")
-                .changed();
+            ui.label("By default anaglyph is grayscale for better visuals. Colorful anaglyph may produce a lot of ghosting. Dubois modes are calibrated for typical glasses of their colors.");
+            egui::ComboBox::from_label("Anaglyph mode")
+                .selected_text(self.anaglyph_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in [
+                        AnaglyphMode::Grayscale,
+                        AnaglyphMode::Colorful,
+                        AnaglyphMode::DuboisRedCyan,
+                        AnaglyphMode::DuboisGreenMagenta,
+                        AnaglyphMode::DuboisAmberBlue,
+                    ] {
+                        changed.uniform |= ui
+                            .selectable_value(&mut self.anaglyph_mode, mode, mode.name())
+                            .changed();
+                    }
+                });
+        }
+        if !self.data.disable_anaglyph && self.anaglyph_mode.uses_ghosting_compensation() {
             ui.label("If you have ghosting on your anaglyph glasses (you can see other's eye image), you can tweaks these two values to get minimal ghosting. Note that blue lens may have no ghosting at all, but red lens may have a bit. Also note that ghosting may always be presented on pitch black background (in pocket dimension for example). So, anaglyph works best in room scenes.");
             ui.horizontal(|ui| {
                 ui.label("Anaglyph P (red lens):");
@@ -1581,8 +2230,30 @@ impl SceneRenderer {
             });
         }
         changed.uniform |= ui
-            .checkbox(&mut self.draw_side_by_side, "Draw side-by-side")
+            .checkbox(&mut self.draw_side_by_side, "Draw stereo pair")
             .changed();
+        ui.horizontal(|ui| {
+            ui.label("Stereo layout:");
+            for layout in StereoLayout::ALL {
+                changed.uniform |= ui
+                    .radio_value(&mut self.stereo_layout, layout, layout.name())
+                    .changed();
+            }
+        });
+        ui.label("(Interlaced layouts are for passive 3D monitors, enable \"Swap eyes\" if the monitor starts with the other eye)");
+        ui.horizontal(|ui| {
+            ui.label("VR video:");
+            for format in [
+                VrFormat::None,
+                VrFormat::Ods360TopBottom,
+                VrFormat::Vr180SideBySide,
+            ] {
+                changed.uniform |= ui
+                    .radio_value(&mut self.vr_format, format, format.name())
+                    .changed();
+            }
+        });
+        ui.label("(VR video replaces the camera projection and side-by-side. Use 1:1 frame for ODS 360 and 2:1 frame for VR180)");
         ui.label("Disable \"Swap eyes\" when you render video. Enable it when you want to look at 3D image with your eyes crossed.");
         changed.uniform |= ui.checkbox(&mut self.swap_eyes, "Swap eyes").changed();
         ui.horizontal(|ui| {
@@ -1638,6 +2309,28 @@ impl SceneRenderer {
                         .clamping(egui::widgets::SliderClamping::Always),
                 );
             });
+            ui.separator();
+            changed.uniform |= ui
+                .checkbox(&mut self.progressive, "Progressive rendering")
+                .changed();
+            ui.label("(While nothing changes, new jittered samples are added each frame and averaged. Needed for PBR materials to converge. Every sample is drawn twice for 16-bit precision and read back from GPU until max samples are reached, so these frames are slower)");
+            if self.progressive {
+                ui.horizontal(|ui| {
+                    ui.label("Max samples:");
+                    changed.uniform |= check_changed(&mut self.progressive_max_samples, |count| {
+                        ui.add(
+                            egui::Slider::new(count, 1..=4096)
+                                .logarithmic(true)
+                                .clamping(egui::widgets::SliderClamping::Always),
+                        );
+                    });
+                });
+                let samples = self.accumulator.as_ref().map(|a| a.count()).unwrap_or(0);
+                ui.label(format!(
+                    "Accumulated samples: {}",
+                    samples as i32 * self.effective_aa_count()
+                ));
+            }
         }
         ui.separator();
         ui.label("Offset after material:");
@@ -1755,6 +2448,27 @@ impl SceneRenderer {
         Some(())
     }
 
+    /// Renders sub-frames inside the shutter interval of the frame at `time`, returns them with
+    /// their weights.
+    fn render_sub_frames(
+        &mut self,
+        memory: &mut egui::Memory,
+        time: f64,
+        frame_duration: f64,
+        sub_frames: usize,
+        width: u32,
+        height: u32,
+    ) -> Vec<(LinearImage, f64)> {
+        let mut result = Vec::new();
+        for (j, (offset, weight)) in self.shutter.samples(sub_frames).into_iter().enumerate() {
+            self.aa_start = j as i32;
+            // Trailing shutter of the first frame would look before the animation start
+            self.update(memory, (time + offset * frame_duration).max(0.0));
+            result.push((self.draw_linear(width as f32, height as f32), weight));
+        }
+        result
+    }
+
     fn render_animation(
         &mut self,
         duration_seconds: f32,
@@ -1765,125 +2479,165 @@ impl SceneRenderer {
         width: u32,
         height: u32,
         skip_existing: bool,
-    ) {
-        let animation_start = std::time::Instant::now();
-
-        if matches!(
-            std::fs::exists(&format!("video/{}.mov", output_name)),
-            Ok(true)
-        ) {
-            if skip_existing {
-                println!("Skip `{output_name}`, because it's already exists");
-                return;
-            }
+    ) -> Result<RenderOutcome, String> {
+        let output_path = std::path::Path::new("video").join(output_name);
+        if skip_existing && self.encoder.output_path(&output_path).exists() {
+            self.progress.message(&format!(
+                "Skip `{output_name}`, because it's already exists"
+            ));
+            return Ok(RenderOutcome::Skipped);
         }
 
-        let output_path = std::path::Path::new("video").join(output_name);
         if let Some(parent) = output_path.parent() {
-            std::fs::create_dir_all(parent).unwrap();
+            std::fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create `{}`: {err}", parent.display()))?;
         }
-        drop(std::fs::create_dir("anim"));
         let count = ((duration_seconds * fps as f32) as usize).max(1);
-        let exposure = 0.5; // number from 0 to 1
-        for i in 0..count {
-            let name = format!("anim/frame_{i}.png");
-            if std::fs::exists(&name).unwrap() {
+        let settings = RenderSettings {
+            scene_hash: self.scene_hash,
+            width,
+            height,
+            fps,
+            motion_blur_frames,
+            aa_count: self.aa_count,
+            render_depth: self.render_depth,
+            side_by_side: self.draw_side_by_side,
+            frame_count: count,
+            shutter: self.shutter,
+            frame_format: self.frame_format,
+            hdr_max: self.output_scale(),
+            vr_format: self.vr_format,
+            stereo_layout: self.stereo_layout,
+            anaglyph: Some(self.anaglyph_mode).filter(|_| self.draw_anaglyph),
+        };
+        let work_dir = WorkDir::new(output_name, self.frame_format.extension());
+        let mut manifest = RenderManifest {
+            completed: work_dir.completed_frames(&settings)?,
+            settings,
+            owner: Some(ProcessOwner::current()),
+        };
+        let frames_dir = work_dir.frames_dir();
+        std::fs::create_dir_all(&frames_dir)
+            .map_err(|err| format!("Failed to create `{}`: {err}", frames_dir.display()))?;
+
+        let frame_duration = duration_seconds as f64 / count as f64;
+        let selection = self.frame_selection;
+        let selected_count = selection.frames(count).count();
+        for (done, i) in selection.frames(count).enumerate() {
+            if manifest.completed.contains(&i) {
                 continue;
             }
 
-            let mut images = vec![];
-            for j in 0..motion_blur_frames {
-                let t = (i as f64 / count as f64)
-                    + j as f64 / motion_blur_frames as f64 / count as f64 * exposure;
-                self.aa_start = j as i32;
-                self.update(memory, t * duration_seconds as f64);
-                self.draw_texture(width as f32, height as f32, true);
+            let frame_start = std::time::Instant::now();
+            self.progress.report(ProgressEvent::FrameStarted {
+                output: output_name,
+                frame: i,
+                count,
+            });
 
-                if i == 0 && j == 0 {
-                    self.render_target
-                        .texture
-                        .get_texture_data()
-                        .export_png(&format!("video/{output_name}.start.png"));
-                }
+            let images = self.render_sub_frames(
+                memory,
+                i as f64 * frame_duration,
+                frame_duration,
+                motion_blur_frames,
+                width,
+                height,
+            );
 
-                if i == count - 1 && j == motion_blur_frames - 1 {
-                    self.render_target
-                        .texture
-                        .get_texture_data()
-                        .export_png(&format!("video/{output_name}.end.png"));
-                }
+            if i == 0 {
+                save_image_png(
+                    std::path::Path::new(&format!("video/{output_name}.start.png")),
+                    &images[0].0.to_rgba8(),
+                )?;
+            }
 
-                images.push(self.render_target.texture.get_texture_data());
+            if i == count - 1 {
+                save_image_png(
+                    std::path::Path::new(&format!("video/{output_name}.end.png")),
+                    &images[images.len() - 1].0.to_rgba8(),
+                )?;
             }
+
             let result = std::hint::black_box(average_images(images));
 
-            result.export_png(&name);
-
-            print!("\r{i}/{count} done      ");
-
-            use std::io::Write;
-            std::io::stdout().lock().flush().unwrap();
-        }
-        println!();
-
-        println!("Start ffmpeg to render video");
-        drop(std::fs::create_dir("video"));
-        let ffmpeg_start = std::time::Instant::now();
-        let command = std::process::Command::new("ffmpeg")
-        .arg("-framerate").arg(fps.to_string())
-        .arg("-i").arg("anim/frame_%d.png")
-
-        // Keep full-range; tag/convert explicitly to sRGB in YUV420 10-bit
-        .arg("-vf").arg("zscale=primariesin=bt709:transferin=iec61966-2-1:matrixin=bt709:rangein=full:primaries=bt709:transfer=iec61966-2-1:matrix=bt709:range=full,format=yuv420p10le")
-
-        .arg("-c:v").arg("libx265")
-        .arg("-pix_fmt").arg("yuv420p10le")
-        .arg("-crf").arg("15")
-        .arg("-preset").arg("slow")
-
-        // Bitstream + container tags (sRGB transfer)
-        .arg("-x265-params").arg("colorprim=bt709:transfer=iec61966-2-1:colormatrix=bt709:range=full")
-        .arg("-colorspace").arg("bt709")
-        .arg("-color_primaries").arg("bt709")
-        .arg("-color_trc").arg("iec61966-2-1")
-        .arg("-color_range").arg("pc")
-
-        // MOV tends to keep colr atom; hvc1 tag improves compatibility
-        .arg("-movflags").arg("+write_colr+faststart")
-        .arg("-tag:v").arg("hvc1")
-
-        .arg("-y")
-        .arg(format!("video/{output_name}.mov"))
-        .output()
-        .expect("failed to execute ffmpeg");
-        let ffmpeg_elapsed = ffmpeg_start.elapsed();
-        std::fs::remove_dir_all("anim").unwrap();
-        if command.status.code() != Some(0) {
-            println!(
-                "ffmpeg error:\n{}\n\n{}",
-                std::str::from_utf8(&command.stdout).unwrap(),
-                std::str::from_utf8(&command.stderr).unwrap()
-            );
+            save_frame(&work_dir.frame_path(i), &result, self.frame_format)?;
+            manifest.completed.insert(i);
+            work_dir.save_manifest(&selection, &manifest)?;
+
+            self.progress.report(ProgressEvent::FrameDone {
+                output: output_name,
+                frame: i,
+                done: done + 1,
+                count: selected_count,
+                seconds: frame_start.elapsed().as_secs_f64(),
+            });
         }
-        println!("ffmpeg status: {}", command.status);
-        println!("ffmpeg time: {:?}", ffmpeg_elapsed);
-        println!(
-            "Finished `{output_name}` in {:?}",
-            animation_start.elapsed()
-        );
+
+        // Other processes could finish their shards meanwhile
+        let completed = work_dir.completed_frames(&manifest.settings)?;
+        let remaining = (0..count).filter(|i| !completed.contains(i)).count();
+        if remaining != 0 {
+            self.progress.message(&format!(
+                "{remaining} of {count} frames of `{output_name}` are not rendered yet, video will be encoded by the process that renders the last of them"
+            ));
+            return Ok(RenderOutcome::Partial);
+        }
+        let Some(lock) = work_dir.try_lock_encoding()? else {
+            self.progress.message(&format!(
+                "`{output_name}` is already encoded by another process"
+            ));
+            return Ok(RenderOutcome::Partial);
+        };
+        // Process that held the lock before has encoded the video and removed frames
+        if !work_dir.path.exists() {
+            self.progress.message(&format!(
+                "`{output_name}` is already encoded by another process"
+            ));
+            return Ok(RenderOutcome::Partial);
+        }
+
+        self.progress.report(ProgressEvent::EncoderStarted {
+            output: output_name,
+        });
+        let encoding_start = std::time::Instant::now();
+        match self.encoder.encode(
+            &frames_dir,
+            self.frame_format.extension(),
+            fps,
+            &output_path,
+        ) {
+            Ok(path) => {
+                self.progress.report(ProgressEvent::EncoderDone {
+                    output: output_name,
+                    path: &path.to_string_lossy(),
+                    seconds: encoding_start.elapsed().as_secs_f64(),
+                });
+                work_dir.remove(lock)?;
+            }
+            Err(err) => {
+                drop(lock);
+                let err = format!("{err}\nFrames are kept in `{}`", frames_dir.display());
+                self.progress.report(ProgressEvent::EncoderFailed {
+                    output: output_name,
+                    error: &err,
+                });
+                return Err(err);
+            }
+        }
+        Ok(RenderOutcome::Rendered)
     }
 
+    /// Videos are written into `video/{output_dir}/{animation_name}`.
     fn render_named_animations(
         &mut self,
         animation_names: &[String],
+        output_dir: &str,
         fps: usize,
         motion_blur_frames: usize,
         skip_existing: bool,
-    ) -> Result<(), String> {
+    ) -> Result<Vec<RenderOutcome>, String> {
         let mut memory = egui::Memory::default();
-
-        drop(std::fs::create_dir("video"));
-        drop(std::fs::create_dir(format!("video/{}", self.scene_name)));
+        let mut outcomes = Vec::new();
 
         for (i, animation_name) in animation_names.iter().enumerate() {
             if self
@@ -1908,25 +2662,33 @@ impl SceneRenderer {
             self.current_motion_blur_frames = motion_blur_frames;
             self.update_inner_variables(animation_name);
 
-            println!(
+            self.progress.message(&format!(
                 "Rendering animation {animation_name}, {}/{}",
                 i + 1,
                 animation_names.len()
-            );
+            ));
 
-            self.render_animation(
+            let animation_start = std::time::Instant::now();
+            let output_name = format!("{output_dir}/{animation_name}");
+            let outcome = self.render_animation(
                 duration as f32,
                 self.current_fps,
                 self.current_motion_blur_frames,
-                &format!("{}/{}", self.scene_name, animation_name),
+                &output_name,
                 &mut memory,
                 self.width,
                 self.height,
                 skip_existing,
-            );
+            )?;
+            self.progress.report(ProgressEvent::Finished {
+                output: &output_name,
+                outcome,
+                seconds: animation_start.elapsed().as_secs_f64(),
+            });
+            outcomes.push(outcome);
         }
 
-        Ok(())
+        Ok(outcomes)
     }
 
     fn render_all_animations(
@@ -1935,43 +2697,26 @@ impl SceneRenderer {
         motion_blur_frames: usize,
         skip_existing: bool,
         starts_with: Option<&str>,
-    ) {
-        let mut memory = egui::Memory::default();
-
-        drop(std::fs::create_dir("video"));
-        drop(std::fs::create_dir(format!("video/{}", self.scene_name)));
-
-        let len = self.scene.animations_len();
-        for i in 0..len {
-            self.scene
-                .init_animation_by_position(i, &mut memory)
-                .unwrap();
-            self.update(&mut memory, 0.);
-            let duration = self.scene.get_current_animation_duration().unwrap();
-
-            let name = self.scene.get_current_animation_name().unwrap().to_owned();
-            self.current_fps = fps;
-            self.current_motion_blur_frames = motion_blur_frames;
-            self.update_inner_variables(&name);
-
-            if let Some(starts_with) = starts_with {
-                if !name.starts_with(starts_with) {
-                    continue;
-                }
-            }
-            println!("Rendering animation {name}, {i}/{len}");
-
-            self.render_animation(
-                duration as f32,
-                self.current_fps,
-                self.current_motion_blur_frames,
-                &format!("{}/{}", self.scene_name, name),
-                &mut memory,
-                self.width,
-                self.height,
-                skip_existing,
-            );
-        }
+    ) -> Result<Vec<RenderOutcome>, String> {
+        let animation_names = self
+            .scene
+            .animations
+            .visible_elements()
+            .map(|(_, name)| name.to_owned())
+            .filter(|name| {
+                starts_with
+                    .map(|starts_with| name.starts_with(starts_with))
+                    .unwrap_or(true)
+            })
+            .collect::<Vec<_>>();
+        let output_dir = self.scene_name.clone();
+        self.render_named_animations(
+            &animation_names,
+            &output_dir,
+            fps,
+            motion_blur_frames,
+            skip_existing,
+        )
     }
 }
 
@@ -2525,6 +3270,8 @@ First, predefined library is included, then uniforms, then user library, then in
                 .vscroll(true)
                 .show(ctx, |ui| {
                     changed |= self.renderer.cam.egui(ui);
+                    ui.separator();
+                    changed |= self.renderer.egui_camera_recording(ui);
                 });
             self.camera_settings_opened = camera_settings_opened;
         }
@@ -2590,6 +3337,24 @@ First, predefined library is included, then uniforms, then user library, then in
         let mouse_over_canvas = !ctx.wants_pointer_input() && !ctx.is_pointer_over_area();
         let egui_using_keyboard = ctx.wants_keyboard_input();
 
+        if self.renderer.cam.pick_focus
+            && mouse_over_canvas
+            && is_mouse_button_pressed(MouseButton::Left)
+        {
+            if let Some(viewport) = self.scene_viewport {
+                let (x, y) = mouse_position();
+                // Click on empty space keeps picking mode
+                let picked = self.renderer.pick_focus(
+                    (x - viewport.x) * self.render_scale,
+                    (y - viewport.y) * self.render_scale,
+                    (viewport.width * self.render_scale).max(1.0),
+                    (viewport.height * self.render_scale).max(1.0),
+                );
+                self.renderer.cam.pick_focus = !picked;
+                changed.uniform = true;
+            }
+        }
+
         self.renderer.cam.current_dpi = ctx.pixels_per_point() as f64;
         self.renderer.cam.current_render_scale = self.render_scale;
         self.renderer.cam.is_something_changed = false;
@@ -2659,6 +3424,13 @@ First, predefined library is included, then uniforms, then user library, then in
 
         self.scene_viewport = Some(new_viewport);
 
+        if is_something_changed || changed.shader {
+            self.renderer.reset_accumulation();
+        }
+        if self.renderer.needs_more_samples() {
+            is_something_changed = true;
+        }
+
         is_something_changed
     }
 
@@ -2673,8 +3445,13 @@ First, predefined library is included, then uniforms, then user library, then in
         let render_width = (viewport.width * self.render_scale).max(1.0);
         let render_height = (viewport.height * self.render_scale).max(1.0);
 
-        self.renderer
-            .draw_texture(render_width, render_height, false);
+        if self.renderer.progressive {
+            self.renderer
+                .draw_texture_progressive(render_width, render_height);
+        } else {
+            self.renderer
+                .draw_texture(render_width, render_height, false);
+        }
 
         draw_texture_ex(
             &self.renderer.render_target.texture,
@@ -2719,6 +3496,7 @@ struct Cli {
 enum CliCommand {
     Render(RenderCliOptions),
     RenderFrame(RenderFrameCliOptions),
+    RenderJobs(RenderJobsCliOptions),
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -2752,6 +3530,63 @@ struct RenderFrameCliOptions {
 
     #[arg(long, alias = "render_depth", default_value_t = 100)]
     render_depth: i32,
+
+    /// Sub-frames inside the shutter interval, for motion trails on a still
+    #[arg(long, alias = "motion_blur_frames", default_value_t = 1)]
+    motion_blur_frames: usize,
+
+    /// Frame duration for the shutter interval is `1 / fps`
+    #[arg(long, default_value_t = 60)]
+    fps: usize,
+
+    #[command(flatten)]
+    shutter: ShutterCliOptions,
+
+    #[arg(
+        long = "frame-format",
+        alias = "frame_format",
+        value_enum,
+        default_value_t = FrameFormat::Png
+    )]
+    frame_format: FrameFormat,
+
+    /// Linear color that becomes the brightest value in EXR frames, brighter colors are clipped.
+    /// Only without float render target, it's used on desktop OpenGL 3+
+    #[arg(long = "hdr-max", alias = "hdr_max", default_value_t = 16.0)]
+    hdr_max: f64,
+
+    /// Maximum side of render target. Larger frames are rendered by tiles and stitched, for
+    /// resolutions beyond the GPU texture limit
+    #[arg(
+        long = "tile-size",
+        alias = "tile_size",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    tile_size: Option<u32>,
+
+    /// Raw auxiliary images, written as `{output}.{pass}.exr` near the output, e.g.
+    /// `--passes depth,normal,object_id,material_id,portal_hops`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    passes: Vec<AuxPass>,
+
+    /// Largest distance stored in the depth pass
+    #[arg(long = "depth-max", alias = "depth_max", default_value_t = 1000.0)]
+    depth_max: f64,
+
+    /// `json` prints one JSON object per line for every frame, encoder status and final summary
+    #[arg(long, value_enum, default_value_t = ProgressFormat::Text)]
+    progress: ProgressFormat,
+
+    /// Camera projection, e.g. `cubemap` with 6:1 frame of six faces
+    #[arg(long, value_enum)]
+    projection: Option<Projection>,
+
+    /// View angle in degrees, for fisheye it is the angle of the image circle
+    #[arg(long = "view-angle", alias = "view_angle")]
+    view_angle: Option<f64>,
+
+    #[arg(long = "panini-param", alias = "panini_param")]
+    panini_param: Option<f64>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -2781,6 +3616,23 @@ struct RenderCliOptions {
     )]
     stereo_image: bool,
 
+    /// Placement of eyes in the frame with `--stereoimage`, `--width` and `--height` are of one eye
+    #[arg(
+        long = "stereo-layout",
+        alias = "stereo_layout",
+        value_enum,
+        default_value_t = StereoLayout::SideBySide
+    )]
+    stereo_layout: StereoLayout,
+
+    /// Mixes both eyes into one image for colored glasses
+    #[arg(long, value_enum, conflicts_with_all = ["stereo_image", "vr"])]
+    anaglyph: Option<AnaglyphMode>,
+
+    /// Stereo equirectangular video for headsets, `--width` and `--height` are of the whole frame
+    #[arg(long, value_enum, default_value_t = VrFormat::None, conflicts_with = "stereo_image")]
+    vr: VrFormat,
+
     #[arg(
         long = "no-skip-existing",
         alias = "no_skip_existing",
@@ -2802,58 +3654,344 @@ struct RenderCliOptions {
 
     #[arg(long, alias = "render_depth", default_value_t = 150)]
     render_depth: i32,
+
+    #[arg(long, value_enum, default_value_t = EncoderPreset::Hevc)]
+    encoder: EncoderPreset,
+
+    /// Custom ffmpeg arguments instead of encoder preset, `{fps}`, `{input}` and `{output}` are substituted.
+    /// For EXR frames `-apply_trc iec61966_2_1` is added before `-i {input}`
+    #[arg(long = "ffmpeg-template", alias = "ffmpeg_template")]
+    ffmpeg_template: Option<String>,
+
+    /// Render only frames `start..end` of each animation, end is exclusive
+    #[arg(long)]
+    frames: Option<FrameRange>,
+
+    /// Render only k-th of n interleaved parts of each animation, so several processes can split the work.
+    /// Video is encoded by the process that finishes the last frame
+    #[arg(long)]
+    shard: Option<Shard>,
+
+    #[command(flatten)]
+    shutter: ShutterCliOptions,
+
+    #[arg(
+        long = "frame-format",
+        alias = "frame_format",
+        value_enum,
+        default_value_t = FrameFormat::Png
+    )]
+    frame_format: FrameFormat,
+
+    /// Linear color that becomes the brightest value in EXR frames, brighter colors are clipped.
+    /// Only without float render target, it's used on desktop OpenGL 3+
+    #[arg(long = "hdr-max", alias = "hdr_max", default_value_t = 16.0)]
+    hdr_max: f64,
+
+    /// Maximum side of render target. Larger frames are rendered by tiles and stitched, for
+    /// resolutions beyond the GPU texture limit
+    #[arg(
+        long = "tile-size",
+        alias = "tile_size",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    tile_size: Option<u32>,
+
+    /// `json` prints one JSON object per line for every frame, encoder status and final summary
+    #[arg(long, value_enum, default_value_t = ProgressFormat::Text)]
+    progress: ProgressFormat,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Args)]
+struct ShutterCliOptions {
+    /// Part of the frame duration when shutter is open, in degrees from 0 to 360
+    #[arg(
+        long = "shutter-angle",
+        alias = "shutter_angle",
+        default_value_t = 180.0
+    )]
+    shutter_angle: f64,
+
+    #[arg(
+        long = "shutter-offset",
+        alias = "shutter_offset",
+        value_enum,
+        default_value_t = ShutterOffset::Leading
+    )]
+    shutter_offset: ShutterOffset,
+
+    /// Weights of motion blur sub-frames
+    #[arg(
+        long = "shutter-kernel",
+        alias = "shutter_kernel",
+        value_enum,
+        default_value_t = ShutterKernel::Box
+    )]
+    shutter_kernel: ShutterKernel,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ShutterCliOptions {
+    fn shutter(&self) -> Result<Shutter, String> {
+        let shutter = Shutter {
+            angle: self.shutter_angle,
+            offset: self.shutter_offset,
+            kernel: self.shutter_kernel,
+        };
+        shutter.check()?;
+        Ok(shutter)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RenderCliOptions {
+    /// Options as if only scene was given in the command line.
+    fn with_defaults(scenes: &str) -> Self {
+        #[derive(Parser)]
+        struct Wrapper {
+            #[command(flatten)]
+            options: RenderCliOptions,
+        }
+        Wrapper::parse_from(["render", scenes]).options
+    }
+}
+
+/// Renders several jobs from a file, for example:
+/// `(defaults: (aa_count: Some(8)), jobs: [(scene: "basics", animations: ["intro"], options: (fps: Some(30))), (scene: "basics", stage: Some("start"))])`
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Args)]
+struct RenderJobsCliOptions {
+    /// RON file with `jobs` list and optional `defaults` for all jobs
+    file: String,
+
+    /// `json` prints one JSON object per line for every frame, encoder status and final summary
+    #[arg(long, value_enum, default_value_t = ProgressFormat::Text)]
+    progress: ProgressFormat,
+}
+
+/// Replaces values of `RenderCliOptions` for one job, or for all jobs in the file.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+struct RenderOverrides {
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: Option<usize>,
+    motion_blur_frames: Option<usize>,
+    stereo_image: Option<bool>,
+    vr: Option<VrFormat>,
+    stereo_layout: Option<StereoLayout>,
+    anaglyph: Option<AnaglyphMode>,
+    no_skip_existing: Option<bool>,
+    aa_count: Option<i32>,
+    render_depth: Option<i32>,
+    encoder: Option<EncoderPreset>,
+    ffmpeg_template: Option<String>,
+    shutter_angle: Option<f64>,
+    shutter_offset: Option<ShutterOffset>,
+    shutter_kernel: Option<ShutterKernel>,
+    frame_format: Option<FrameFormat>,
+    hdr_max: Option<f64>,
+    tile_size: Option<u32>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RenderOverrides {
+    fn apply(&self, options: &mut RenderCliOptions) {
+        fn set<T: Clone>(to: &mut T, from: &Option<T>) {
+            if let Some(from) = from {
+                *to = from.clone();
+            }
+        }
+        set(&mut options.width, &self.width);
+        set(&mut options.height, &self.height);
+        set(&mut options.fps, &self.fps);
+        set(&mut options.motion_blur_frames, &self.motion_blur_frames);
+        set(&mut options.stereo_image, &self.stereo_image);
+        set(&mut options.vr, &self.vr);
+        set(&mut options.stereo_layout, &self.stereo_layout);
+        if self.anaglyph.is_some() {
+            options.anaglyph = self.anaglyph;
+        }
+        set(&mut options.no_skip_existing, &self.no_skip_existing);
+        set(&mut options.aa_count, &self.aa_count);
+        set(&mut options.render_depth, &self.render_depth);
+        set(&mut options.encoder, &self.encoder);
+        set(&mut options.shutter.shutter_angle, &self.shutter_angle);
+        set(&mut options.shutter.shutter_offset, &self.shutter_offset);
+        set(&mut options.shutter.shutter_kernel, &self.shutter_kernel);
+        set(&mut options.frame_format, &self.frame_format);
+        set(&mut options.hdr_max, &self.hdr_max);
+        if self.ffmpeg_template.is_some() {
+            options.ffmpeg_template = self.ffmpeg_template.clone();
+        }
+        if self.tile_size.is_some() {
+            options.tile_size = self.tile_size;
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, serde::Deserialize)]
+struct RenderJob {
+    scene: String,
+
+    /// Empty means all animations of the scene
+    #[serde(default)]
+    animations: Vec<String>,
+
+    /// Renders still frame of this stage instead of animations
+    #[serde(default)]
+    stage: Option<String>,
+
+    /// Camera for the stage frame
+    #[serde(default)]
+    camera: Option<String>,
+
+    /// Directory inside `video/` for animations, or image path for the stage frame
+    #[serde(default)]
+    output: Option<String>,
+
+    /// Auxiliary images for the stage frame, e.g. `passes: [Depth, Normal]`
+    #[serde(default)]
+    passes: Vec<AuxPass>,
+
+    /// Largest distance stored in the depth pass of the stage frame
+    #[serde(default)]
+    depth_max: Option<f64>,
+
+    #[serde(default)]
+    options: RenderOverrides,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-async fn render(options: RenderCliOptions) -> Result<(), String> {
-    let width = if options.stereo_image {
+#[derive(Debug, Clone, serde::Deserialize)]
+struct RenderJobFile {
+    #[serde(default)]
+    defaults: RenderOverrides,
+
+    jobs: Vec<RenderJob>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn encoder_from_options(options: &RenderCliOptions) -> Result<Encoder, String> {
+    let encoder = Encoder {
+        preset: options.encoder,
+        ffmpeg_template: options.ffmpeg_template.clone(),
+    };
+    encoder.check()?;
+    Ok(encoder)
+}
+
+/// Render target is only as large as one tile, frames are stitched from tiles in `draw_linear`.
+#[cfg(not(target_arch = "wasm32"))]
+fn render_target_size(width: u32, height: u32, tile_size: Option<u32>) -> (u32, u32) {
+    match tile_size {
+        Some(tile_size) => (width.min(tile_size), height.min(tile_size)),
+        None => (width, height),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn create_renderer(
+    scene_name: &str,
+    options: &RenderCliOptions,
+    encoder: &Encoder,
+) -> Result<SceneRenderer, String> {
+    let (width, height) = if options.stereo_image {
         options
-            .width
-            .checked_mul(2)
-            .ok_or_else(|| "Render width overflow after stereo doubling".to_owned())?
+            .stereo_layout
+            .frame_size(options.width, options.height)
+            .ok_or_else(|| "Render size overflow after stereo doubling".to_owned())?
     } else {
-        options.width
+        (options.width, options.height)
     };
 
     let scenes = Scenes::default();
+    let scene_content = scenes
+        .get_by_link(scene_name)
+        .map(|(content, _)| content)
+        .ok_or_else(|| format!("Unknown scene `{scene_name}`"))?;
+    let scene: SerializedScene = ron::from_str(scene_content)
+        .map_err(|err| format!("Failed to parse scene `{scene_name}`: {err}"))?;
+    let (target_width, target_height) = render_target_size(width, height, options.tile_size);
+    let mut renderer = SceneRenderer::new(
+        Scene::from_serialized(scene),
+        target_width,
+        target_height,
+        scene_name,
+    )
+    .await;
+    renderer.width = width;
+    renderer.height = height;
+    renderer.aa_count = options.aa_count;
+    renderer.render_depth = options.render_depth;
+    renderer.draw_side_by_side = options.stereo_image;
+    renderer.stereo_layout = options.stereo_layout;
+    if let Some(mode) = options.anaglyph {
+        renderer.data.disable_anaglyph = false;
+        renderer.material = renderer
+            .scene
+            .get_new_material(&renderer.data)
+            .ok_or_else(|| format!("Failed to compile scene `{scene_name}` with anaglyph"))?
+            .map_err(|err| {
+                format!(
+                    "Failed to compile scene `{scene_name}` with anaglyph: {}",
+                    err.1
+                )
+            })?;
+        renderer.draw_anaglyph = true;
+        renderer.anaglyph_mode = mode;
+    }
+    renderer.vr_format = options.vr;
+    renderer.encoder = encoder.clone();
+    renderer.frame_selection = FrameSelection {
+        range: options.frames,
+        shard: options.shard,
+    };
+    renderer.scene_hash = scene_hash(scene_content);
+    renderer.shutter = options.shutter.shutter()?;
+    renderer.frame_format = options.frame_format;
+    renderer.hdr_max = options.hdr_max;
+    renderer.progress = Progress {
+        format: options.progress,
+    };
+    Ok(renderer)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn render(options: RenderCliOptions, summary: &mut RenderSummary) -> Result<(), String> {
+    let progress = Progress {
+        format: options.progress,
+    };
+    let encoder = encoder_from_options(&options)?;
+
     for scene_name in options
         .scenes
         .split(',')
         .map(str::trim)
         .filter(|x| !x.is_empty())
     {
-        println!("Rendering scene {scene_name}");
-
-        let scene_content = scenes
-            .get_by_link(scene_name)
-            .map(|(content, _)| content)
-            .ok_or_else(|| format!("Unknown scene `{scene_name}`"))?;
-        let scene: SerializedScene = ron::from_str(scene_content)
-            .map_err(|err| format!("Failed to parse scene `{scene_name}`: {err}"))?;
-        let mut renderer = SceneRenderer::new(
-            Scene::from_serialized(scene),
-            width,
-            options.height,
-            scene_name,
-        )
-        .await;
-        renderer.aa_count = options.aa_count;
-        renderer.render_depth = options.render_depth;
-        renderer.draw_side_by_side = options.stereo_image;
+        progress.message(&format!("Rendering scene {scene_name}"));
 
-        if let Some(animation_names) = &options.animations {
+        let mut renderer = create_renderer(scene_name, &options, &encoder).await?;
+
+        let outcomes = if let Some(animation_names) = &options.animations {
             let animation_names = animation_names
                 .split(',')
                 .map(str::trim)
                 .filter(|x| !x.is_empty())
                 .map(str::to_owned)
                 .collect::<Vec<_>>();
+            let output_dir = renderer.scene_name.clone();
             renderer.render_named_animations(
                 &animation_names,
+                &output_dir,
                 options.fps,
                 options.motion_blur_frames,
                 !options.no_skip_existing,
-            )?;
+            )?
         } else {
             let starts_with = if options.starts_with.is_empty() {
                 None
@@ -2865,13 +4003,211 @@ async fn render(options: RenderCliOptions) -> Result<(), String> {
                 options.motion_blur_frames,
                 !options.no_skip_existing,
                 starts_with,
-            );
+            )?
+        };
+        for outcome in outcomes {
+            summary.add(&Ok(outcome));
         }
     }
 
     Ok(())
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+struct JobSummaryRow {
+    job: usize,
+    scene: String,
+    target: String,
+    result: Result<RenderOutcome, String>,
+    time: std::time::Duration,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn print_job_summary(rows: &[JobSummaryRow]) {
+    let scene_width = rows
+        .iter()
+        .map(|r| r.scene.len())
+        .chain([5])
+        .max()
+        .unwrap_or(5);
+    let target_width = rows
+        .iter()
+        .map(|r| r.target.len())
+        .chain([6])
+        .max()
+        .unwrap_or(6);
+    println!();
+    println!(
+        "{:>4}  {:scene_width$}  {:target_width$}  {:8}  {:>10}",
+        "Job", "Scene", "Target", "Result", "Time"
+    );
+    for row in rows {
+        let result = match &row.result {
+            Ok(outcome) => outcome.name(),
+            Err(_) => "FAILED",
+        };
+        println!(
+            "{:>4}  {:scene_width$}  {:target_width$}  {:8}  {:>9.1}s",
+            row.job,
+            row.scene,
+            row.target,
+            result,
+            row.time.as_secs_f64()
+        );
+    }
+}
+
+/// Outcome and render time of every target of the job.
+#[cfg(not(target_arch = "wasm32"))]
+async fn render_job(
+    job: &RenderJob,
+    options: &RenderCliOptions,
+) -> Vec<(String, Result<RenderOutcome, String>, std::time::Duration)> {
+    let start = std::time::Instant::now();
+    if let Some(stage) = &job.stage {
+        let output = job.output.clone().unwrap_or_else(|| {
+            format!(
+                "video/{}/{stage}.{}",
+                job.scene,
+                options.frame_format.extension()
+            )
+        });
+        let target = format!("stage {stage}");
+        if !options.no_skip_existing && std::path::Path::new(&output).exists() {
+            return vec![(target, Ok(RenderOutcome::Skipped), start.elapsed())];
+        }
+        if options.stereo_image || options.vr != VrFormat::None || options.anaglyph.is_some() {
+            let err = format!(
+                "Stage `{stage}` can't be rendered in stereo, remove `stereo_image`, `vr` and `anaglyph` from options of the job"
+            );
+            return vec![(target, Err(err), start.elapsed())];
+        }
+        let result = render_frame(RenderFrameCliOptions {
+            scene: job.scene.clone(),
+            stage: Some(stage.clone()),
+            animation: None,
+            camera: job.camera.clone(),
+            time: 0.0,
+            output,
+            width: options.width,
+            height: options.height,
+            aa_count: options.aa_count,
+            render_depth: options.render_depth,
+            motion_blur_frames: options.motion_blur_frames,
+            fps: options.fps,
+            shutter: options.shutter.clone(),
+            frame_format: options.frame_format,
+            hdr_max: options.hdr_max,
+            tile_size: options.tile_size,
+            passes: job.passes.clone(),
+            depth_max: job.depth_max.unwrap_or(1000.0),
+            progress: options.progress,
+            projection: None,
+            view_angle: None,
+            panini_param: None,
+        })
+        .await;
+        return vec![(
+            target,
+            result.map(|()| RenderOutcome::Rendered),
+            start.elapsed(),
+        )];
+    }
+
+    let prepared = match encoder_from_options(options) {
+        Ok(encoder) => create_renderer(&job.scene, options, &encoder).await,
+        Err(err) => Err(err),
+    };
+    let mut renderer = match prepared {
+        Ok(renderer) => renderer,
+        Err(err) => return vec![("all animations".to_owned(), Err(err), start.elapsed())],
+    };
+
+    let animation_names = if job.animations.is_empty() {
+        renderer
+            .scene
+            .animations
+            .visible_elements()
+            .map(|(_, name)| name.to_owned())
+            .collect()
+    } else {
+        job.animations.clone()
+    };
+    let output_dir = job
+        .output
+        .clone()
+        .unwrap_or_else(|| renderer.scene_name.clone());
+
+    let mut result = Vec::new();
+    for name in animation_names {
+        let start = std::time::Instant::now();
+        let outcome = renderer
+            .render_named_animations(
+                std::slice::from_ref(&name),
+                &output_dir,
+                options.fps,
+                options.motion_blur_frames,
+                !options.no_skip_existing,
+            )
+            .map(|outcomes| outcomes[0]);
+        result.push((format!("animation {name}"), outcome, start.elapsed()));
+    }
+    result
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn render_jobs(
+    options: RenderJobsCliOptions,
+    summary: &mut RenderSummary,
+) -> Result<(), String> {
+    let progress = Progress {
+        format: options.progress,
+    };
+    let content = std::fs::read_to_string(&options.file)
+        .map_err(|err| format!("Failed to read `{}`: {err}", options.file))?;
+    let file: RenderJobFile = ron::from_str(&content)
+        .map_err(|err| format!("Failed to parse `{}`: {err}", options.file))?;
+
+    let mut rows = Vec::new();
+    for (i, job) in file.jobs.iter().enumerate() {
+        progress.message(&format!(
+            "Job {}/{}: scene {}",
+            i + 1,
+            file.jobs.len(),
+            job.scene
+        ));
+        let mut job_options = RenderCliOptions::with_defaults(&job.scene);
+        file.defaults.apply(&mut job_options);
+        job.options.apply(&mut job_options);
+        job_options.progress = options.progress;
+
+        for (target, result, time) in render_job(job, &job_options).await {
+            if let Err(err) = &result {
+                progress.report(ProgressEvent::Error { error: err });
+            }
+            summary.add(&result);
+            rows.push(JobSummaryRow {
+                job: i + 1,
+                scene: job.scene.clone(),
+                target,
+                result,
+                time,
+            });
+        }
+    }
+
+    if options.progress == ProgressFormat::Text {
+        print_job_summary(&rows);
+    }
+
+    let failed = rows.iter().filter(|r| r.result.is_err()).count();
+    if failed != 0 {
+        Err(format!("{failed} of {} renders failed", rows.len()))
+    } else {
+        Ok(())
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 async fn render_frame(options: RenderFrameCliOptions) -> Result<(), String> {
     let scenes = Scenes::default();
@@ -2881,15 +4217,37 @@ async fn render_frame(options: RenderFrameCliOptions) -> Result<(), String> {
         .ok_or_else(|| format!("Unknown scene `{}`", options.scene))?;
     let scene: SerializedScene = ron::from_str(scene_content)
         .map_err(|err| format!("Failed to parse scene `{}`: {err}", options.scene))?;
+    let (target_width, target_height) =
+        render_target_size(options.width, options.height, options.tile_size);
     let mut renderer = SceneRenderer::new(
         Scene::from_serialized(scene),
-        options.width,
-        options.height,
+        target_width,
+        target_height,
         &options.scene,
     )
     .await;
     renderer.aa_count = options.aa_count;
     renderer.render_depth = options.render_depth;
+    renderer.shutter = options.shutter.shutter()?;
+    renderer.frame_format = options.frame_format;
+    renderer.hdr_max = options.hdr_max;
+    renderer.depth_max = options.depth_max;
+    if let Some(view_angle) = options.view_angle {
+        let projection = options.projection.unwrap_or(renderer.cam.projection);
+        if let Some((min, max)) = projection.view_angle_range() {
+            if !(min..=max).contains(&view_angle) {
+                return Err(format!(
+                    "View angle for {} must be in {min}..={max} degrees",
+                    projection.name()
+                ));
+            }
+        }
+    }
+    renderer.projection_override = ProjectionOverride {
+        projection: options.projection,
+        view_angle: options.view_angle.map(deg2rad),
+        panini_param: options.panini_param,
+    };
 
     let mut memory = egui::Memory::default();
     memory.data.insert_persisted(
@@ -2925,23 +4283,38 @@ async fn render_frame(options: RenderFrameCliOptions) -> Result<(), String> {
             .insert_persisted(egui::Id::new("CurrentCam"), CurrentCam(Some(camera_id)));
     }
 
-    renderer.update(&mut memory, options.time);
-    renderer.draw_texture(options.width as f32, options.height as f32, true);
-
+    let images = renderer.render_sub_frames(
+        &mut memory,
+        options.time,
+        1.0 / options.fps.max(1) as f64,
+        options.motion_blur_frames,
+        options.width,
+        options.height,
+    );
     let output = std::path::Path::new(&options.output);
-    if let Some(parent) = output
-        .parent()
-        .filter(|parent| !parent.as_os_str().is_empty())
-    {
-        std::fs::create_dir_all(parent)
-            .map_err(|err| format!("Failed to create `{}`: {err}", parent.display()))?;
-    }
-    renderer
-        .render_target
-        .texture
-        .get_texture_data()
-        .export_png(&options.output);
-    println!("Rendered `{}` to `{}`", options.scene, options.output);
+    save_frame(output, &average_images(images), options.frame_format)?;
+    let progress = Progress {
+        format: options.progress,
+    };
+    progress.message(&format!(
+        "Rendered `{}` to `{}`",
+        options.scene, options.output
+    ));
+
+    if !options.passes.is_empty() {
+        // Passes are not motion blurred, they show the scene exactly at `time`
+        renderer.update(&mut memory, options.time);
+    }
+    for pass in &options.passes {
+        let path = output.with_extension(format!("{}.exr", pass.name()));
+        let image = renderer.draw_aux_pass(*pass, options.width as f32, options.height as f32);
+        save_exr(&path, &image)?;
+        progress.message(&format!(
+            "Rendered {} pass to `{}`",
+            pass.name(),
+            path.display()
+        ));
+    }
     Ok(())
 }
 
@@ -2953,18 +4326,52 @@ async fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     match Cli::parse().command {
         Some(CliCommand::Render(options)) => {
+            let progress = Progress {
+                format: options.progress,
+            };
             let render_start = std::time::Instant::now();
-            let result = render(options).await;
-            println!("Total render time: {:?}", render_start.elapsed());
-            if let Err(err) = result {
-                eprintln!("{err}");
+            let mut summary = RenderSummary::default();
+            let result = render(options, &mut summary).await;
+            if let Err(err) = &result {
+                // Render stops on the first error
+                summary.failed += 1;
+                progress.report(ProgressEvent::Error { error: err });
+            }
+            progress.report(ProgressEvent::Summary {
+                summary,
+                seconds: render_start.elapsed().as_secs_f64(),
+            });
+            if result.is_err() {
                 std::process::exit(1);
             }
             return;
         }
         Some(CliCommand::RenderFrame(options)) => {
+            let progress = Progress {
+                format: options.progress,
+            };
             if let Err(err) = render_frame(options).await {
-                eprintln!("{err}");
+                progress.report(ProgressEvent::Error { error: &err });
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(CliCommand::RenderJobs(options)) => {
+            let progress = Progress {
+                format: options.progress,
+            };
+            let render_start = std::time::Instant::now();
+            let mut summary = RenderSummary::default();
+            // Failed jobs are already reported and counted in the summary
+            let result = render_jobs(options, &mut summary).await;
+            if let Err(err) = &result {
+                progress.report(ProgressEvent::Error { error: err });
+            }
+            progress.report(ProgressEvent::Summary {
+                summary,
+                seconds: render_start.elapsed().as_secs_f64(),
+            });
+            if result.is_err() {
                 std::process::exit(1);
             }
             return;
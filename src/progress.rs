@@ -0,0 +1,249 @@
+use std::io::Write;
+
+/// How render progress is printed to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum ProgressFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+
+    /// One JSON object per line, for job schedulers
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderOutcome {
+    Rendered,
+    Skipped,
+    /// Only part of frames is rendered, because of `--frames` or `--shard`
+    Partial,
+}
+
+impl RenderOutcome {
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderOutcome::Rendered => "rendered",
+            RenderOutcome::Skipped => "skipped",
+            RenderOutcome::Partial => "partial",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderSummary {
+    pub rendered: usize,
+    pub skipped: usize,
+    pub partial: usize,
+    pub failed: usize,
+}
+
+impl RenderSummary {
+    pub fn add(&mut self, result: &Result<RenderOutcome, String>) {
+        match result {
+            Ok(RenderOutcome::Rendered) => self.rendered += 1,
+            Ok(RenderOutcome::Skipped) => self.skipped += 1,
+            Ok(RenderOutcome::Partial) => self.partial += 1,
+            Err(_) => self.failed += 1,
+        }
+    }
+}
+
+pub enum ProgressEvent<'a> {
+    Message {
+        text: &'a str,
+    },
+    FrameStarted {
+        output: &'a str,
+        frame: usize,
+        count: usize,
+    },
+    FrameDone {
+        output: &'a str,
+        frame: usize,
+        done: usize,
+        count: usize,
+        seconds: f64,
+    },
+    EncoderStarted {
+        output: &'a str,
+    },
+    EncoderDone {
+        output: &'a str,
+        path: &'a str,
+        seconds: f64,
+    },
+    EncoderFailed {
+        output: &'a str,
+        error: &'a str,
+    },
+    Finished {
+        output: &'a str,
+        outcome: RenderOutcome,
+        seconds: f64,
+    },
+    Error {
+        error: &'a str,
+    },
+    Summary {
+        summary: RenderSummary,
+        seconds: f64,
+    },
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+impl ProgressEvent<'_> {
+    pub fn to_json(&self) -> String {
+        use ProgressEvent::*;
+        let s = json_string;
+        match self {
+            Message { text } => format!(r#"{{"event":"message","text":{}}}"#, s(text)),
+            FrameStarted {
+                output,
+                frame,
+                count,
+            } => format!(
+                r#"{{"event":"frame_started","output":{},"frame":{frame},"count":{count}}}"#,
+                s(output)
+            ),
+            FrameDone {
+                output,
+                frame,
+                done,
+                count,
+                seconds,
+            } => format!(
+                r#"{{"event":"frame_done","output":{},"frame":{frame},"done":{done},"count":{count},"seconds":{seconds:.3}}}"#,
+                s(output)
+            ),
+            EncoderStarted { output } => {
+                format!(r#"{{"event":"encoder_started","output":{}}}"#, s(output))
+            }
+            EncoderDone {
+                output,
+                path,
+                seconds,
+            } => format!(
+                r#"{{"event":"encoder_done","output":{},"path":{},"seconds":{seconds:.3}}}"#,
+                s(output),
+                s(path)
+            ),
+            EncoderFailed { output, error } => format!(
+                r#"{{"event":"encoder_failed","output":{},"error":{}}}"#,
+                s(output),
+                s(error)
+            ),
+            Finished {
+                output,
+                outcome,
+                seconds,
+            } => format!(
+                r#"{{"event":"finished","output":{},"outcome":{},"seconds":{seconds:.3}}}"#,
+                s(output),
+                s(outcome.name())
+            ),
+            Error { error } => format!(r#"{{"event":"error","error":{}}}"#, s(error)),
+            Summary { summary, seconds } => format!(
+                r#"{{"event":"summary","rendered":{},"skipped":{},"partial":{},"failed":{},"seconds":{seconds:.3}}}"#,
+                summary.rendered, summary.skipped, summary.partial, summary.failed
+            ),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        use ProgressEvent::*;
+        match self {
+            Message { text } => text.to_string(),
+            FrameStarted { .. } => String::new(),
+            FrameDone { done, count, .. } => format!("\r{done}/{count} done      "),
+            EncoderStarted { .. } => "Start encoding video".to_owned(),
+            EncoderDone { path, seconds, .. } => format!("Encoded `{path}` in {seconds:.1}s"),
+            EncoderFailed { error, .. } => format!("Encoding failed: {error}"),
+            Finished {
+                output,
+                outcome,
+                seconds,
+            } => format!("Finished `{output}` ({}) in {seconds:.1}s", outcome.name()),
+            Error { error } => error.to_string(),
+            Summary { summary, seconds } => format!(
+                "Rendered: {}, skipped: {}, partial: {}, failed: {}. Total render time: {seconds:.1}s",
+                summary.rendered, summary.skipped, summary.partial, summary.failed
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub format: ProgressFormat,
+}
+
+impl Progress {
+    pub fn report(&self, event: ProgressEvent) {
+        let mut stdout = std::io::stdout().lock();
+        // Nothing to do if stdout is closed, render should continue
+        drop(match self.format {
+            ProgressFormat::Json => writeln!(stdout, "{}", event.to_json()),
+            ProgressFormat::Text => match event {
+                ProgressEvent::FrameStarted { .. } => Ok(()),
+                ProgressEvent::FrameDone { done, count, .. } if done != count => {
+                    write!(stdout, "{}", event.to_text())
+                }
+                ProgressEvent::Error { .. } | ProgressEvent::EncoderFailed { .. } => {
+                    writeln!(std::io::stderr(), "{}", event.to_text())
+                }
+                _ => writeln!(stdout, "{}", event.to_text()),
+            },
+        });
+        drop(stdout.flush());
+    }
+
+    pub fn message(&self, text: &str) {
+        self.report(ProgressEvent::Message { text });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_escaping() {
+        assert_eq!(
+            ProgressEvent::EncoderFailed {
+                output: "scene/a \"b\"",
+                error: "line\nother\\",
+            }
+            .to_json(),
+            r#"{"event":"encoder_failed","output":"scene/a \"b\"","error":"line\nother\\"}"#
+        );
+        assert_eq!(
+            ProgressEvent::FrameDone {
+                output: "x",
+                frame: 3,
+                done: 1,
+                count: 10,
+                seconds: 0.5,
+            }
+            .to_json(),
+            r#"{"event":"frame_done","output":"x","frame":3,"done":1,"count":10,"seconds":0.500}"#
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::gui::combo_box::ComboBoxChoosable;
+use serde::{Deserialize, Serialize};
+
+/// How pixels of the image are turned into camera rays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum Projection {
+    /// Pinhole camera
+    #[default]
+    Perspective,
+
+    /// Perspective that keeps vertical lines straight and stretches less at wide angles
+    Panini,
+
+    /// Equirectangular 360°, center of the image looks forward. Frame is 2:1
+    #[cfg_attr(not(target_arch = "wasm32"), value(name = "360"))]
+    Equirectangular360,
+
+    /// Equirectangular front hemisphere (VR180)
+    #[cfg_attr(not(target_arch = "wasm32"), value(name = "180"))]
+    Equirectangular180,
+
+    /// Fisheye where distance from the center is proportional to the angle
+    FisheyeEquidistant,
+
+    /// Fisheye where area of the image is proportional to the solid angle
+    FisheyeEquisolid,
+
+    /// Six 90° faces in a row: +X, -X, +Y, -Y, +Z, -Z of camera. Frame is 6:1
+    Cubemap,
+
+    /// Parallel rays, visible height equals the view angle at the distance of look_at
+    Orthographic,
+}
+
+impl Projection {
+    pub const ALL: [Projection; 8] = [
+        Projection::Perspective,
+        Projection::Panini,
+        Projection::Equirectangular360,
+        Projection::Equirectangular180,
+        Projection::FisheyeEquidistant,
+        Projection::FisheyeEquisolid,
+        Projection::Cubemap,
+        Projection::Orthographic,
+    ];
+
+    /// Names for `ComboBoxChoosable`, in order of `ALL`.
+    const NAMES: [&'static str; 8] = {
+        let mut result = [""; 8];
+        let mut i = 0;
+        while i < result.len() {
+            result[i] = Projection::ALL[i].name();
+            i += 1;
+        }
+        result
+    };
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Projection::Perspective => "Perspective",
+            Projection::Panini => "Panini",
+            Projection::Equirectangular360 => "360 camera",
+            Projection::Equirectangular180 => "180 camera (VR180)",
+            Projection::FisheyeEquidistant => "Fisheye equidistant",
+            Projection::FisheyeEquisolid => "Fisheye equisolid",
+            Projection::Cubemap => "Cubemap",
+            Projection::Orthographic => "Orthographic",
+        }
+    }
+
+    /// Value of `_projection` in shader.
+    pub fn shader_index(self) -> i32 {
+        match self {
+            Projection::Perspective => 0,
+            Projection::Panini => 1,
+            Projection::Equirectangular360 => 2,
+            Projection::Equirectangular180 => 3,
+            Projection::FisheyeEquidistant => 4,
+            Projection::FisheyeEquisolid => 5,
+            Projection::Cubemap => 6,
+            Projection::Orthographic => 7,
+        }
+    }
+
+    /// Range of view angle in degrees, `None` if the projection always covers the same angle.
+    pub fn view_angle_range(self) -> Option<(f64, f64)> {
+        match self {
+            Projection::Perspective | Projection::Orthographic => Some((2., 140.)),
+            Projection::Panini => Some((2., 250.)),
+            Projection::FisheyeEquidistant | Projection::FisheyeEquisolid => Some((2., 360.)),
+            Projection::Equirectangular360
+            | Projection::Equirectangular180
+            | Projection::Cubemap => None,
+        }
+    }
+}
+
+impl ComboBoxChoosable for Projection {
+    fn variants() -> &'static [&'static str] {
+        &Self::NAMES
+    }
+
+    fn get_number(&self) -> usize {
+        self.shader_index() as usize
+    }
+
+    fn set_number(&mut self, number: usize) {
+        *self = Self::ALL[number];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combo_box_round_trip() {
+        for (i, projection) in Projection::ALL.into_iter().enumerate() {
+            assert_eq!(projection.get_number(), i);
+            assert_eq!(Projection::variants()[i], projection.name());
+
+            let mut chosen = Projection::default();
+            chosen.set_number(projection.get_number());
+            assert_eq!(chosen, projection);
+        }
+        assert_eq!(Projection::variants().len(), Projection::ALL.len());
+    }
+}
@@ -0,0 +1,520 @@
+use crate::image_export::FrameFormat;
+use crate::shutter::Shutter;
+use crate::stereo::{AnaglyphMode, StereoLayout, VrFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Hash of the scene file, must be stable between runs, so `DefaultHasher` is not used. FNV-1a.
+pub fn scene_hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Everything that changes pixels of rendered frames. Frames rendered with other settings are
+/// stale.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub scene_hash: u64,
+    pub width: u32,
+    pub height: u32,
+    pub fps: usize,
+    pub motion_blur_frames: usize,
+    pub aa_count: i32,
+    pub render_depth: i32,
+    pub side_by_side: bool,
+    pub frame_count: usize,
+    #[serde(default)]
+    pub shutter: Shutter,
+    #[serde(default)]
+    pub frame_format: FrameFormat,
+    #[serde(default)]
+    pub hdr_max: f32,
+    #[serde(default)]
+    pub vr_format: VrFormat,
+    #[serde(default)]
+    pub stereo_layout: StereoLayout,
+    #[serde(default)]
+    pub anaglyph: Option<AnaglyphMode>,
+}
+
+/// Frames rendered by one process, stored near the frames to resume after crash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderManifest {
+    pub settings: RenderSettings,
+    pub completed: BTreeSet<usize>,
+    /// Manifest without owner is from older version and considered live.
+    #[serde(default)]
+    pub owner: Option<ProcessOwner>,
+}
+
+/// Frames `start..end`, end is exclusive and can be omitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("Frame range `{s}` should look like `start..end`"))?;
+        let parse = |x: &str| {
+            x.trim()
+                .parse::<usize>()
+                .map_err(|err| format!("Wrong frame number `{x}` in `{s}`: {err}"))
+        };
+        let start = if start.trim().is_empty() {
+            0
+        } else {
+            parse(start)?
+        };
+        let end = if end.trim().is_empty() {
+            None
+        } else {
+            Some(parse(end)?)
+        };
+        if end.map(|end| end <= start).unwrap_or(false) {
+            return Err(format!("Frame range `{s}` is empty"));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Every `count`-th frame, starting from `index - 1`. Frames are interleaved, because complexity of
+/// animation usually changes over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize, // 1..=count
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Shard `{s}` should look like `k/n`"))?;
+        let parse = |x: &str| {
+            x.trim()
+                .parse::<usize>()
+                .map_err(|err| format!("Wrong number `{x}` in shard `{s}`: {err}"))
+        };
+        let (index, count) = (parse(index)?, parse(count)?);
+        if index == 0 || index > count {
+            return Err(format!("Shard `{s}` should have 1 <= k <= n"));
+        }
+        Ok(Self { index, count })
+    }
+}
+
+/// Which frames of animation are rendered by this process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameSelection {
+    pub range: Option<FrameRange>,
+    pub shard: Option<Shard>,
+}
+
+impl FrameSelection {
+    pub fn is_full(&self) -> bool {
+        self.range.is_none() && self.shard.is_none()
+    }
+
+    pub fn frames(&self, frame_count: usize) -> impl Iterator<Item = usize> {
+        let start = self.range.map(|r| r.start).unwrap_or(0);
+        let end = self
+            .range
+            .and_then(|r| r.end)
+            .unwrap_or(frame_count)
+            .min(frame_count);
+        let shard = self.shard;
+        (start..end).filter(move |i| {
+            shard
+                .map(|shard| (i - start) % shard.count == shard.index - 1)
+                .unwrap_or(true)
+        })
+    }
+
+    /// Processes with different selections write different manifests, so they don't race.
+    fn manifest_name(&self) -> String {
+        let mut result = "manifest".to_owned();
+        if let Some(range) = self.range {
+            result += &format!(
+                "-frames-{}-{}",
+                range.start,
+                range.end.unwrap_or(usize::MAX)
+            );
+        }
+        if let Some(shard) = self.shard {
+            result += &format!("-shard-{}-of-{}", shard.index, shard.count);
+        }
+        result + ".ron"
+    }
+}
+
+/// Directory `anim/{output_name}`, own for every animation, so several renders don't mix frames.
+pub struct WorkDir {
+    pub path: PathBuf,
+    pub frame_extension: &'static str,
+}
+
+impl WorkDir {
+    pub fn new(output_name: &str, frame_extension: &'static str) -> Self {
+        Self {
+            path: Path::new("anim").join(output_name),
+            frame_extension,
+        }
+    }
+
+    pub fn frames_dir(&self) -> PathBuf {
+        self.path.join("frames")
+    }
+
+    pub fn frame_path(&self, i: usize) -> PathBuf {
+        self.frames_dir()
+            .join(format!("frame_{i}.{}", self.frame_extension))
+    }
+
+    fn manifests(&self) -> Result<Vec<(PathBuf, RenderManifest)>, String> {
+        let mut result = Vec::new();
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(result),
+            Err(err) => return Err(format!("Failed to read `{}`: {err}", self.path.display())),
+        };
+        for entry in entries {
+            let path = entry
+                .map_err(|err| format!("Failed to read `{}`: {err}", self.path.display()))?
+                .path();
+            let is_manifest = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with("manifest") && name.ends_with(".ron"))
+                .unwrap_or(false);
+            if is_manifest {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|err| format!("Failed to read `{}`: {err}", path.display()))?;
+                // Manifest can be broken by crash while writing, then its frames are rendered again
+                if let Ok(manifest) = ron::from_str(&content) {
+                    result.push((path, manifest));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Frames that are completed by all processes with the same settings. Frames and manifests
+    /// of other settings are stale and removed, but only when their processes are finished,
+    /// otherwise render is refused.
+    pub fn completed_frames(&self, settings: &RenderSettings) -> Result<BTreeSet<usize>, String> {
+        let (same, other): (Vec<_>, Vec<_>) = self
+            .manifests()?
+            .into_iter()
+            .partition(|(_, m)| m.settings == *settings);
+
+        let host = current_host();
+        let live = other
+            .iter()
+            .filter(|(_, m)| {
+                m.owner
+                    .as_ref()
+                    .map(|owner| owner.is_live(&host, std::process::id(), process_alive))
+                    .unwrap_or(true)
+            })
+            .map(|(path, _)| format!("`{}`", path.display()))
+            .collect::<Vec<_>>();
+        if !live.is_empty() {
+            return Err(format!(
+                "`{}` is rendered with other settings or scene by another process: {}. Wait for it, use another output name, or remove the directory if that process doesn't run anymore",
+                self.path.display(),
+                live.join(", ")
+            ));
+        }
+
+        if !other.is_empty() {
+            // Stderr, so machine-readable progress in stdout is not broken
+            eprintln!(
+                "Settings or scene changed, removing stale frames in `{}`",
+                self.path.display()
+            );
+        }
+        let mut stale = BTreeSet::new();
+        for (path, manifest) in other {
+            for i in manifest.completed {
+                let frame = self.frame_path(i);
+                match std::fs::remove_file(&frame) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(format!("Failed to remove `{}`: {err}", frame.display()))
+                    }
+                    _ => {}
+                }
+                stale.insert(i);
+            }
+            std::fs::remove_file(&path)
+                .map_err(|err| format!("Failed to remove `{}`: {err}", path.display()))?;
+        }
+
+        Ok(same
+            .into_iter()
+            .flat_map(|(_, m)| m.completed)
+            .filter(|i| !stale.contains(i) && self.frame_path(*i).exists())
+            .collect())
+    }
+
+    pub fn save_manifest(
+        &self,
+        selection: &FrameSelection,
+        manifest: &RenderManifest,
+    ) -> Result<(), String> {
+        let path = self.path.join(selection.manifest_name());
+        let temp = path.with_extension("ron.temp");
+        let content = ron::to_string(manifest).map_err(|err| err.to_string())?;
+        std::fs::write(&temp, content)
+            .map_err(|err| format!("Failed to write `{}`: {err}", temp.display()))?;
+        std::fs::rename(&temp, &path)
+            .map_err(|err| format!("Failed to write `{}`: {err}", path.display()))
+    }
+
+    /// Near the directory instead of inside it, so the directory can be removed while the lock is
+    /// held.
+    fn lock_path(&self) -> PathBuf {
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        self.path.with_file_name(format!("{name}.encoding.lock"))
+    }
+
+    /// Only one of processes that finished last shards should encode the video. Lock is held by
+    /// OS until returned value is dropped, so it is released even if process crashes or is killed.
+    pub fn try_lock_encoding(&self) -> Result<Option<EncodingLock>, String> {
+        let path = self.lock_path();
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|err| format!("Failed to open `{}`: {err}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(EncodingLock { file, path })),
+            Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+            Err(std::fs::TryLockError::Error(err)) => {
+                Err(format!("Failed to lock `{}`: {err}", path.display()))
+            }
+        }
+    }
+
+    /// Removes the directory with frames, while holding the lock, so other process that takes
+    /// the lock after it sees that video is already encoded.
+    pub fn remove(&self, lock: EncodingLock) -> Result<(), String> {
+        std::fs::remove_dir_all(&self.path)
+            .map_err(|err| format!("Failed to remove `{}`: {err}", self.path.display()))?;
+        // Can fail on Windows, because file is open, empty lock file is harmless
+        drop(std::fs::remove_file(&lock.path));
+        Ok(())
+    }
+}
+
+/// Held lock of `anim/{output_name}.encoding.lock`, released on drop.
+pub struct EncodingLock {
+    #[allow(dead_code)]
+    file: std::fs::File,
+    path: PathBuf,
+}
+
+/// Process that renders frames of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessOwner {
+    pub pid: u32,
+    pub host: String,
+}
+
+impl ProcessOwner {
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            host: current_host(),
+        }
+    }
+
+    /// Liveness of process is known only on the same host, shards can be rendered by several
+    /// machines in a shared directory. Renders in one process don't run at the same time.
+    fn is_live(&self, host: &str, own_pid: u32, alive: impl Fn(u32) -> bool) -> bool {
+        self.host != host || (self.pid != own_pid && alive(self.pid))
+    }
+}
+
+fn current_host() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|host| host.trim().to_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Unknown, so process is considered alive.
+#[cfg(not(target_os = "linux"))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_selection() {
+        assert_eq!(
+            "10..20".parse(),
+            Ok(FrameRange {
+                start: 10,
+                end: Some(20)
+            })
+        );
+        assert_eq!(
+            "10..".parse(),
+            Ok(FrameRange {
+                start: 10,
+                end: None
+            })
+        );
+        assert!("20..10".parse::<FrameRange>().is_err());
+        assert_eq!("2/4".parse(), Ok(Shard { index: 2, count: 4 }));
+        assert!("0/4".parse::<Shard>().is_err());
+        assert!("5/4".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn shards_cover_all_frames() {
+        let range = Some("3..20".parse().unwrap());
+        let mut all = (1..=3)
+            .flat_map(|index| {
+                FrameSelection {
+                    range,
+                    shard: Some(Shard { index, count: 3 }),
+                }
+                .frames(15)
+                .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(all, (3..15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn live_owner() {
+        let owner = ProcessOwner {
+            pid: 4242,
+            host: "render-box".to_owned(),
+        };
+        assert!(owner.is_live("render-box", 1, |_| true));
+        assert!(!owner.is_live("render-box", 1, |_| false));
+        // Earlier render of the same process
+        assert!(!owner.is_live("render-box", 4242, |_| true));
+        // Process on another machine can't be checked
+        assert!(owner.is_live("other", 1, |_| false));
+    }
+
+    fn test_dir(name: &str) -> WorkDir {
+        let path = std::env::temp_dir()
+            .join(format!("portal-test-{}", std::process::id()))
+            .join(name);
+        drop(std::fs::remove_dir_all(&path));
+        std::fs::create_dir_all(path.join("frames")).unwrap();
+        WorkDir {
+            path,
+            frame_extension: "png",
+        }
+    }
+
+    fn settings(width: u32) -> RenderSettings {
+        RenderSettings {
+            scene_hash: 1,
+            width,
+            height: 100,
+            fps: 30,
+            motion_blur_frames: 1,
+            aa_count: 1,
+            render_depth: 100,
+            side_by_side: false,
+            frame_count: 4,
+            shutter: Default::default(),
+            frame_format: Default::default(),
+            hdr_max: 1.,
+            vr_format: Default::default(),
+            stereo_layout: Default::default(),
+            anaglyph: None,
+        }
+    }
+
+    #[test]
+    fn frames_of_other_settings() {
+        let work_dir = test_dir("settings");
+        let save = |settings: RenderSettings, owner: ProcessOwner, shard, frames: &[usize]| {
+            for i in frames {
+                std::fs::write(work_dir.frame_path(*i), "").unwrap();
+            }
+            let selection = FrameSelection {
+                range: None,
+                shard: Some(Shard {
+                    index: shard,
+                    count: 2,
+                }),
+            };
+            let manifest = RenderManifest {
+                settings,
+                completed: frames.iter().copied().collect(),
+                owner: Some(owner),
+            };
+            work_dir.save_manifest(&selection, &manifest).unwrap();
+        };
+
+        // Shard of this process with current settings and shard of finished earlier render
+        save(settings(100), ProcessOwner::current(), 1, &[0, 2]);
+        save(settings(200), ProcessOwner::current(), 2, &[1, 3]);
+        assert_eq!(work_dir.completed_frames(&settings(100)), Ok([0, 2].into()));
+        assert!(!work_dir.frame_path(1).exists());
+        assert_eq!(work_dir.manifests().unwrap().len(), 1);
+
+        // Other settings are rendered on another machine, nothing is removed
+        let other = ProcessOwner {
+            pid: 1,
+            host: "other".to_owned(),
+        };
+        save(settings(200), other, 2, &[1, 3]);
+        assert!(work_dir.completed_frames(&settings(100)).is_err());
+        assert!(work_dir.frame_path(0).exists());
+        assert!(work_dir.frame_path(1).exists());
+        assert_eq!(work_dir.manifests().unwrap().len(), 2);
+
+        std::fs::remove_dir_all(&work_dir.path).unwrap();
+    }
+
+    #[test]
+    fn encoding_lock() {
+        let work_dir = test_dir("lock");
+        let lock = work_dir.try_lock_encoding().unwrap();
+        assert!(lock.is_some());
+        assert!(work_dir.try_lock_encoding().unwrap().is_none());
+
+        // Lock is released by OS when process ends, the same as when it is dropped
+        drop(lock);
+        let lock = work_dir.try_lock_encoding().unwrap().unwrap();
+        work_dir.remove(lock).unwrap();
+        assert!(!work_dir.path.exists());
+        assert!(!work_dir.lock_path().exists());
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+/// Where the shutter interval is placed relative to the frame time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum ShutterOffset {
+    /// Shutter opens at the frame time, motion trails are behind the objects
+    #[default]
+    Leading,
+
+    /// Frame time is in the middle of the interval
+    Centered,
+
+    /// Shutter closes at the frame time
+    Trailing,
+}
+
+/// How much every sub-frame contributes to the final frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum ShutterKernel {
+    /// All sub-frames are equal
+    #[default]
+    Box,
+
+    /// Linear falloff to the edges of the interval
+    Triangle,
+
+    /// Gaussian with sigma equal to 1/4 of the interval, gives the softest trails
+    Gaussian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Shutter {
+    pub angle: f64, // degrees, 360 means that shutter is open during the whole frame
+    pub offset: ShutterOffset,
+    pub kernel: ShutterKernel,
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Self {
+            angle: 180.,
+            offset: ShutterOffset::Leading,
+            kernel: ShutterKernel::Box,
+        }
+    }
+}
+
+impl Shutter {
+    pub fn check(&self) -> Result<(), String> {
+        if !(0.0..=360.0).contains(&self.angle) {
+            return Err(format!(
+                "Shutter angle should be from 0 to 360, got {}",
+                self.angle
+            ));
+        }
+        Ok(())
+    }
+
+    fn weight(&self, x: f64) -> f64 {
+        // x is from -0.5 to 0.5 inside the shutter interval
+        match self.kernel {
+            ShutterKernel::Box => 1.0,
+            ShutterKernel::Triangle => 1.0 - 2.0 * x.abs(),
+            ShutterKernel::Gaussian => (-x * x / (2.0 * 0.25 * 0.25)).exp(),
+        }
+    }
+
+    /// Time offsets of sub-frames in frames, and their weights with sum of 1. The interval is
+    /// split into equal parts and every sub-frame is in the middle of its part. Only `Leading`
+    /// samples at the starts of parts, as it was before the shutter settings, so the default
+    /// shutter gives the same times `j / n * 0.5`. Weights are always taken in the middles of
+    /// parts, so the triangle kernel never gets zero weight.
+    pub fn samples(&self, sub_frames: usize) -> Vec<(f64, f64)> {
+        if sub_frames <= 1 {
+            return vec![(0.0, 1.0)];
+        }
+
+        let exposure = self.angle / 360.0;
+        let (start, shift) = match self.offset {
+            ShutterOffset::Leading => (0.0, 0.0),
+            ShutterOffset::Centered => (-exposure / 2.0, 0.5),
+            ShutterOffset::Trailing => (-exposure, 0.5),
+        };
+        let n = sub_frames as f64;
+        let weights = (0..sub_frames)
+            .map(|j| self.weight((j as f64 + 0.5) / n - 0.5))
+            .collect::<Vec<_>>();
+        let weight_sum = weights.iter().sum::<f64>();
+        weights
+            .into_iter()
+            .enumerate()
+            .map(|(j, w)| (start + (j as f64 + shift) / n * exposure, w / weight_sum))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples() {
+        // Same times as the fixed half-frame exposure before the shutter settings
+        let shutter = Shutter::default();
+        assert_eq!(shutter.samples(1), [(0.0, 1.0)]);
+        assert_eq!(
+            shutter.samples(4),
+            [(0.0, 0.25), (0.125, 0.25), (0.25, 0.25), (0.375, 0.25)]
+        );
+
+        let shutter = Shutter {
+            offset: ShutterOffset::Centered,
+            angle: 360.,
+            ..Default::default()
+        };
+        assert_eq!(shutter.samples(2), [(-0.25, 0.5), (0.25, 0.5)]);
+
+        let shutter = Shutter {
+            angle: 180.,
+            offset: ShutterOffset::Trailing,
+            kernel: ShutterKernel::Triangle,
+        };
+        let samples = shutter.samples(4);
+        let times = samples.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+        assert_eq!(times, [-0.4375, -0.3125, -0.1875, -0.0625]);
+        let weights = samples.iter().map(|(_, w)| *w).collect::<Vec<_>>();
+        assert_eq!(weights, [0.125, 0.375, 0.375, 0.125]);
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+/// Both eyes in one equirectangular frame for headset video. Every column has its own pair of eye
+/// positions on the circle around the camera (omnidirectional stereo), so depth is correct in
+/// every direction, not only forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum VrFormat {
+    /// Ordinary image
+    #[default]
+    None,
+
+    /// 360° for both eyes, left eye on top, right eye at the bottom. Square frame is used usually
+    #[cfg_attr(not(target_arch = "wasm32"), value(name = "ods360"))]
+    Ods360TopBottom,
+
+    /// 180° for both eyes, left eye on the left. Frame is 2:1
+    #[cfg_attr(not(target_arch = "wasm32"), value(name = "vr180"))]
+    Vr180SideBySide,
+}
+
+impl VrFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            VrFormat::None => "None",
+            VrFormat::Ods360TopBottom => "ODS 360 top-bottom",
+            VrFormat::Vr180SideBySide => "VR180 side-by-side",
+        }
+    }
+
+    /// Value of `_vr_format` in shader.
+    pub fn shader_index(self) -> i32 {
+        match self {
+            VrFormat::None => 0,
+            VrFormat::Ods360TopBottom => 1,
+            VrFormat::Vr180SideBySide => 2,
+        }
+    }
+}
+
+/// How images of two eyes are placed in one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum StereoLayout {
+    /// Left eye on the left, frame is twice wider
+    #[default]
+    SideBySide,
+
+    /// Left eye on top, frame is twice higher
+    TopBottom,
+
+    /// Eyes take even and odd rows, for passive 3D monitors
+    RowInterlaced,
+
+    /// Eyes take even and odd columns
+    ColumnInterlaced,
+}
+
+impl StereoLayout {
+    pub const ALL: [StereoLayout; 4] = [
+        StereoLayout::SideBySide,
+        StereoLayout::TopBottom,
+        StereoLayout::RowInterlaced,
+        StereoLayout::ColumnInterlaced,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StereoLayout::SideBySide => "Side-by-side",
+            StereoLayout::TopBottom => "Top-bottom",
+            StereoLayout::RowInterlaced => "Row interlaced",
+            StereoLayout::ColumnInterlaced => "Column interlaced",
+        }
+    }
+
+    /// Value of `_stereo_layout` in shader.
+    pub fn shader_index(self) -> i32 {
+        match self {
+            StereoLayout::SideBySide => 0,
+            StereoLayout::TopBottom => 1,
+            StereoLayout::RowInterlaced => 2,
+            StereoLayout::ColumnInterlaced => 3,
+        }
+    }
+
+    /// Size of the whole frame when every eye is `width`×`height`. Interlaced eyes share pixels
+    /// of one frame.
+    pub fn frame_size(self, width: u32, height: u32) -> Option<(u32, u32)> {
+        match self {
+            StereoLayout::SideBySide => Some((width.checked_mul(2)?, height)),
+            StereoLayout::TopBottom => Some((width, height.checked_mul(2)?)),
+            StereoLayout::RowInterlaced | StereoLayout::ColumnInterlaced => Some((width, height)),
+        }
+    }
+}
+
+/// How two eyes are mixed into one image for colored glasses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum AnaglyphMode {
+    /// Red-cyan, both eyes in grayscale, ghosting is compensated by P and Q
+    #[default]
+    Grayscale,
+
+    /// Red-cyan, left eye in grayscale and right eye in color, ghosting is compensated by P and Q
+    Colorful,
+
+    /// Least squares matrices by Eric Dubois for red-cyan glasses
+    DuboisRedCyan,
+
+    /// Least squares matrices by Eric Dubois for green-magenta glasses
+    DuboisGreenMagenta,
+
+    /// Least squares matrices by Eric Dubois for amber-blue glasses
+    DuboisAmberBlue,
+}
+
+impl AnaglyphMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            AnaglyphMode::Grayscale => "Grayscale red-cyan",
+            AnaglyphMode::Colorful => "Colorful red-cyan",
+            AnaglyphMode::DuboisRedCyan => "Dubois red-cyan",
+            AnaglyphMode::DuboisGreenMagenta => "Dubois green-magenta",
+            AnaglyphMode::DuboisAmberBlue => "Dubois amber-blue",
+        }
+    }
+
+    /// Value of `_anaglyph_mode` in shader.
+    pub fn shader_index(self) -> i32 {
+        match self {
+            AnaglyphMode::Grayscale => 0,
+            AnaglyphMode::Colorful => 1,
+            AnaglyphMode::DuboisRedCyan => 2,
+            AnaglyphMode::DuboisGreenMagenta => 3,
+            AnaglyphMode::DuboisAmberBlue => 4,
+        }
+    }
+
+    /// P and Q are used only by ghosting compensation.
+    pub fn uses_ghosting_compensation(self) -> bool {
+        matches!(self, AnaglyphMode::Grayscale | AnaglyphMode::Colorful)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Indexes in the comment of uniform, e.g. `// 0 - side-by-side, 1 - top-bottom`.
+    fn shader_names(uniform: &str) -> Vec<(i32, String)> {
+        let shader = include_str!("frag.glsl");
+        let line = shader
+            .lines()
+            .find(|line| line.starts_with(&format!("uniform int {uniform};")))
+            .unwrap();
+        line.split_once("//")
+            .unwrap()
+            .1
+            .split(',')
+            .map(|item| {
+                let (index, name) = item.split_once(" - ").unwrap();
+                (index.trim().parse().unwrap(), name.trim().to_lowercase())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shader_index() {
+        let layouts = StereoLayout::ALL
+            .iter()
+            .map(|layout| (layout.shader_index(), layout.name().to_lowercase()))
+            .collect::<Vec<_>>();
+        assert_eq!(layouts, shader_names("_stereo_layout"));
+
+        let formats = [
+            VrFormat::None,
+            VrFormat::Ods360TopBottom,
+            VrFormat::Vr180SideBySide,
+        ]
+        .iter()
+        .map(|format| (format.shader_index(), format.name().to_lowercase()))
+        .collect::<Vec<_>>();
+        assert_eq!(formats, shader_names("_vr_format"));
+    }
+
+    #[test]
+    fn frame_size() {
+        assert_eq!(
+            StereoLayout::SideBySide.frame_size(640, 480),
+            Some((1280, 480))
+        );
+        assert_eq!(
+            StereoLayout::TopBottom.frame_size(640, 480),
+            Some((640, 960))
+        );
+        assert_eq!(
+            StereoLayout::RowInterlaced.frame_size(640, 480),
+            Some((640, 480))
+        );
+        assert_eq!(
+            StereoLayout::ColumnInterlaced.frame_size(640, 480),
+            Some((640, 480))
+        );
+        assert_eq!(StereoLayout::SideBySide.frame_size(u32::MAX, 1), None);
+        assert_eq!(StereoLayout::TopBottom.frame_size(1, u32::MAX), None);
+    }
+}
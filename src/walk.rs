@@ -0,0 +1,131 @@
+use glam::{DMat4, DQuat, DVec3, DVec4};
+
+/// Vertical movement of the camera in walk mode during one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FallStep {
+    pub dy: f64, // in camera coordinates, along up of the world
+    pub vertical_speed: f64,
+    pub on_ground: bool,
+}
+
+/// Applies gravity for `dt` seconds and lands on the floor at `eye_height` below the camera.
+/// `probe(max)` returns distance to the floor under the camera, if it is nearer than `max`.
+pub fn fall_step(
+    eye_height: f64,
+    gravity: f64,
+    vertical_speed: f64,
+    dt: f64,
+    probe: impl FnOnce(f64) -> Option<f64>,
+) -> FallStep {
+    let speed = vertical_speed - gravity * dt;
+    let dy = speed * dt;
+    match probe(eye_height * 2. + (-dy).max(0.)) {
+        Some(distance) if distance - eye_height + dy <= 0. => FallStep {
+            dy: eye_height - distance,
+            vertical_speed: 0.,
+            on_ground: true,
+        },
+        _ => FallStep {
+            dy,
+            vertical_speed: speed,
+            on_ground: false,
+        },
+    }
+}
+
+/// Rotates `matrix` around `pos` by at most `max_angle`, so up of the camera returns to up of the
+/// world after a portal that rotates space.
+pub fn align_up(matrix: DMat4, pos: DVec3, max_angle: f64) -> DMat4 {
+    let up = (matrix * DVec4::new(0., 1., 0., 0.)).truncate().normalize();
+    let angle = up.dot(DVec3::Y).clamp(-1., 1.).acos();
+    if angle < 1e-6 {
+        return matrix;
+    }
+    let axis = up.cross(DVec3::Y);
+    let axis = if axis.length() > 1e-9 {
+        axis.normalize()
+    } else {
+        // Upside down, any horizontal axis works
+        (matrix * DVec4::new(1., 0., 0., 0.)).truncate().normalize()
+    };
+    DMat4::from_translation(pos)
+        * DMat4::from_quat(DQuat::from_axis_angle(axis, angle.min(max_angle)))
+        * DMat4::from_translation(-pos)
+        * matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn falling_and_landing() {
+        // Nothing below, speed grows
+        let step = fall_step(1., 4., 0., 0.5, |_| None);
+        assert_eq!(
+            step,
+            FallStep {
+                dy: -1.,
+                vertical_speed: -2.,
+                on_ground: false,
+            }
+        );
+
+        // Floor is below the eye height after this step, camera stops on it
+        let mut max = 0.;
+        let step = fall_step(1., 4., -5., 0.5, |m| {
+            max = m;
+            Some(1.25)
+        });
+        assert_eq!(max, 5.5);
+        assert_eq!(
+            step,
+            FallStep {
+                dy: -0.25,
+                vertical_speed: 0.,
+                on_ground: true,
+            }
+        );
+
+        // Floor is still far
+        let step = fall_step(1., 4., -5., 0.5, |_| Some(5.));
+        assert_eq!(
+            step,
+            FallStep {
+                dy: -3.5,
+                vertical_speed: -7.,
+                on_ground: false,
+            }
+        );
+
+        // Jump goes up even while standing on the floor
+        let step = fall_step(1., 4., 3., 0.5, |_| Some(1.));
+        assert_eq!(
+            step,
+            FallStep {
+                dy: 0.5,
+                vertical_speed: 1.,
+                on_ground: false,
+            }
+        );
+    }
+
+    #[test]
+    fn up_alignment() {
+        let pos = DVec3::new(1., 2., 3.);
+        let tilted = DMat4::from_translation(pos)
+            * DMat4::from_rotation_z(PI / 2.)
+            * DMat4::from_translation(-pos);
+        let up = |matrix: DMat4| (matrix * DVec4::new(0., 1., 0., 0.)).truncate();
+
+        let aligned = align_up(tilted, pos, PI);
+        assert!((up(aligned) - DVec3::Y).length() < 1e-9);
+        assert!((aligned.transform_point3(pos) - pos).length() < 1e-9);
+
+        let half = align_up(tilted, pos, PI / 4.);
+        assert!((up(half).angle_between(DVec3::Y) - PI / 4.).abs() < 1e-9);
+
+        assert_eq!(align_up(DMat4::IDENTITY, pos, PI), DMat4::IDENTITY);
+    }
+}